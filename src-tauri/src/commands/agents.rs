@@ -1,6 +1,8 @@
 use crate::models::{Agent, AgentHealth, HealthStatus, Capability};
 use crate::state::AppState;
 use crate::utils::AppResult;
+use crate::services::agent_probe::AgentProber;
+use crate::services::agent_host::agent_host;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use chrono::Utc;
@@ -43,6 +45,7 @@ pub fn agents_register(
             error_rate: 0.0,
             success_count: 0,
             failure_count: 0,
+            breaker: Default::default(),
         };
     }
     
@@ -63,6 +66,7 @@ pub fn agents_register(
     if let Err(e) = state.storage.save_json("agents.json", &agents) {
         log::error!("Failed to save agents: {}", e);
     }
+    agent_host().sync(&agents);
     
     Ok(json!({
         "ok": true,
@@ -94,6 +98,7 @@ pub fn agents_enable(
         if let Err(e) = state.storage.save_json("agents.json", &agents.clone()) {
             log::error!("Failed to save agents: {}", e);
         }
+        agent_host().sync(&agents);
         
         Ok(json!({ "ok": true }))
     } else {
@@ -116,6 +121,7 @@ pub fn agents_delete(
         if let Err(e) = state.storage.save_json("agents.json", &agents.clone()) {
             log::error!("Failed to save agents: {}", e);
         }
+        agent_host().sync(&agents);
         
         Ok(json!({ "ok": true }))
     } else {
@@ -124,38 +130,55 @@ pub fn agents_delete(
 }
 
 #[tauri::command]
-pub fn agents_test(
-    state: tauri::State<AppState>,
+pub async fn agents_test(
+    state: tauri::State<'_, AppState>,
     name: String,
+    model: Option<String>,
 ) -> Result<serde_json::Value, String> {
-    let mut agents = state.agents.write();
+    let agent = state.agents.read().iter().find(|a| a.name == name).cloned()
+        .ok_or_else(|| format!("Agent '{}' not found", name))?;
     
-    if let Some(agent) = agents.iter_mut().find(|a| a.name == name) {
-        // Simulate health check
-        let latency_ms = if agent.local { 0 } else { 50 + (rand::random::<u32>() % 100) };
-        
-        agent.health = AgentHealth {
-            status: HealthStatus::Healthy,
-            last_check: Utc::now(),
-            latency_ms: Some(latency_ms),
-            error_rate: 0.0,
-            success_count: agent.health.success_count + 1,
-            failure_count: agent.health.failure_count,
-        };
+    // Real round-trip appropriate to the agent type
+    let model = model.or_else(|| agent.model.clone());
+    let report = AgentProber::new().probe(&agent, model).await;
+    
+    {
+        let mut agents = state.agents.write();
+        if let Some(agent) = agents.iter_mut().find(|a| a.name == name) {
+            let now = Utc::now();
+            let latency_ms = report.latency_ms.unwrap_or(0);
+            if report.ok {
+                agent.health.success_count += 1;
+            } else {
+                agent.health.failure_count += 1;
+            }
+            // The test call is traffic like any other; after a cooldown it is the probe
+            agent.health.breaker.on_dispatch(now);
+            agent.health.breaker.record(report.ok, latency_ms, now);
+            agent.health.error_rate = agent.health.breaker.failure_rate();
+            agent.health.latency_ms = report.latency_ms;
+            agent.health.last_check = now;
+            agent.health.status = if report.ok {
+                agent.health.breaker.health_status()
+            } else {
+                HealthStatus::Unhealthy
+            };
+        }
         
         // Persist changes
         if let Err(e) = state.storage.save_json("agents.json", &agents.clone()) {
             log::error!("Failed to save agents: {}", e);
         }
-        
-        Ok(json!({
-            "ok": true,
-            "latency_ms": latency_ms,
-            "health": "healthy"
-        }))
-    } else {
-        Err(format!("Agent '{}' not found", name))
     }
+    
+    Ok(json!({
+        "ok": report.ok,
+        "latency_ms": report.latency_ms,
+        "health": if report.ok { "healthy" } else { "unhealthy" },
+        "report": report,
+        // Capabilities and limits a connected WebSocket agent advertised
+        "handshake": agent_host().handshake(&name)
+    }))
 }
 
 #[derive(Deserialize)]
@@ -182,10 +205,14 @@ pub fn agents_register_free_defaults(state: tauri::State<AppState>) -> Result<se
             error_rate: 0.0,
             success_count: 0,
             failure_count: 0,
+            breaker: Default::default(),
         },
         local: true,
         max_concurrent_tasks: 2,
         token_limit: Some(4000),
+        process: None,
+        provider: None,
+        model: None,
     };
     let free_code = crate::models::Agent {
         name: "FreeCodeAgent".to_string(),
@@ -201,10 +228,14 @@ pub fn agents_register_free_defaults(state: tauri::State<AppState>) -> Result<se
             error_rate: 0.0,
             success_count: 0,
             failure_count: 0,
+            breaker: Default::default(),
         },
         local: true,
         max_concurrent_tasks: 2,
         token_limit: Some(8000),
+        process: None,
+        provider: None,
+        model: None,
     };
     // De-duplicate by name
     if !agents.iter().any(|a| a.name == free_text.name) { agents.push(free_text); }
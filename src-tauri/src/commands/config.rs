@@ -1,7 +1,11 @@
 use serde_json::json;
 use tauri::State;
 use crate::state::AppState;
-use crate::models::AppConfig;
+use crate::models::{AppConfig, ModelInfo, ProviderConfig, RateLimitConfig, ResponseCacheConfig};
+use crate::services::rate_limiter::provider_limiter;
+use crate::services::providers::provider_registry;
+use crate::services::response_cache::response_cache;
+use std::collections::HashMap;
 
 #[tauri::command]
 pub fn config_update(
@@ -19,6 +23,38 @@ pub fn config_update(
     if let Some(backup_enabled) = partial_config.get("backup_enabled").and_then(|v| v.as_bool()) { cfg.backup_enabled = backup_enabled; }
     if let Some(backup_interval_hours) = partial_config.get("backup_interval_hours").and_then(|v| v.as_u64()) { cfg.backup_interval_hours = backup_interval_hours as u32; }
     if let Some(ignore_limits) = partial_config.get("ignore_task_token_limits").and_then(|v| v.as_bool()) { cfg.ignore_task_token_limits = ignore_limits; }
+    if let Some(limits) = partial_config.get("rate_limits") {
+        let limits: HashMap<String, RateLimitConfig> = serde_json::from_value(limits.clone())
+            .map_err(|e| format!("Invalid rate_limits: {}", e))?;
+        cfg.rate_limits = limits;
+        provider_limiter().configure(cfg.rate_limits.clone());
+    }
+    if let Some(providers) = partial_config.get("providers") {
+        let providers: HashMap<String, ProviderConfig> = serde_json::from_value(providers.clone())
+            .map_err(|e| format!("Invalid providers: {}", e))?;
+        cfg.providers = providers;
+        provider_registry().configure(cfg.providers.clone());
+    }
+    if let Some(default_provider) = partial_config.get("default_provider").and_then(|v| v.as_str()) {
+        cfg.default_provider = default_provider.to_string();
+        provider_registry().set_default_provider(&cfg.default_provider);
+    }
+    if let Some(models) = partial_config.get("models") {
+        let models: HashMap<String, ModelInfo> = serde_json::from_value(models.clone())
+            .map_err(|e| format!("Invalid models: {}", e))?;
+        cfg.models = models;
+        provider_registry().register_models(cfg.models.clone());
+    }
+    if let Some(cache) = partial_config.get("response_cache") {
+        let cache: ResponseCacheConfig = serde_json::from_value(cache.clone())
+            .map_err(|e| format!("Invalid response_cache: {}", e))?;
+        cfg.response_cache = cache;
+        response_cache().configure(cfg.response_cache.clone(), state.storage.get_base_path().join("response_cache"));
+    }
+    if let Some(schemas) = partial_config.get("output_schemas") {
+        cfg.output_schemas = serde_json::from_value(schemas.clone())
+            .map_err(|e| format!("Invalid output_schemas: {}", e))?;
+    }
     // Persist
     if let Err(e) = state.storage.save_json("config.json", &*cfg) {
         log::error!("Failed to save config: {}", e);
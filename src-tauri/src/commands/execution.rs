@@ -4,14 +4,41 @@ use once_cell::sync::Lazy;
 use tokio::sync::RwLock;
 use crate::state::AppState;
 use crate::services::task_runner::TaskRunner;
+use crate::services::rate_limiter::provider_limiter;
+use crate::services::providers::provider_registry;
+use crate::services::response_cache::response_cache;
+use crate::services::cassette::{cassettes, CassetteMode};
+use crate::services::agent_host::agent_host;
 
 // Global task runner instance
 static TASK_RUNNER: Lazy<Arc<RwLock<Option<Arc<TaskRunner>>>>> = Lazy::new(|| {
     Arc::new(RwLock::new(None))
 });
 
-pub async fn init_task_runner(state: Arc<AppState>) {
-    let runner = Arc::new(TaskRunner::new(state));
+pub async fn init_task_runner(state: Arc<AppState>, app_handle: tauri::AppHandle) {
+    provider_limiter().configure(state.config.read().rate_limits.clone());
+    provider_registry().configure(state.config.read().providers.clone());
+    provider_registry().set_default_provider(&state.config.read().default_provider);
+    provider_registry().register_models(state.config.read().models.clone());
+    response_cache().configure(
+        state.config.read().response_cache.clone(),
+        state.storage.get_base_path().join("response_cache"),
+    );
+    cassettes().configure(Arc::clone(&state.storage));
+    agent_host().sync(&state.agents.read());
+    agent_host().supervise(Arc::clone(&state));
+    
+    // Free cached context now and then; evicted entries reload from disk on use
+    let context_pool = Arc::clone(&state.context_pool);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(300));
+        loop {
+            interval.tick().await;
+            context_pool.cleanup_expired();
+        }
+    });
+    
+    let runner = Arc::new(TaskRunner::new(state).with_app_handle(app_handle));
     
     // Set default API keys from environment variables
     if let Ok(openai_key) = std::env::var("OPENAI_API_KEY") {
@@ -100,4 +127,33 @@ pub async fn test_api_connection(provider: String) -> Result<Value, String> {
         Ok(_) => Ok(json!({"ok": true, "message": format!("{} connection successful", provider)})),
         Err(e) => Ok(json!({"ok": false, "error": e.to_string()}))
     }
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub fn response_cache_clear() -> Result<Value, String> {
+    Ok(json!({"ok": true, "removed": response_cache().clear()}))
+}
+
+/// Set a project's cassette to `off`, `record` (starts a fresh cassette) or `replay`.
+#[tauri::command]
+pub fn cassette_set_mode(project_id: String, mode: String) -> Result<Value, String> {
+    let mode: CassetteMode = serde_json::from_value(json!(mode))
+        .map_err(|_| format!("Unknown cassette mode '{}'", mode))?;
+    let loaded = cassettes().set_mode(&project_id, mode).map_err(|e| e.to_string())?;
+    Ok(json!({"ok": true, "mode": mode, "loaded": loaded}))
+}
+
+#[tauri::command]
+pub fn cassette_status(project_id: String) -> Result<Value, String> {
+    let cassettes = cassettes();
+    Ok(json!({
+        "ok": true,
+        "mode": cassettes.mode(Some(&project_id)),
+        "recorded": cassettes.recorded_count(&project_id)
+    }))
+}
+
+#[tauri::command]
+pub fn rate_limits_status() -> Result<Value, String> {
+    Ok(json!({"ok": true, "limits": provider_limiter().snapshot()}))
+}
@@ -6,6 +6,8 @@ pub mod queue;
 pub mod templates;
 pub mod execution;
 pub mod tools;
+pub mod usage;
+pub mod ollama;
 
 pub use agents::*;
 pub use projects::*;
@@ -0,0 +1,133 @@
+use serde_json::json;
+use tauri::{Manager, State};
+use crate::state::AppState;
+use crate::services::ollama::{agent_name, OllamaClient, OllamaModel};
+use crate::services::providers::provider_registry;
+
+fn client(state: &AppState, provider: &str) -> Result<OllamaClient, String> {
+    let config = state.config.read();
+    let provider_config = config.providers.get(provider)
+        .ok_or_else(|| format!("Unknown provider: {}", provider))?;
+    OllamaClient::from_config(provider, provider_config).map_err(|e| e.to_string())
+}
+
+fn save(state: &AppState) {
+    if let Err(e) = state.storage.save_json("agents.json", &*state.agents.read()) {
+        log::error!("Failed to save agents: {}", e);
+    }
+    let config = state.config.read();
+    provider_registry().register_models(config.models.clone());
+    if let Err(e) = state.storage.save_json("config.json", &*config) {
+        log::error!("Failed to save config: {}", e);
+    }
+}
+
+/// Add or refresh the agent and model registry entry for each model. Existing agents
+/// keep their enabled flag, priority and health.
+fn register(state: &AppState, provider: &str, base_url: &str, models: &[OllamaModel]) {
+    {
+        let mut agents = state.agents.write();
+        for model in models.iter().filter(|m| !m.capabilities.is_empty()) {
+            let agent = model.agent(provider, base_url);
+            match agents.iter_mut().find(|a| a.name == agent.name) {
+                Some(existing) => {
+                    existing.capabilities = agent.capabilities;
+                    existing.endpoint_url = agent.endpoint_url;
+                    existing.token_limit = agent.token_limit;
+                    existing.provider = agent.provider;
+                    existing.model = agent.model;
+                }
+                None => agents.push(agent),
+            }
+        }
+    }
+    let mut config = state.config.write();
+    for model in models {
+        config.models.insert(model.name.clone(), model.model_info(provider));
+    }
+}
+
+fn unregister(state: &AppState, provider: &str, model: &str) {
+    let name = agent_name(model);
+    state.agents.write().retain(|a| !(a.name == name && a.provider.as_deref() == Some(provider)));
+    let mut config = state.config.write();
+    if config.models.get(model).and_then(|m| m.provider.as_deref()) == Some(provider) {
+        config.models.remove(model);
+    }
+}
+
+/// List installed models and register each as an agent; agents for models that were
+/// removed outside the app are dropped.
+#[tauri::command]
+pub async fn ollama_list_models(
+    state: State<'_, AppState>,
+    provider: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let provider = provider.unwrap_or_else(|| "ollama".to_string());
+    let client = client(&state, &provider)?;
+    let models = client.list_models().await
+        .map_err(|e| format!("Failed to list Ollama models: {}", e))?;
+
+    let stale: Vec<String> = state.agents.read()
+        .iter()
+        .filter(|a| a.provider.as_deref() == Some(provider.as_str()) && a.name.starts_with("ollama:"))
+        .filter_map(|a| a.model.clone())
+        .filter(|m| !models.iter().any(|installed| &installed.name == m))
+        .collect();
+    for model in &stale {
+        unregister(&state, &provider, model);
+    }
+    register(&state, &provider, client.base_url(), &models);
+    save(&state);
+
+    Ok(json!({
+        "ok": true,
+        "provider": provider,
+        "models": models,
+        "removed": stale
+    }))
+}
+
+/// Download a model, emitting `ollama-pull-progress` events, then register it.
+#[tauri::command]
+pub async fn ollama_pull_model(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    model: String,
+    provider: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let provider = provider.unwrap_or_else(|| "ollama".to_string());
+    let client = client(&state, &provider)?;
+
+    client.pull(&model, |progress| {
+        let _ = app.emit_all("ollama-pull-progress", &progress);
+    })
+    .await
+    .map_err(|e| {
+        let _ = app.emit_all("ollama-pull-progress", json!({"model": model, "status": "error", "error": e.to_string(), "done": true}));
+        e.to_string()
+    })?;
+
+    let installed = client.show(&model).await
+        .map_err(|e| format!("Pulled {} but could not read its details: {}", model, e))?;
+    register(&state, &provider, client.base_url(), std::slice::from_ref(&installed));
+    save(&state);
+
+    Ok(json!({ "ok": true, "model": installed }))
+}
+
+#[tauri::command]
+pub async fn ollama_delete_model(
+    state: State<'_, AppState>,
+    model: String,
+    provider: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let provider = provider.unwrap_or_else(|| "ollama".to_string());
+    let client = client(&state, &provider)?;
+    client.delete(&model).await.map_err(|e| e.to_string())?;
+
+    unregister(&state, &provider, &model);
+    save(&state);
+
+    Ok(json!({ "ok": true, "model": model }))
+}
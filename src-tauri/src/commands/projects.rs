@@ -142,11 +142,18 @@ pub fn projects_delete(
     // Remove from memory
     state.projects.write().remove(&project_id);
     state.tasks.write().remove(&project_id);
+    {
+        let mut foreground = state.foreground_project.write();
+        if foreground.as_deref() == Some(project_id.as_str()) {
+            *foreground = None;
+        }
+    }
     
     // Remove from storage
     if let Err(e) = state.storage.delete(&format!("project_{}.json", project_id)) {
         log::error!("Failed to delete project file: {}", e);
     }
+    state.context_pool.clear_project_context(&project_id);
     
     Ok(json!({ "ok": true }))
 }
@@ -254,15 +261,30 @@ pub async fn shredder_analyze(
     let exec = SimpleExecutor::new();
     let task = TaskExecution {
         task_id: format!("analyze-{}", project_id),
+        project_id: Some(project_id.clone()),
         preamble: instruction,
         input: serde_json::json!({ "prompt": project.prompt }),
         capability: "text".to_string(),
+        task_type: None,
+        agent: None,
         tool: None,
         api_key: provider.as_ref().and_then(|p| {
             let key_name = format!("{}_API_KEY", p.to_uppercase());
             std::env::var(key_name).ok()
         }),
         model,
+        provider,
+        context_window: None,
+        max_output_tokens: None,
+        token_limit: None,
+        output_schema: Some(shredder_output_schema()),
+        temperature: None,
+        cache: None,
+        tools: None,
+        max_tool_steps: None,
+        chunking: None,
+        ensemble: None,
+        acceptance: None,
         max_retries: None,
         timeout_secs: None,
         full_context: None,
@@ -272,39 +294,64 @@ pub async fn shredder_analyze(
     };
 
     let result = exec.execute_task(task).await.map_err(|e| e.to_string())?;
-    if let Some(output) = result.output {
-        if let Some(content) = output.get("content").and_then(|c| c.as_str()) {
-            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(content) {
-                let _ = state.storage.save_project_data(&project_id, "atoms.json", &parsed["atoms"]);
-                let _ = state.storage.save_project_data(&project_id, "atomic_task_types.json", &parsed["atomic_task_types"]);
-                let _ = state.storage.save_project_data(&project_id, "clarification_questions.json", &parsed["questions"]);
-                // Also persist into project struct for hot load/unload
-                {
-                    let mut projects = state.projects.write();
-                    if let Some(p) = projects.get_mut(&project_id) {
-                        p.elaboration = output.get("elaboration").and_then(|v| v.as_str()).map(|s| s.to_string());
-                        p.shredder_atoms = parsed.get("atoms").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
-                        p.shredder_atomic_task_types = parsed.get("atomic_task_types").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
-                        p.shredder_questions = parsed.get("questions").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
-                        p.shredder_raw = Some(parsed.clone());
-                        p.updated_at = Utc::now();
-                        let _ = state.storage.save_json(&format!("project_{}.json", project_id), &*p);
-                    }
+    if let (true, Some(output)) = (result.success, result.output) {
+        // Validated against the contract, so fenced or prose-wrapped JSON is already unwrapped
+        if let Some(parsed) = output.get("json").cloned() {
+            let _ = state.storage.save_project_data(&project_id, "atoms.json", &parsed["atoms"]);
+            let _ = state.storage.save_project_data(&project_id, "atomic_task_types.json", &parsed["atomic_task_types"]);
+            let _ = state.storage.save_project_data(&project_id, "clarification_questions.json", &parsed["questions"]);
+            // Also persist into project struct for hot load/unload
+            {
+                let mut projects = state.projects.write();
+                if let Some(p) = projects.get_mut(&project_id) {
+                    p.elaboration = output.get("elaboration").and_then(|v| v.as_str()).map(|s| s.to_string());
+                    p.shredder_atoms = parsed.get("atoms").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
+                    p.shredder_atomic_task_types = parsed.get("atomic_task_types").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
+                    p.shredder_questions = parsed.get("questions").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect()).unwrap_or_default();
+                    p.shredder_raw = Some(parsed.clone());
+                    p.updated_at = Utc::now();
+                    let _ = state.storage.save_json(&format!("project_{}.json", project_id), &*p);
                 }
-                return Ok(serde_json::json!({
-                    "ok": true,
-                    "atoms": parsed.get("atoms").cloned().unwrap_or(serde_json::json!([])),
-                    "atomic_task_types": parsed.get("atomic_task_types").cloned().unwrap_or(serde_json::json!([])),
-                    "questions": parsed.get("questions").cloned().unwrap_or(serde_json::json!([])),
-                    "tasks": parsed.get("tasks").cloned().unwrap_or(serde_json::json!([])),
-                }));
             }
+            return Ok(serde_json::json!({
+                "ok": true,
+                "atoms": parsed.get("atoms").cloned().unwrap_or(serde_json::json!([])),
+                "atomic_task_types": parsed.get("atomic_task_types").cloned().unwrap_or(serde_json::json!([])),
+                "questions": parsed.get("questions").cloned().unwrap_or(serde_json::json!([])),
+                "tasks": parsed.get("tasks").cloned().unwrap_or(serde_json::json!([])),
+            }));
         }
         return Ok(serde_json::json!({ "ok": true, "raw": output }));
     }
     Err(result.error.unwrap_or_else(|| "Analysis failed".to_string()))
 }
 
+fn shredder_output_schema() -> serde_json::Value {
+    let strings = serde_json::json!({"type": "array", "items": {"type": "string"}});
+    serde_json::json!({
+        "type": "object",
+        "required": ["atoms", "atomic_task_types", "questions", "tasks"],
+        "properties": {
+            "atoms": strings,
+            "atomic_task_types": strings,
+            "questions": strings,
+            "tasks": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["task_type", "preamble", "capability"],
+                    "properties": {
+                        "task_type": {"type": "string"},
+                        "preamble": {"type": "string"},
+                        "capability": {"enum": ["text", "code", "image", "sound", "video"]},
+                        "dependencies": strings
+                    }
+                }
+            }
+        }
+    })
+}
+
 #[tauri::command]
 pub fn shredder_apply(
     state: tauri::State<AppState>,
@@ -356,6 +403,10 @@ pub fn shredder_apply(
             oneshot_count: 0,
             last_agent: None,
             last_agent_key_hint: None,
+            partial_output: None,
+            token_usage: None,
+            cost_usd: None,
+            evaluation: None,
         };
         new_tasks.push(task);
     }
@@ -424,6 +475,10 @@ fn generate_tasks_for_project(
                 oneshot_count: 0,
                 last_agent: None,
                 last_agent_key_hint: None,
+                partial_output: None,
+                token_usage: None,
+                cost_usd: None,
+                evaluation: None,
             });
             
             tasks.push(Task {
@@ -451,6 +506,10 @@ fn generate_tasks_for_project(
                 oneshot_count: 0,
                 last_agent: None,
                 last_agent_key_hint: None,
+                partial_output: None,
+                token_usage: None,
+                cost_usd: None,
+                evaluation: None,
             });
         },
         ProjectType::DataAnalysis => {
@@ -480,6 +539,10 @@ fn generate_tasks_for_project(
                 oneshot_count: 0,
                 last_agent: None,
                 last_agent_key_hint: None,
+                partial_output: None,
+                token_usage: None,
+                cost_usd: None,
+                evaluation: None,
             });
         },
         _ => {
@@ -509,6 +572,10 @@ fn generate_tasks_for_project(
                 oneshot_count: 0,
                 last_agent: None,
                 last_agent_key_hint: None,
+                partial_output: None,
+                token_usage: None,
+                cost_usd: None,
+                evaluation: None,
             });
         }
     }
@@ -91,6 +91,8 @@ pub fn queue_cancel(state: State<AppState>, project_id: String) -> Result<serde_
         p.updated_at = Utc::now();
         let _ = state.storage.save_json(&format!("project_{}.json", p.id), &*p);
     }
+    // Free cached context; outputs of finished tasks stay on disk
+    state.context_pool.evict_project_context(&project_id);
     Ok(json!({"ok": true}))
 }
 
@@ -100,6 +102,18 @@ pub fn queue_reorder(_project_id: String, _position: u32) -> Result<serde_json::
     Ok(json!({"ok": true, "queue": []}))
 }
 
+#[tauri::command]
+pub fn queue_set_foreground(state: State<AppState>, project_id: Option<String>) -> Result<serde_json::Value, String> {
+    // Fair-share dispatch boosts the weight of whichever project the user is watching
+    if let Some(id) = project_id.as_ref() {
+        if !state.projects.read().contains_key(id) {
+            return Err(format!("Project '{}' not found", id));
+        }
+    }
+    *state.foreground_project.write() = project_id.clone();
+    Ok(json!({"ok": true, "foreground_project": project_id}))
+}
+
 #[tauri::command]
 pub fn queue_load_saved_projects(
     state: State<AppState>,
@@ -99,6 +99,10 @@ pub fn tasks_create_simple(
         oneshot_count: 0,
         last_agent: None,
         last_agent_key_hint: None,
+        partial_output: None,
+        token_usage: None,
+        cost_usd: None,
+        evaluation: None,
     };
 
     // Store in state
@@ -202,36 +202,20 @@ pub fn tools_register_manual(tool: ManualToolInput) -> Result<serde_json::Value,
     let mut tools_vec = arr;
 
     // Upsert
-    let mut found = false;
-    for t in tools_vec.iter_mut() {
-        if t["id"].as_str() == Some(&tool.id) {
-            *t = json!({
-                "id": tool.id,
-                "name": tool.name,
-                "category": tool.category,
-                "capabilities": tool.capabilities.unwrap_or_default(),
-                "input_formats": tool.input_formats.unwrap_or_default(),
-                "output_formats": tool.output_formats.unwrap_or_default(),
-                "is_available": false,
-                "requires_gpu": tool.requires_gpu.unwrap_or(false),
-                "requires_network": tool.requires_network.unwrap_or(false)
-            });
-            found = true;
-            break;
-        }
-    }
-    if !found {
-        tools_vec.push(json!({
-            "id": tool.id,
-            "name": tool.name,
-            "category": tool.category,
-            "capabilities": tool.capabilities.unwrap_or_default(),
-            "input_formats": tool.input_formats.unwrap_or_default(),
-            "output_formats": tool.output_formats.unwrap_or_default(),
-            "is_available": false,
-            "requires_gpu": tool.requires_gpu.unwrap_or(false),
-            "requires_network": tool.requires_network.unwrap_or(false)
-        }));
+    let entry = json!({
+        "id": tool.id,
+        "name": tool.name,
+        "category": tool.category,
+        "capabilities": tool.capabilities.unwrap_or_default(),
+        "input_formats": tool.input_formats.unwrap_or_default(),
+        "output_formats": tool.output_formats.unwrap_or_default(),
+        "is_available": false,
+        "requires_gpu": tool.requires_gpu.unwrap_or(false),
+        "requires_network": tool.requires_network.unwrap_or(false)
+    });
+    match tools_vec.iter_mut().find(|t| t["id"] == entry["id"]) {
+        Some(existing) => *existing = entry,
+        None => tools_vec.push(entry),
     }
 
     root["tools"] = json!(tools_vec);
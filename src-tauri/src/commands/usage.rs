@@ -0,0 +1,38 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde_json::json;
+use tauri::State;
+use crate::state::AppState;
+use crate::services::usage::{build_report, load_usage};
+
+// Accepts RFC 3339 timestamps or plain dates; a plain `to` date includes that whole day
+fn parse_bound(value: &str, end_of_range: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD or RFC 3339", value))?;
+    let start = date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    Ok(if end_of_range { start + Duration::days(1) } else { start })
+}
+
+#[tauri::command]
+pub fn usage_report(
+    state: State<AppState>,
+    from: Option<String>,
+    to: Option<String>,
+    project_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let from = from.as_deref().map(|v| parse_bound(v, false)).transpose()?;
+    let to = to.as_deref().map(|v| parse_bound(v, true)).transpose()?;
+
+    let records = load_usage(&state.storage, project_id.as_deref(), from, to)
+        .map_err(|e| format!("Failed to load usage: {}", e))?;
+    let report = build_report(&records);
+
+    Ok(json!({
+        "ok": true,
+        "from": from,
+        "to": to,
+        "report": report,
+    }))
+}
@@ -4,6 +4,7 @@ use serde::{Serialize, Deserialize};
 use serde_json::json;
 use std::sync::Arc;
 use std::path::PathBuf;
+use tauri::Manager;
 
 mod state;
 mod models;
@@ -32,10 +33,33 @@ fn notifications_test() -> Result<serde_json::Value, String> {
 
 // Queue - moved to commands/queue.rs
 
-// Clarify - placeholder for now
+// Clarify - answers go to the context pool, where the project's tasks read them
 #[tauri::command]
-fn clarify_submit(_project_id: String, _answers: Vec<String>) -> Result<OkResponse, String> {
-    // TODO: Implement clarification handling when needed
+fn clarify_submit(state: tauri::State<AppState>, project_id: String, answers: Vec<String>) -> Result<OkResponse, String> {
+    let now = chrono::Utc::now();
+    let entry = services::context_pool::ContextEntry {
+        id: format!("ctx-{}", uuid::Uuid::new_v4()),
+        project_id: project_id.clone(),
+        task_id: String::new(),
+        content_type: services::context_pool::ContextType::Document,
+        content: json!({
+            "type": "clarification",
+            "answers": answers,
+        }),
+        metadata: Default::default(),
+        created_at: now,
+        updated_at: now,
+        references: vec![],
+        ttl_seconds: None,
+    };
+    state.context_pool.add_context(entry).map_err(|e| e.to_string())?;
+    
+    let mut projects = state.projects.write();
+    if let Some(project) = projects.get_mut(&project_id) {
+        if project.status == models::ProjectStatus::WaitingClarification {
+            project.status = models::ProjectStatus::Queued;
+        }
+    }
     Ok(OkResponse { ok: true })
 }
 
@@ -63,13 +87,13 @@ fn main() {
     
     tauri::Builder::default()
         .manage(AppState::default())
-        .setup(|_app| {
-            // Initialize the simple task runner with a new AppState instance
-            // This is OK since the task runner will use its own state instance
-            let runner_state = Arc::new(AppState::default());
+        .setup(|app| {
+            // The runner shares the managed state, so config edits and task updates reach both
+            let runner_state = Arc::new(app.state::<AppState>().inner().clone());
+            let app_handle = app.handle();
             
             tauri::async_runtime::block_on(async {
-                commands::execution::init_task_runner(runner_state).await;
+                commands::execution::init_task_runner(runner_state, app_handle).await;
             });
             
             Ok(())
@@ -97,6 +121,7 @@ fn main() {
             commands::queue::queue_resume, 
             commands::queue::queue_cancel, 
             commands::queue::queue_reorder,
+            commands::queue::queue_set_foreground,
             commands::queue::queue_load_saved_projects,
             commands::queue::queue_process_lazy,
             commands::queue::queue_get_status,
@@ -119,6 +144,14 @@ fn main() {
             commands::execution::cancel_task,
             commands::execution::set_api_key,
             commands::execution::test_api_connection,
+            commands::execution::rate_limits_status,
+            commands::usage::usage_report,
+            commands::execution::response_cache_clear,
+            commands::execution::cassette_set_mode,
+            commands::execution::cassette_status,
+            commands::ollama::ollama_list_models,
+            commands::ollama::ollama_pull_model,
+            commands::ollama::ollama_delete_model,
             commands::tools::tools_list,
             commands::tools::tools_detect,
             commands::tools::tools_validate,
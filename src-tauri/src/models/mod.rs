@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 
+pub mod tool;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
     pub id: String,
@@ -84,6 +86,66 @@ pub struct Task {
     pub last_agent: Option<String>,
     #[serde(default)]
     pub last_agent_key_hint: Option<String>,
+    // Streamed content of the attempt in progress; cleared once output is final
+    #[serde(default)]
+    pub partial_output: Option<String>,
+    #[serde(default)]
+    pub token_usage: Option<TokenUsage>,
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+    // Last acceptance evaluation: score, threshold, findings and rework rounds
+    #[serde(default)]
+    pub evaluation: Option<serde_json::Value>,
+}
+
+// Estimated counts come from the model's tokenizer before the call; the rest are
+// what the provider reported, left empty when it reported nothing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub estimated_prompt_tokens: u32,
+    #[serde(default)]
+    pub estimated_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub prompt_tokens: Option<u32>,
+    #[serde(default)]
+    pub completion_tokens: Option<u32>,
+}
+
+impl TokenUsage {
+    /// Provider-reported counts where available, estimates otherwise.
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens.unwrap_or(self.estimated_prompt_tokens)
+            + self.completion_tokens.or(self.estimated_completion_tokens).unwrap_or(0)
+    }
+
+    pub fn is_actual(&self) -> bool {
+        self.prompt_tokens.is_some() && self.completion_tokens.is_some()
+    }
+
+    /// Fold in another call made for the same task, such as a repair round-trip.
+    pub fn add(&mut self, other: &TokenUsage) {
+        fn sum(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+            match (a, b) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            }
+        }
+        self.estimated_prompt_tokens += other.estimated_prompt_tokens;
+        self.estimated_completion_tokens = sum(self.estimated_completion_tokens, other.estimated_completion_tokens);
+        self.prompt_tokens = sum(self.prompt_tokens, other.prompt_tokens);
+        self.completion_tokens = sum(self.completion_tokens, other.completion_tokens);
+    }
+
+    /// Take out a call already accounted for elsewhere, such as an ensemble member priced on its own.
+    pub fn remove(&mut self, other: &TokenUsage) {
+        fn sub(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+            a.map(|a| a.saturating_sub(b.unwrap_or(0)))
+        }
+        self.estimated_prompt_tokens = self.estimated_prompt_tokens.saturating_sub(other.estimated_prompt_tokens);
+        self.estimated_completion_tokens = sub(self.estimated_completion_tokens, other.estimated_completion_tokens);
+        self.prompt_tokens = sub(self.prompt_tokens, other.prompt_tokens);
+        self.completion_tokens = sub(self.completion_tokens, other.completion_tokens);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -122,6 +184,28 @@ pub struct Agent {
     pub local: bool,
     pub max_concurrent_tasks: usize,
     pub token_limit: Option<u32>,
+    // Executable spawned for local agents speaking the stdio JSON protocol
+    #[serde(default)]
+    pub process: Option<LocalProcessConfig>,
+    // Entry in AppConfig.providers used for direct API calls
+    #[serde(default)]
+    pub provider: Option<String>,
+    // Model requested from the provider when the task names none
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalProcessConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,6 +224,60 @@ pub struct AgentHealth {
     pub error_rate: f32,
     pub success_count: u32,
     pub failure_count: u32,
+    #[serde(default)]
+    pub breaker: CircuitBreaker,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerSample {
+    pub success: bool,
+    pub latency_ms: u32,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerTransition {
+    pub from: BreakerState,
+    pub to: BreakerState,
+    pub at: DateTime<Utc>,
+    pub reason: String,
+}
+
+// Sliding-window circuit breaker; transition logic lives in services::circuit_breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    pub state: BreakerState,
+    #[serde(default)]
+    pub window: Vec<BreakerSample>,
+    #[serde(default)]
+    pub opened_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub probes_in_flight: u32,
+    #[serde(default)]
+    pub probe_successes: u32,
+    #[serde(default)]
+    pub transitions: Vec<BreakerTransition>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            window: Vec::new(),
+            opened_at: None,
+            probes_in_flight: 0,
+            probe_successes: 0,
+            transitions: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -163,6 +301,240 @@ pub struct AppConfig {
     pub backup_enabled: bool,
     pub backup_interval_hours: u32,
     pub ignore_task_token_limits: bool,
+    // Per-provider request/token budgets, keyed by provider name (openai, anthropic, ollama)
+    #[serde(default = "default_rate_limits")]
+    pub rate_limits: HashMap<String, RateLimitConfig>,
+    // Model API endpoints by name; agents and tasks pick one of these
+    #[serde(default = "default_providers")]
+    pub providers: HashMap<String, ProviderConfig>,
+    // Provider for tasks that name neither a provider nor a model it recognises
+    #[serde(default = "default_provider")]
+    pub default_provider: String,
+    // JSON Schemas that outputs of a task type must satisfy
+    #[serde(default)]
+    pub output_schemas: HashMap<String, serde_json::Value>,
+    // Pricing and limits by model name; versioned names match their longest known prefix
+    #[serde(default = "default_models")]
+    pub models: HashMap<String, ModelInfo>,
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+}
+
+// On-disk cache of text responses; off unless enabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseCacheConfig {
+    pub enabled: bool,
+    pub max_size_mb: u64,
+    pub ttl_hours: u64,
+    // Also cache calls sampled with temperature above zero
+    #[serde(default)]
+    pub cache_nondeterministic: bool,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size_mb: 256,
+            ttl_hours: 24 * 7,
+            cache_nondeterministic: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    #[serde(default)]
+    pub provider: Option<String>,
+    // USD per 1,000 tokens
+    pub input_price_per_1k: f64,
+    pub output_price_per_1k: f64,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+}
+
+impl ModelInfo {
+    fn new(provider: &str, input_price_per_1k: f64, output_price_per_1k: f64, context_window: u32, max_output_tokens: u32) -> Self {
+        Self {
+            provider: Some(provider.to_string()),
+            input_price_per_1k,
+            output_price_per_1k,
+            context_window,
+            max_output_tokens,
+        }
+    }
+
+    /// Cost of a call, using reported usage and falling back to estimates.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let prompt = usage.prompt_tokens.unwrap_or(usage.estimated_prompt_tokens) as f64;
+        let completion = usage.completion_tokens.or(usage.estimated_completion_tokens).unwrap_or(0) as f64;
+        prompt / 1000.0 * self.input_price_per_1k + completion / 1000.0 * self.output_price_per_1k
+    }
+}
+
+/// Registry entry for a model: exact name first, else the longest registered prefix
+/// (so `gpt-4o-2024-08-06` resolves to `gpt-4o`).
+pub fn lookup_model<'a>(models: &'a HashMap<String, ModelInfo>, model: &str) -> Option<&'a ModelInfo> {
+    models.get(model).or_else(|| {
+        models
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, info)| info)
+    })
+}
+
+pub fn default_models() -> HashMap<String, ModelInfo> {
+    let mut models = HashMap::new();
+    models.insert("gpt-4o".to_string(), ModelInfo::new("openai", 0.0025, 0.01, 128_000, 16_384));
+    models.insert("gpt-4o-mini".to_string(), ModelInfo::new("openai", 0.00015, 0.0006, 128_000, 16_384));
+    models.insert("gpt-4-turbo".to_string(), ModelInfo::new("openai", 0.01, 0.03, 128_000, 4_096));
+    models.insert("gpt-4".to_string(), ModelInfo::new("openai", 0.03, 0.06, 8_192, 8_192));
+    models.insert("gpt-3.5-turbo".to_string(), ModelInfo::new("openai", 0.0005, 0.0015, 16_385, 4_096));
+    models.insert("claude-3-5-sonnet".to_string(), ModelInfo::new("anthropic", 0.003, 0.015, 200_000, 8_192));
+    models.insert("claude-3-5-haiku".to_string(), ModelInfo::new("anthropic", 0.0008, 0.004, 200_000, 8_192));
+    models.insert("claude-3-opus".to_string(), ModelInfo::new("anthropic", 0.015, 0.075, 200_000, 4_096));
+    models.insert("claude-3-haiku".to_string(), ModelInfo::new("anthropic", 0.00025, 0.00125, 200_000, 4_096));
+    models.insert("llama3".to_string(), ModelInfo::new("ollama", 0.0, 0.0, 8_192, 4_096));
+    models.insert("mistral".to_string(), ModelInfo::new("ollama", 0.0, 0.0, 32_768, 4_096));
+    models
+}
+
+// Wire format spoken by a provider. OpenAI-compatible servers (vLLM, llama.cpp,
+// LM Studio, mocks) use `openai` with their own base URL.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[serde(rename = "openai")]
+    OpenAi,
+    Anthropic,
+    Ollama,
+    StableDiffusion,
+    // Runs a TTS engine detected by ToolManager instead of calling an API
+    LocalTts,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    Bearer,
+    XApiKey,
+    None,
+}
+
+// Structured-output support used when a task carries an output schema
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonMode {
+    #[default]
+    None,
+    JsonObject,
+    JsonSchema,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub base_url: String,
+    pub auth_style: AuthStyle,
+    #[serde(default)]
+    pub default_model: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    // Environment variable read when no key is stored for this provider
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub json_mode: JsonMode,
+}
+
+pub fn default_provider() -> String {
+    "openai".to_string()
+}
+
+pub fn default_providers() -> HashMap<String, ProviderConfig> {
+    let mut providers = HashMap::new();
+    providers.insert("openai".to_string(), ProviderConfig {
+        kind: ProviderKind::OpenAi,
+        base_url: "https://api.openai.com/v1".to_string(),
+        auth_style: AuthStyle::Bearer,
+        default_model: Some("gpt-4o".to_string()),
+        headers: HashMap::new(),
+        capabilities: vec![Capability::Text, Capability::Code, Capability::Image, Capability::Sound],
+        api_key_env: Some("OPENAI_API_KEY".to_string()),
+        json_mode: JsonMode::JsonSchema,
+    });
+    let mut anthropic_headers = HashMap::new();
+    anthropic_headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
+    providers.insert("anthropic".to_string(), ProviderConfig {
+        kind: ProviderKind::Anthropic,
+        base_url: "https://api.anthropic.com/v1".to_string(),
+        auth_style: AuthStyle::XApiKey,
+        default_model: Some("claude-3-5-sonnet-20241022".to_string()),
+        headers: anthropic_headers,
+        capabilities: vec![Capability::Text, Capability::Code],
+        api_key_env: Some("ANTHROPIC_API_KEY".to_string()),
+        json_mode: JsonMode::None,
+    });
+    providers.insert("ollama".to_string(), ProviderConfig {
+        kind: ProviderKind::Ollama,
+        base_url: "http://localhost:11434".to_string(),
+        auth_style: AuthStyle::None,
+        default_model: Some("llama3".to_string()),
+        headers: HashMap::new(),
+        capabilities: vec![Capability::Text, Capability::Code],
+        api_key_env: None,
+        json_mode: JsonMode::JsonSchema,
+    });
+    // AUTOMATIC1111 web UI started with --api
+    providers.insert("stable_diffusion".to_string(), ProviderConfig {
+        kind: ProviderKind::StableDiffusion,
+        base_url: "http://127.0.0.1:7860".to_string(),
+        auth_style: AuthStyle::None,
+        default_model: None,
+        headers: HashMap::new(),
+        capabilities: vec![Capability::Image],
+        api_key_env: None,
+        json_mode: JsonMode::None,
+    });
+    // Piper or eSpeak NG on the PATH; default_model is the voice
+    providers.insert("local_tts".to_string(), ProviderConfig {
+        kind: ProviderKind::LocalTts,
+        base_url: String::new(),
+        auth_style: AuthStyle::None,
+        default_model: None,
+        headers: HashMap::new(),
+        capabilities: vec![Capability::Sound],
+        api_key_env: None,
+        json_mode: JsonMode::None,
+    });
+    providers
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: u32,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            tokens_per_minute: None,
+        }
+    }
+}
+
+pub fn default_rate_limits() -> HashMap<String, RateLimitConfig> {
+    let mut limits = HashMap::new();
+    limits.insert("openai".to_string(), RateLimitConfig { requests_per_minute: 60, tokens_per_minute: Some(90000) });
+    limits.insert("anthropic".to_string(), RateLimitConfig { requests_per_minute: 50, tokens_per_minute: Some(40000) });
+    limits.insert("ollama".to_string(), RateLimitConfig { requests_per_minute: 100, tokens_per_minute: None });
+    limits
 }
 
 impl Default for AppConfig {
@@ -189,6 +561,12 @@ impl Default for AppConfig {
             backup_enabled: true,
             backup_interval_hours: 24,
             ignore_task_token_limits: false,
+            rate_limits: default_rate_limits(),
+            providers: default_providers(),
+            default_provider: default_provider(),
+            output_schemas: HashMap::new(),
+            models: default_models(),
+            response_cache: ResponseCacheConfig::default(),
         }
     }
 }
\ No newline at end of file
@@ -354,4 +354,68 @@ impl Tool {
             documentation_url: Some("https://pandoc.org/".to_string()),
         }
     }
+    
+    /// Neural TTS reading text from stdin; voices are `.onnx` model files.
+    pub fn piper() -> Self {
+        let mut parameters = HashMap::new();
+        
+        parameters.insert("model".to_string(), ParameterDefinition {
+            name: "model".to_string(),
+            param_type: ParameterType::FilePath,
+            required: true,
+            default_value: None,
+            description: "Voice model".to_string(),
+            validation: Some(ParameterValidation {
+                min_value: None,
+                max_value: None,
+                regex_pattern: None,
+                file_extensions: Some(vec!["onnx".to_string()]),
+            }),
+            depends_on: None,
+        });
+        
+        Tool {
+            id: "piper".to_string(),
+            name: "Piper".to_string(),
+            category: ToolCategory::AudioProcessing,
+            executable_path: None,
+            version: None,
+            capabilities: vec![ToolCapability::AudioSynthesize],
+            input_formats: vec!["txt".to_string()],
+            output_formats: vec!["wav".to_string()],
+            parameters,
+            environment_vars: HashMap::new(),
+            working_directory: None,
+            timeout_seconds: Some(600),
+            is_available: false,
+            requires_gpu: false,
+            requires_network: false,
+            platform_specific: HashMap::new(),
+            validation_command: Some("--version".to_string()),
+            documentation_url: Some("https://github.com/rhasspy/piper".to_string()),
+        }
+    }
+    
+    pub fn espeak_ng() -> Self {
+        Tool {
+            id: "espeak-ng".to_string(),
+            name: "eSpeak NG".to_string(),
+            category: ToolCategory::AudioProcessing,
+            executable_path: None,
+            version: None,
+            capabilities: vec![ToolCapability::AudioSynthesize],
+            input_formats: vec!["txt".to_string()],
+            output_formats: vec!["wav".to_string()],
+            parameters: HashMap::new(),
+            environment_vars: HashMap::new(),
+            working_directory: None,
+            timeout_seconds: Some(300),
+            is_available: false,
+            requires_gpu: false,
+            requires_network: false,
+            platform_specific: HashMap::new(),
+            validation_command: Some("--version".to_string()),
+            documentation_url: Some("https://github.com/espeak-ng/espeak-ng".to_string()),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::models::{Agent, Capability};
+use crate::state::AppState;
+use super::local_agent::LocalProcessAgent;
+use super::ws_agent::{is_websocket_endpoint, WsAgentConnection, WsAgentEvent, WsHandshake};
+
+// How often the supervisor checks that hosted agents are up and match the agent list
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Shared so the executor and the agent commands see the same processes
+static AGENT_HOST: Lazy<Arc<AgentHost>> = Lazy::new(|| Arc::new(AgentHost::new()));
+
+pub fn agent_host() -> Arc<AgentHost> {
+    Arc::clone(&AGENT_HOST)
+}
+
+/// Configured agent preference list for a capability, project override first.
+pub fn preferred_agents(state: &AppState, project_id: &str, capability: &Capability) -> Vec<String> {
+    let project_override = state.projects.read()
+        .get(project_id)
+        .and_then(|p| p.config_override.as_ref())
+        .and_then(|o| o.get("agent_priorities"))
+        .and_then(|v| serde_json::from_value::<HashMap<Capability, Vec<String>>>(v.clone()).ok())
+        .and_then(|mut priorities| priorities.remove(capability));
+    
+    project_override
+        .or_else(|| state.config.read().agent_priorities.get(capability).cloned())
+        .unwrap_or_default()
+}
+
+/// A task as sent to an agent that runs it itself rather than through a provider API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRequest {
+    pub task_id: String,
+    pub task_type: String,
+    pub capability: Capability,
+    pub input: serde_json::Value,
+    pub preamble: String,
+    pub token_limit: u32,
+    pub context: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentResponse {
+    pub task_id: String,
+    pub success: bool,
+    pub output: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub tokens_used: Option<u32>,
+    pub execution_time_ms: u64,
+}
+
+/// Agents the app runs or connects to itself: enabled agents with a `process` run as
+/// supervised local subprocesses, those with a `ws://` or `wss://` endpoint keep a
+/// WebSocket connection open.
+pub struct AgentHost {
+    local: RwLock<HashMap<String, Arc<LocalProcessAgent>>>,
+    websocket: RwLock<HashMap<String, Arc<WsAgentConnection>>>,
+}
+
+impl AgentHost {
+    fn new() -> Self {
+        Self {
+            local: RwLock::new(HashMap::new()),
+            websocket: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Match the hosted agents to the agent list: add new ones, restart those whose
+    /// process settings changed and stop disabled or deleted ones.
+    pub fn sync(&self, agents: &[Agent]) {
+        let mut local = self.local.write();
+        let stale: Vec<String> = local.iter()
+            .filter(|(name, process)| !agents.iter().any(|a| {
+                &a.name == *name && a.enabled && a.process.as_ref() == Some(process.config())
+            }))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            if let Some(process) = local.remove(&name) {
+                info!("Stopping local agent {}", name);
+                tauri::async_runtime::spawn(async move { process.shutdown().await });
+            }
+        }
+        for agent in agents.iter().filter(|a| a.enabled) {
+            if let Some(config) = &agent.process {
+                if !local.contains_key(&agent.name) {
+                    info!("Hosting local agent {} ({})", agent.name, config.command);
+                    local.insert(agent.name.clone(), Arc::new(LocalProcessAgent::new(agent, config.clone())));
+                }
+            }
+        }
+        drop(local);
+
+        // Dropping a connection closes its socket; a changed endpoint or auth reconnects
+        let mut websocket = self.websocket.write();
+        websocket.retain(|name, connection| {
+            let current = connection.agent();
+            agents.iter().any(|a| {
+                &a.name == name
+                    && a.enabled
+                    && a.endpoint_url == current.endpoint_url
+                    && serde_json::to_value(&a.auth).ok() == serde_json::to_value(&current.auth).ok()
+            })
+        });
+        for agent in agents.iter().filter(|a| a.enabled && a.process.is_none()) {
+            let endpoint = agent.endpoint_url.as_deref().unwrap_or_default();
+            if is_websocket_endpoint(endpoint) && !websocket.contains_key(&agent.name) {
+                info!("Connecting to WebSocket agent {} at {}", agent.name, endpoint);
+                let connection = Arc::new(WsAgentConnection::new(agent));
+                connection.connect();
+                websocket.insert(agent.name.clone(), connection);
+            }
+        }
+    }
+
+    /// Whether tasks for this agent go to a hosted agent instead of a provider.
+    pub fn hosts(&self, agent: &str) -> bool {
+        self.local.read().contains_key(agent) || self.websocket.read().contains_key(agent)
+    }
+
+    /// The running process behind a hosted local agent.
+    pub fn local(&self, agent: &str) -> Option<Arc<LocalProcessAgent>> {
+        self.local.read().get(agent).cloned()
+    }
+
+    pub async fn execute(&self, agent: &str, request: AgentRequest) -> anyhow::Result<AgentResponse> {
+        let start_time = std::time::Instant::now();
+        let process = self.local.read().get(agent).cloned();
+        let mut response = match process {
+            Some(process) => process.execute(request).await?,
+            None => {
+                let connection = self.websocket.read().get(agent).cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Agent {} is not hosted", agent))?;
+                connection.execute(request).await?
+            }
+        };
+        response.execution_time_ms = start_time.elapsed().as_millis() as u64;
+        Ok(response)
+    }
+
+    /// Progress and partial output from a WebSocket agent.
+    pub fn subscribe(&self, agent: &str) -> Option<tokio::sync::broadcast::Receiver<WsAgentEvent>> {
+        self.websocket.read().get(agent).map(|connection| connection.subscribe())
+    }
+
+    /// What a WebSocket agent advertised when it last connected.
+    pub fn handshake(&self, agent: &str) -> Option<WsHandshake> {
+        self.websocket.read().get(agent).and_then(|connection| connection.handshake())
+    }
+
+    /// Cancel a task running on a WebSocket agent; local processes cannot be interrupted.
+    pub fn cancel(&self, task_id: &str) {
+        for connection in self.websocket.read().values().filter(|c| c.is_running(task_id)) {
+            connection.cancel(task_id);
+        }
+    }
+
+    /// Keep hosted agents in step with the agent list and their processes running,
+    /// restarting crashed ones with the process's own backoff.
+    pub fn supervise(self: Arc<Self>, state: Arc<AppState>) {
+        tauri::async_runtime::spawn(async move {
+            let mut interval = tokio::time::interval(SUPERVISE_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.sync(&state.agents.read());
+
+                let processes: Vec<(String, Arc<LocalProcessAgent>)> = self.local.read()
+                    .iter()
+                    .map(|(name, process)| (name.clone(), Arc::clone(process)))
+                    .collect();
+                for (name, process) in processes {
+                    if let Err(e) = process.ensure_running().await {
+                        warn!("Local agent {} is not running: {}", name, e);
+                    }
+                }
+            }
+        });
+    }
+}
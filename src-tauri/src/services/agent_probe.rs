@@ -0,0 +1,459 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::debug;
+use crate::models::{Agent, Capability, LocalProcessConfig, ProviderKind};
+use super::agent_host::{agent_host, AgentRequest};
+use super::local_agent::LocalProcessAgent;
+use super::providers::provider_registry;
+
+const PROBE_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentKind {
+    OpenAi,
+    Anthropic,
+    Ollama,
+    Remote,
+    Local,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeCheck {
+    pub name: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Structured result of a real round-trip against an agent.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentProbeReport {
+    pub agent: String,
+    pub kind: AgentKind,
+    pub ok: bool,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_ok: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_available: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_limit_mismatch: Option<String>,
+    pub checks: Vec<ProbeCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AgentProbeReport {
+    fn new(agent: &Agent, kind: AgentKind, model: Option<String>) -> Self {
+        Self {
+            agent: agent.name.clone(),
+            kind,
+            ok: false,
+            reachable: false,
+            latency_ms: None,
+            auth_ok: None,
+            model,
+            model_available: None,
+            context_window: None,
+            token_limit_mismatch: None,
+            checks: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn record(&mut self, check: ProbeCheck) {
+        if let Some(latency) = check.latency_ms {
+            self.latency_ms = Some(self.latency_ms.map_or(latency, |l| l.max(latency)));
+        }
+        self.checks.push(check);
+    }
+
+    fn finish(mut self) -> Self {
+        self.ok = self.reachable
+            && self.auth_ok != Some(false)
+            && self.model_available != Some(false)
+            && self.checks.iter().all(|c| c.ok);
+        if !self.ok && self.error.is_none() {
+            self.error = self.checks.iter().find(|c| !c.ok).and_then(|c| c.detail.clone());
+        }
+        self
+    }
+}
+
+/// Decide how to talk to an agent from its auth type and endpoint.
+pub fn agent_kind(agent: &Agent) -> AgentKind {
+    let auth_type = agent.auth.as_ref().map(|a| a.auth_type.to_lowercase()).unwrap_or_default();
+    let endpoint = agent.endpoint_url.as_deref().unwrap_or("").to_lowercase();
+
+    if auth_type == "openai" || endpoint.contains("api.openai.com") {
+        AgentKind::OpenAi
+    } else if auth_type == "anthropic" || endpoint.contains("api.anthropic.com") {
+        AgentKind::Anthropic
+    } else if auth_type == "ollama" || endpoint.contains(":11434") {
+        AgentKind::Ollama
+    } else if !endpoint.is_empty() {
+        AgentKind::Remote
+    } else {
+        AgentKind::Local
+    }
+}
+
+pub struct AgentProber {
+    http_client: reqwest::Client,
+}
+
+impl AgentProber {
+    pub fn new() -> Self {
+        Self {
+            http_client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(PROBE_TIMEOUT_SECS))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    pub async fn probe(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let kind = agent_kind(agent);
+        debug!("Probing agent {} as {:?}", agent.name, kind);
+
+        let report = match kind {
+            AgentKind::OpenAi => self.probe_openai(agent, model).await,
+            AgentKind::Anthropic => self.probe_anthropic(agent, model).await,
+            AgentKind::Ollama => self.probe_ollama(agent, model).await,
+            AgentKind::Remote => self.probe_remote(agent, model).await,
+            AgentKind::Local => match &agent.process {
+                Some(config) => self.probe_process(agent, config, model).await,
+                None => self.probe_provider(agent, model).await,
+            },
+        };
+        report.finish()
+    }
+
+    async fn probe_openai(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let model = model.unwrap_or_else(|| "gpt-4o-mini".to_string());
+        let mut report = AgentProbeReport::new(agent, AgentKind::OpenAi, Some(model.clone()));
+        let base = agent.endpoint_url.clone().unwrap_or_else(|| "https://api.openai.com".to_string());
+
+        let Some(api_key) = api_key_for(agent, "OPENAI_API_KEY") else {
+            report.auth_ok = Some(false);
+            report.error = Some("No API key configured for OpenAI".to_string());
+            return report;
+        };
+
+        let url = format!("{}/v1/models", base.trim_end_matches('/'));
+        let request = self.http_client.get(&url).bearer_auth(api_key);
+        let (check, body) = self.timed("models", request).await;
+        report.reachable = check.status.is_some();
+        report.auth_ok = check.status.map(|s| s != 401 && s != 403);
+        report.record(check);
+
+        if let Some(body) = body {
+            let available = body["data"]
+                .as_array()
+                .map(|models| models.iter().any(|m| m["id"].as_str() == Some(model.as_str())));
+            report.model_available = available;
+        }
+        check_token_limit(&mut report, agent, context_window(&model));
+        report
+    }
+
+    async fn probe_anthropic(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let model = model.unwrap_or_else(|| "claude-3-haiku-20240307".to_string());
+        let mut report = AgentProbeReport::new(agent, AgentKind::Anthropic, Some(model.clone()));
+        let base = agent.endpoint_url.clone().unwrap_or_else(|| "https://api.anthropic.com".to_string());
+
+        let Some(api_key) = api_key_for(agent, "ANTHROPIC_API_KEY") else {
+            report.auth_ok = Some(false);
+            report.error = Some("No API key configured for Anthropic".to_string());
+            return report;
+        };
+
+        let url = format!("{}/v1/models/{}", base.trim_end_matches('/'), model);
+        let request = self.http_client
+            .get(&url)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01");
+        let (check, _) = self.timed("model", request).await;
+        report.reachable = check.status.is_some();
+        report.auth_ok = check.status.map(|s| s != 401 && s != 403);
+        report.model_available = match check.status {
+            Some(404) => Some(false),
+            Some(s) if (200..300).contains(&s) => Some(true),
+            _ => None,
+        };
+        report.record(check);
+
+        check_token_limit(&mut report, agent, context_window(&model));
+        report
+    }
+
+    async fn probe_ollama(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let mut report = AgentProbeReport::new(agent, AgentKind::Ollama, model.clone());
+        let base = agent.endpoint_url.clone().unwrap_or_else(|| "http://localhost:11434".to_string());
+        let base = base.trim_end_matches('/');
+
+        let (check, body) = self.timed("tags", self.http_client.get(format!("{}/api/tags", base))).await;
+        report.reachable = check.status.is_some();
+        report.record(check);
+
+        let installed: Vec<String> = body
+            .as_ref()
+            .and_then(|b| b["models"].as_array())
+            .map(|models| models.iter().filter_map(|m| m["name"].as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default();
+        let model = model.or_else(|| installed.first().cloned());
+        report.model = model.clone();
+
+        if let Some(model) = model {
+            report.model_available = Some(installed.iter().any(|m| m == &model || m.trim_end_matches(":latest") == model));
+
+            let request = self.http_client
+                .post(format!("{}/api/show", base))
+                .json(&json!({ "model": model }));
+            let (check, body) = self.timed("show", request).await;
+            let context = body.as_ref().and_then(|b| {
+                b["model_info"].as_object().and_then(|info| {
+                    info.iter()
+                        .find(|(k, _)| k.ends_with(".context_length"))
+                        .and_then(|(_, v)| v.as_u64())
+                        .map(|v| v as u32)
+                })
+            });
+            report.record(check);
+            check_token_limit(&mut report, agent, context);
+        }
+        report
+    }
+
+    /// Start the agent's process, or use the one already hosted, and send it a tiny task
+    /// over the stdio protocol.
+    async fn probe_process(&self, agent: &Agent, config: &LocalProcessConfig, model: Option<String>) -> AgentProbeReport {
+        let mut report = AgentProbeReport::new(agent, AgentKind::Local, model);
+        // Disabled agents are not hosted; run a throwaway process for the test
+        let (process, temporary) = match agent_host().local(&agent.name) {
+            Some(process) => (process, false),
+            None => (Arc::new(LocalProcessAgent::new(agent, config.clone())), true),
+        };
+
+        let start = Instant::now();
+        let spawned = process.ensure_running().await;
+        report.reachable = spawned.is_ok();
+        report.record(ProbeCheck {
+            name: "process".to_string(),
+            ok: spawned.is_ok(),
+            latency_ms: Some(start.elapsed().as_millis() as u32),
+            status: None,
+            detail: spawned.err().map(|e| format!("{}: {}", config.command, e)),
+        });
+
+        if report.reachable {
+            let start = Instant::now();
+            let outcome = tokio::time::timeout(
+                Duration::from_secs(PROBE_TIMEOUT_SECS),
+                process.execute(probe_request(agent)),
+            )
+            .await;
+            let (ok, detail) = match outcome {
+                Ok(Ok(response)) if response.success => (true, None),
+                Ok(Ok(response)) => (false, Some(response.error.unwrap_or_else(|| "Agent reported failure".to_string()))),
+                Ok(Err(e)) => (false, Some(e.to_string())),
+                Err(_) => (false, Some(format!("No answer within {}s", PROBE_TIMEOUT_SECS))),
+            };
+            report.record(ProbeCheck {
+                name: "task".to_string(),
+                ok,
+                latency_ms: Some(start.elapsed().as_millis() as u32),
+                status: None,
+                detail,
+            });
+        }
+
+        if temporary {
+            process.shutdown().await;
+        }
+        report
+    }
+
+    /// Agents without a process run on a provider API, resolved the way the executor
+    /// resolves it; probe that provider.
+    async fn probe_provider(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let model = model.or_else(|| agent.model.clone());
+        let provider = match provider_registry().resolve(agent.provider.as_deref(), model.as_deref()) {
+            Ok(provider) => provider,
+            Err(e) => {
+                let mut report = AgentProbeReport::new(agent, AgentKind::Local, model);
+                report.error = Some(e.to_string());
+                return report;
+            }
+        };
+        let model = model.or_else(|| provider.default_model().map(|m| m.to_string()));
+
+        // The probes add the API version to the endpoint themselves
+        let config = provider.config();
+        let mut routed = agent.clone();
+        routed.endpoint_url = Some(config.base_url.trim_end_matches('/').trim_end_matches("/v1").to_string());
+        match config.kind {
+            ProviderKind::OpenAi => self.probe_openai(&routed, model).await,
+            ProviderKind::Anthropic => self.probe_anthropic(&routed, model).await,
+            ProviderKind::Ollama => self.probe_ollama(&routed, model).await,
+            ProviderKind::StableDiffusion | ProviderKind::LocalTts => {
+                let mut report = AgentProbeReport::new(agent, AgentKind::Local, model);
+                if config.base_url.is_empty() {
+                    // Runs an engine found on this machine, nothing to call
+                    report.reachable = true;
+                    return report;
+                }
+                let (mut check, _) = self.timed("provider", self.http_client.get(&config.base_url)).await;
+                check.ok = check.status.is_some();
+                report.reachable = check.ok;
+                report.record(check);
+                report
+            }
+        }
+    }
+
+    async fn probe_remote(&self, agent: &Agent, model: Option<String>) -> AgentProbeReport {
+        let mut report = AgentProbeReport::new(agent, AgentKind::Remote, model);
+        let endpoint = agent.endpoint_url.clone().unwrap_or_default();
+        let headers = match remote_headers(agent) {
+            Ok(headers) => headers,
+            Err(e) => {
+                report.error = Some(format!("Invalid auth headers: {}", e));
+                return report;
+            }
+        };
+
+        let health_url = format!("{}/health", endpoint.trim_end_matches('/'));
+        let (check, _) = self.timed("health", self.http_client.get(&health_url).headers(headers.clone())).await;
+        report.reachable = check.status.is_some();
+        report.record(check);
+
+        // Same request shape the agent gets for real tasks
+        let (mut check, body) = self.timed("task", self.http_client.post(&endpoint).headers(headers).json(&probe_request(agent))).await;
+        report.auth_ok = check.status.map(|s| s != 401 && s != 403);
+        if let Some(body) = body {
+            if body["success"].as_bool() == Some(false) {
+                check.ok = false;
+                check.detail = body["error"].as_str().map(|s| s.to_string()).or(check.detail);
+            }
+        }
+        report.record(check);
+        report
+    }
+
+    async fn timed(&self, name: &str, request: reqwest::RequestBuilder) -> (ProbeCheck, Option<Value>) {
+        let start = Instant::now();
+        match request.send().await {
+            Ok(response) => {
+                let latency = start.elapsed().as_millis() as u32;
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                let body = serde_json::from_str::<Value>(&text).ok();
+                let detail = if status.is_success() {
+                    None
+                } else {
+                    let message = body
+                        .as_ref()
+                        .and_then(|b| b["error"]["message"].as_str().or(b["error"].as_str()))
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| text.chars().take(200).collect());
+                    Some(format!("HTTP {}: {}", status.as_u16(), message))
+                };
+                (
+                    ProbeCheck {
+                        name: name.to_string(),
+                        ok: status.is_success(),
+                        latency_ms: Some(latency),
+                        status: Some(status.as_u16()),
+                        detail,
+                    },
+                    body,
+                )
+            }
+            Err(e) => (
+                ProbeCheck {
+                    name: name.to_string(),
+                    ok: false,
+                    latency_ms: None,
+                    status: None,
+                    detail: Some(e.to_string()),
+                },
+                None,
+            ),
+        }
+    }
+}
+
+impl Default for AgentProber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tiny task exercising the same path real tasks use.
+fn probe_request(agent: &Agent) -> AgentRequest {
+    AgentRequest {
+        task_id: format!("probe-{}", uuid::Uuid::new_v4()),
+        task_type: "connectivity_probe".to_string(),
+        capability: agent.capabilities.first().cloned().unwrap_or(Capability::Text),
+        input: json!({ "prompt": "Reply with OK." }),
+        preamble: "Connectivity test. Reply with OK.".to_string(),
+        token_limit: 16,
+        context: Vec::new(),
+    }
+}
+
+/// Context window of the model as registered in `AppConfig.models`.
+fn context_window(model: &str) -> Option<u32> {
+    provider_registry().model_info(model).map(|info| info.context_window)
+}
+
+fn api_key_for(agent: &Agent, env_var: &str) -> Option<String> {
+    agent.auth
+        .as_ref()
+        .and_then(|a| a.api_key.clone().or_else(|| a.bearer_token.clone()))
+        .or_else(|| std::env::var(env_var).ok())
+        .filter(|k| !k.is_empty())
+}
+
+/// Same header scheme `AgentPool::execute_remote_task` uses for real tasks.
+pub fn remote_headers(agent: &Agent) -> anyhow::Result<reqwest::header::HeaderMap> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(auth) = &agent.auth {
+        if let Some(api_key) = &auth.api_key {
+            headers.insert("X-API-Key", api_key.parse()?);
+        }
+        if let Some(bearer) = &auth.bearer_token {
+            headers.insert("Authorization", format!("Bearer {}", bearer).parse()?);
+        }
+        for (key, value) in &auth.custom_headers {
+            headers.insert(reqwest::header::HeaderName::from_bytes(key.as_bytes())?, value.parse()?);
+        }
+    }
+    Ok(headers)
+}
+
+fn check_token_limit(report: &mut AgentProbeReport, agent: &Agent, context_window: Option<u32>) {
+    report.context_window = context_window;
+    if let (Some(limit), Some(window)) = (agent.token_limit, context_window) {
+        if limit > window {
+            report.token_limit_mismatch = Some(format!(
+                "agent token_limit {} exceeds model context window {}",
+                limit, window
+            ));
+        }
+    }
+}
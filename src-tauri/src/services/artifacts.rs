@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::process::Command;
+use tracing::debug;
+use which::which;
+
+/// Directory for a task's generated media: `<projects>/<project_id>/artifacts`, or the
+/// temp directory for calls made outside a project.
+pub fn artifact_dir(projects_root: Option<&Path>, project_id: Option<&str>) -> PathBuf {
+    match (projects_root, project_id) {
+        (Some(root), Some(project_id)) => root.join(project_id).join("artifacts"),
+        _ => std::env::temp_dir().join("supercollider-artifacts"),
+    }
+}
+
+/// File stem safe on every platform; task IDs come from the model and may contain anything.
+pub fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn write(dir: &Path, stem: &str, extension: &str, bytes: &[u8]) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.{}", file_stem(stem), extension));
+    fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+fn image_extension(content_type: Option<&str>, bytes: &[u8]) -> String {
+    match content_type.and_then(|ct| ct.split(';').next()).map(str::trim) {
+        Some("image/png") => "png".to_string(),
+        Some("image/jpeg") => "jpg".to_string(),
+        Some("image/webp") => "webp".to_string(),
+        Some("image/gif") => "gif".to_string(),
+        _ => match imagesize::image_type(bytes) {
+            Ok(imagesize::ImageType::Jpeg) => "jpg".to_string(),
+            Ok(kind) => format!("{:?}", kind).to_lowercase(),
+            Err(_) => "png".to_string(),
+        },
+    }
+}
+
+/// Store a generated image and describe it as a task output.
+pub fn save_image(dir: &Path, stem: &str, content_type: Option<&str>, bytes: &[u8]) -> Result<Value> {
+    let format = image_extension(content_type, bytes);
+    let path = write(dir, stem, &format, bytes)?;
+    let size = imagesize::blob_size(bytes).ok();
+    Ok(json!({
+        "type": "image",
+        "path": path.to_string_lossy(),
+        "format": format,
+        "width": size.as_ref().map(|s| s.width),
+        "height": size.as_ref().map(|s| s.height),
+        "bytes": bytes.len()
+    }))
+}
+
+/// Store generated audio and describe it as a task output, with its duration when
+/// ffprobe is installed.
+pub async fn save_audio(dir: &Path, stem: &str, format: &str, bytes: &[u8]) -> Result<Value> {
+    let path = write(dir, stem, format, bytes)?;
+    let duration = media_duration(&path).await;
+    Ok(json!({
+        "type": "audio",
+        "path": path.to_string_lossy(),
+        "format": format,
+        "duration_secs": duration,
+        "bytes": bytes.len()
+    }))
+}
+
+/// Duration in seconds as reported by ffprobe.
+pub async fn media_duration(path: &Path) -> Option<f64> {
+    let ffprobe = which("ffprobe").ok()?;
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    let duration = String::from_utf8_lossy(&output.stdout).trim().parse().ok();
+    if duration.is_none() {
+        debug!("ffprobe reported no duration for {}", path.display());
+    }
+    duration
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+use crate::storage::StorageService;
+
+// Kept in each project's directory next to usage.jsonl
+const CASSETTE_FILE: &str = "cassette.jsonl";
+
+static CASSETTES: Lazy<Arc<Cassettes>> = Lazy::new(|| Arc::new(Cassettes::new()));
+
+pub fn cassettes() -> Arc<Cassettes> {
+    Arc::clone(&CASSETTES)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    #[default]
+    Off,
+    Record,
+    Replay,
+}
+
+/// One provider round-trip. Body chunks are base64 so audio and SSE replay byte for byte.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub key: String,
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub request_body: Value,
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub chunks: Vec<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Raised in replay mode when the cassette has no response for a request; never retried.
+#[derive(Debug)]
+pub struct CassetteMissError {
+    pub project_id: String,
+    pub method: String,
+    pub url: String,
+    pub key: String,
+}
+
+impl fmt::Display for CassetteMissError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cassette for project {} has no recorded response for {} {} (request {})",
+            self.project_id, self.method, self.url, self.key
+        )
+    }
+}
+
+impl std::error::Error for CassetteMissError {}
+
+#[derive(Default)]
+struct Replay {
+    interactions: HashMap<String, Vec<Interaction>>,
+    // How many responses have been served per key; identical requests replay in recorded order
+    served: HashMap<String, usize>,
+}
+
+pub struct Cassettes {
+    storage: RwLock<Option<Arc<StorageService>>>,
+    modes: RwLock<HashMap<String, CassetteMode>>,
+    replays: Mutex<HashMap<String, Replay>>,
+}
+
+impl Cassettes {
+    fn new() -> Self {
+        Self {
+            storage: RwLock::new(None),
+            modes: RwLock::new(HashMap::new()),
+            replays: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn configure(&self, storage: Arc<StorageService>) {
+        *self.storage.write() = Some(storage);
+    }
+
+    pub fn mode(&self, project_id: Option<&str>) -> CassetteMode {
+        project_id
+            .and_then(|id| self.modes.read().get(id).copied())
+            .unwrap_or_default()
+    }
+
+    /// Switch a project's mode. Recording starts a fresh cassette; replay loads the
+    /// existing one and fails if there is nothing to replay.
+    pub fn set_mode(&self, project_id: &str, mode: CassetteMode) -> Result<usize> {
+        let storage = self.storage()?;
+        let mut recorded = 0;
+        match mode {
+            CassetteMode::Off => {}
+            CassetteMode::Record => {
+                let path = format!("projects/{}/{}", project_id, CASSETTE_FILE);
+                if storage.exists(&path) {
+                    storage.delete(&path)?;
+                }
+            }
+            CassetteMode::Replay => {
+                let mut replay = Replay::default();
+                for value in storage.load_project_jsonl(project_id, CASSETTE_FILE)? {
+                    let interaction: Interaction = serde_json::from_value(value)?;
+                    replay.interactions.entry(interaction.key.clone()).or_default().push(interaction);
+                    recorded += 1;
+                }
+                if recorded == 0 {
+                    return Err(anyhow!("No cassette recorded for project {}", project_id));
+                }
+                self.replays.lock().insert(project_id.to_string(), replay);
+            }
+        }
+        if mode != CassetteMode::Replay {
+            self.replays.lock().remove(project_id);
+        }
+        self.modes.write().insert(project_id.to_string(), mode);
+        info!("Cassette for project {} set to {:?}", project_id, mode);
+        Ok(recorded)
+    }
+
+    pub fn recorded_count(&self, project_id: &str) -> usize {
+        self.storage()
+            .and_then(|storage| storage.load_project_jsonl(project_id, CASSETTE_FILE))
+            .map(|entries| entries.len())
+            .unwrap_or(0)
+    }
+
+    fn storage(&self) -> Result<Arc<StorageService>> {
+        self.storage.read().clone().ok_or_else(|| anyhow!("Cassettes are not configured"))
+    }
+
+    /// Send a provider request, recording or replaying it when the project has a cassette.
+    ///
+    /// Recorded responses are read in full before the caller sees them, so streamed output
+    /// arrives in one burst while recording.
+    pub async fn send(&self, client: &reqwest::Client, project_id: Option<&str>, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = request.build()?;
+        let project_id = match (project_id, self.mode(project_id)) {
+            (Some(id), CassetteMode::Record | CassetteMode::Replay) => id,
+            _ => return Ok(client.execute(request).await?),
+        };
+
+        let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        let request_body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        let key = request_key(request.method().as_str(), request.url().as_str(), &request_body, body);
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+
+        if self.mode(Some(project_id)) == CassetteMode::Replay {
+            let interaction = self.next_replay(project_id, &key).ok_or_else(|| {
+                let miss = CassetteMissError { project_id: project_id.to_string(), method, url, key };
+                error!("{}", miss);
+                miss
+            })?;
+            return to_response(&interaction);
+        }
+
+        let response = client.execute(request).await?;
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let mut chunks = Vec::new();
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            chunks.push(BASE64.encode(chunk?));
+        }
+
+        let interaction = Interaction {
+            key,
+            method,
+            url,
+            request_body,
+            status,
+            content_type,
+            chunks,
+            recorded_at: Utc::now(),
+        };
+        self.storage()?.append_to_jsonl(project_id, CASSETTE_FILE, &serde_json::to_value(&interaction)?)?;
+        to_response(&interaction)
+    }
+
+    fn next_replay(&self, project_id: &str, key: &str) -> Option<Interaction> {
+        let mut replays = self.replays.lock();
+        let replay = replays.get_mut(project_id)?;
+        let recorded = replay.interactions.get(key)?;
+        let served = replay.served.entry(key.to_string()).or_insert(0);
+        // Past the end, keep answering with the last response for this request
+        let interaction = recorded.get(*served).or_else(|| recorded.last())?.clone();
+        *served += 1;
+        Some(interaction)
+    }
+}
+
+/// SHA-256 over method, URL and body; JSON bodies are hashed in canonical form.
+fn request_key(method: &str, url: &str, json_body: &Value, raw_body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b" ");
+    hasher.update(url.as_bytes());
+    hasher.update(b"\n");
+    match json_body {
+        Value::Null => hasher.update(raw_body),
+        body => hasher.update(body.to_string().as_bytes()),
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Rebuild a response that streams the recorded chunks with their original boundaries.
+fn to_response(interaction: &Interaction) -> Result<reqwest::Response> {
+    let chunks = interaction
+        .chunks
+        .iter()
+        .map(|chunk| BASE64.decode(chunk))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let stream = futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>));
+
+    let mut builder = http::Response::builder().status(interaction.status);
+    if let Some(content_type) = &interaction.content_type {
+        builder = builder.header(reqwest::header::CONTENT_TYPE, content_type.as_str());
+    }
+    let response = builder.body(reqwest::Body::wrap_stream(stream))?;
+    Ok(reqwest::Response::from(response))
+}
@@ -0,0 +1,210 @@
+use chrono::{DateTime, Duration, Utc};
+use tracing::info;
+use crate::models::{BreakerSample, BreakerState, BreakerTransition, CircuitBreaker, HealthStatus};
+
+// Number of recent outcomes the failure rate is computed over
+const WINDOW_SIZE: usize = 20;
+// Outcomes required before the breaker may trip
+const MIN_SAMPLES: usize = 5;
+const FAILURE_RATE_THRESHOLD: f32 = 0.5;
+// Calls slower than this count against the agent like failures
+const SLOW_CALL_MS: u32 = 30_000;
+const OPEN_COOLDOWN_SECS: i64 = 30;
+// Consecutive successful probes needed to close a half-open breaker
+const PROBES_TO_CLOSE: u32 = 2;
+const MAX_TRANSITIONS: usize = 10;
+
+impl CircuitBreaker {
+    /// Whether the scheduler may send this agent a task right now.
+    pub fn admits(&self, now: DateTime<Utc>) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => self.cooldown_elapsed(now),
+            BreakerState::HalfOpen => self.probes_in_flight == 0,
+        }
+    }
+
+    /// Note that a task was dispatched; an expired open breaker moves to half-open here.
+    pub fn on_dispatch(&mut self, now: DateTime<Utc>) {
+        if self.state == BreakerState::Open && self.cooldown_elapsed(now) {
+            self.transition(BreakerState::HalfOpen, now, "cooldown elapsed, probing");
+        }
+        if self.state == BreakerState::HalfOpen {
+            self.probes_in_flight += 1;
+        }
+    }
+
+    pub fn record(&mut self, success: bool, latency_ms: u32, now: DateTime<Utc>) {
+        let healthy = success && latency_ms <= SLOW_CALL_MS;
+
+        self.window.push(BreakerSample { success: healthy, latency_ms, at: now });
+        if self.window.len() > WINDOW_SIZE {
+            let excess = self.window.len() - WINDOW_SIZE;
+            self.window.drain(..excess);
+        }
+
+        // Once the cooldown is over the outcome is a probe, whether or not it was dispatched as one
+        if self.state == BreakerState::Open && self.cooldown_elapsed(now) {
+            self.transition(BreakerState::HalfOpen, now, "cooldown elapsed, probing");
+        }
+
+        match self.state {
+            BreakerState::HalfOpen => {
+                self.probes_in_flight = self.probes_in_flight.saturating_sub(1);
+                if healthy {
+                    self.probe_successes += 1;
+                    if self.probe_successes >= PROBES_TO_CLOSE {
+                        // Start the closed period from a clean window
+                        self.window.clear();
+                        self.transition(BreakerState::Closed, now, "probes succeeded");
+                    }
+                } else {
+                    self.transition(BreakerState::Open, now, "probe failed");
+                }
+            }
+            BreakerState::Closed => {
+                if self.window.len() >= MIN_SAMPLES && self.failure_rate() >= FAILURE_RATE_THRESHOLD {
+                    let reason = format!("failure rate {:.0}% over last {} calls", self.failure_rate() * 100.0, self.window.len());
+                    self.transition(BreakerState::Open, now, &reason);
+                }
+            }
+            // Still cooling down; the outcome only joins the window
+            BreakerState::Open => {}
+        }
+    }
+
+    /// Failure rate over the sliding window, not the agent's lifetime.
+    pub fn failure_rate(&self) -> f32 {
+        if self.window.is_empty() {
+            return 0.0;
+        }
+        let failures = self.window.iter().filter(|s| !s.success).count();
+        failures as f32 / self.window.len() as f32
+    }
+
+    pub fn health_status(&self) -> HealthStatus {
+        match self.state {
+            BreakerState::Open => HealthStatus::Unhealthy,
+            BreakerState::HalfOpen => HealthStatus::Degraded,
+            BreakerState::Closed if self.failure_rate() > 0.1 => HealthStatus::Degraded,
+            BreakerState::Closed => HealthStatus::Healthy,
+        }
+    }
+
+    fn cooldown_elapsed(&self, now: DateTime<Utc>) -> bool {
+        self.opened_at
+            .is_none_or(|opened| now - opened >= Duration::seconds(OPEN_COOLDOWN_SECS))
+    }
+
+    fn transition(&mut self, to: BreakerState, now: DateTime<Utc>, reason: &str) {
+        if self.state == to {
+            return;
+        }
+        info!("Circuit breaker {:?} -> {:?}: {}", self.state, to, reason);
+
+        self.transitions.push(BreakerTransition {
+            from: self.state.clone(),
+            to: to.clone(),
+            at: now,
+            reason: reason.to_string(),
+        });
+        if self.transitions.len() > MAX_TRANSITIONS {
+            self.transitions.remove(0);
+        }
+
+        match to {
+            BreakerState::Open => {
+                self.opened_at = Some(now);
+                self.probes_in_flight = 0;
+            }
+            BreakerState::HalfOpen => {
+                self.probe_successes = 0;
+                self.probes_in_flight = 0;
+            }
+            BreakerState::Closed => {
+                self.opened_at = None;
+                self.probe_successes = 0;
+            }
+        }
+        self.state = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail_until_open(breaker: &mut CircuitBreaker, now: DateTime<Utc>) {
+        for _ in 0..MIN_SAMPLES {
+            breaker.on_dispatch(now);
+            breaker.record(false, 100, now);
+        }
+        assert_eq!(breaker.state, BreakerState::Open);
+    }
+
+    #[test]
+    fn closed_opens_on_failure_rate_then_recovers_through_half_open() {
+        let start = Utc::now();
+        let mut breaker = CircuitBreaker::default();
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert!(breaker.admits(start));
+
+        fail_until_open(&mut breaker, start);
+        assert!(!breaker.admits(start));
+
+        let later = start + Duration::seconds(OPEN_COOLDOWN_SECS);
+        assert!(breaker.admits(later));
+        breaker.on_dispatch(later);
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        assert!(!breaker.admits(later), "one probe at a time");
+
+        breaker.record(true, 100, later);
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        breaker.on_dispatch(later);
+        breaker.record(true, 100, later);
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert!(breaker.window.is_empty());
+        assert_eq!(breaker.transitions.len(), 3);
+    }
+
+    #[test]
+    fn outcome_after_cooldown_probes_without_dispatch() {
+        let start = Utc::now();
+        let mut breaker = CircuitBreaker::default();
+        fail_until_open(&mut breaker, start);
+
+        // Too early: stays open
+        breaker.record(true, 100, start + Duration::seconds(1));
+        assert_eq!(breaker.state, BreakerState::Open);
+
+        let later = start + Duration::seconds(OPEN_COOLDOWN_SECS);
+        breaker.record(true, 100, later);
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        breaker.record(true, 100, later);
+        assert_eq!(breaker.state, BreakerState::Closed);
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let start = Utc::now();
+        let mut breaker = CircuitBreaker::default();
+        fail_until_open(&mut breaker, start);
+
+        let later = start + Duration::seconds(OPEN_COOLDOWN_SECS);
+        breaker.on_dispatch(later);
+        breaker.record(false, 100, later);
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.opened_at, Some(later));
+        assert!(!breaker.admits(later));
+    }
+
+    #[test]
+    fn slow_calls_count_as_failures() {
+        let now = Utc::now();
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..MIN_SAMPLES {
+            breaker.record(true, SLOW_CALL_MS + 1, now);
+        }
+        assert_eq!(breaker.state, BreakerState::Open);
+    }
+}
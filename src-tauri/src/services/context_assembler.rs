@@ -0,0 +1,276 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use crate::utils::AppError;
+use super::tokens::estimate_tokens;
+
+// Assumed for models missing from the model registry
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 8_192;
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 4_000;
+// The completion reserve shrinks to this before a prompt is refused
+const MIN_OUTPUT_TOKENS: u32 = 256;
+// Upstream outputs that would be cut shorter than this are dropped instead
+const MIN_EXCERPT_TOKENS: u32 = 128;
+
+/// Token limits that apply to one call.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextBudget {
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    // Smallest of the task's and agent's token limits; None when limits are ignored
+    pub token_limit: Option<u32>,
+}
+
+impl ContextBudget {
+    /// Tokens available to prompt and completion together. The model's window always
+    /// applies; task and agent limits can only narrow it.
+    pub fn limit(&self) -> u32 {
+        let window = self.context_window.unwrap_or(DEFAULT_CONTEXT_WINDOW);
+        self.token_limit.map_or(window, |limit| limit.min(window))
+    }
+
+    fn max_output(&self) -> u32 {
+        self.max_output_tokens.unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS)
+    }
+
+    // Room kept for the completion while the prompt is packed: up to half the limit
+    fn output_reserve(&self) -> u32 {
+        let limit = self.limit();
+        self.max_output().min(limit / 2).max(MIN_OUTPUT_TOKENS.min(limit))
+    }
+}
+
+/// What the assembler kept, cut and left out; stored on the task output.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContextReport {
+    pub limit: u32,
+    pub prompt_tokens: u32,
+    pub max_tokens: u32,
+    pub included: Vec<String>,
+    pub truncated: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssembledContext {
+    // User message: the task input followed by the upstream outputs that fit
+    pub user: String,
+    pub max_tokens: u32,
+    pub report: ContextReport,
+}
+
+struct Upstream {
+    task_id: String,
+    text: String,
+    tokens: u32,
+}
+
+fn render_upstream(model: &str, output: &Value) -> Upstream {
+    let task_id = output["task_id"].as_str().unwrap_or("unknown").to_string();
+    let capability = output["capability"].as_str().unwrap_or("text");
+    let body = match output["output"]["content"].as_str() {
+        Some(content) => content.to_string(),
+        None => output["output"].to_string(),
+    };
+    let text = format!("### Output of task {} ({})\n{}", task_id, capability, body);
+    let tokens = estimate_tokens(model, &text);
+    Upstream { task_id, text, tokens }
+}
+
+/// Keep the head and tail of `text` within `max_tokens`, marking the cut.
+fn excerpt(model: &str, text: &str, tokens: u32, max_tokens: u32) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut keep = (chars.len() as f64 * max_tokens as f64 / tokens.max(1) as f64) as usize;
+    loop {
+        let head = keep * 2 / 3;
+        let tail = keep - head;
+        let omitted = tokens.saturating_sub(max_tokens);
+        let cut: String = chars[..head].iter().collect::<String>()
+            + &format!("\n[... about {} tokens omitted ...]\n", omitted)
+            + &chars[chars.len() - tail..].iter().collect::<String>();
+        // Character ratios only approximate tokens; shrink until the count agrees
+        if keep == 0 || estimate_tokens(model, &cut) <= max_tokens {
+            return cut;
+        }
+        keep = keep * 4 / 5;
+    }
+}
+
+/// Fit preamble, input and upstream outputs into the budget.
+///
+/// Preamble and input are always sent whole. Upstream outputs are ranked newest first:
+/// each is kept if it fits, the first that does not is cut to the remaining room, and
+/// older ones are dropped. Fails with `AppError::TokenLimitExceeded` only when preamble
+/// and input leave no room for a completion even with every upstream output dropped.
+pub fn assemble(model: &str, preamble: &str, input: &Value, upstream: &[Value], budget: &ContextBudget) -> Result<AssembledContext> {
+    let input = input.to_string();
+    let limit = budget.limit();
+    let fixed = estimate_tokens(model, &format!("{}\n{}", preamble, input));
+    let required = fixed + MIN_OUTPUT_TOKENS.min(limit);
+    if required > limit {
+        return Err(AppError::TokenLimitExceeded { used: required, limit }.into());
+    }
+
+    let mut report = ContextReport { limit, ..Default::default() };
+    let mut room = limit.saturating_sub(fixed + budget.output_reserve());
+    let mut kept: Vec<(usize, String)> = Vec::new();
+    let mut used = 0;
+    let rendered: Vec<Upstream> = upstream.iter().map(|output| render_upstream(model, output)).collect();
+    for (i, output) in rendered.iter().enumerate().rev() {
+        if output.tokens <= room {
+            room -= output.tokens;
+            used += output.tokens;
+            report.included.push(output.task_id.clone());
+            kept.push((i, output.text.clone()));
+        } else if room >= MIN_EXCERPT_TOKENS {
+            let cut = excerpt(model, &output.text, output.tokens, room);
+            let tokens = estimate_tokens(model, &cut);
+            used += tokens;
+            // Older outputs go; excerpting them too would leave the prompt all fragments
+            room = 0;
+            report.truncated.push(output.task_id.clone());
+            kept.push((i, cut));
+        } else {
+            report.dropped.push(output.task_id.clone());
+        }
+    }
+    kept.sort_by_key(|(i, _)| *i);
+
+    let user = if kept.is_empty() {
+        input
+    } else {
+        let sections: Vec<String> = kept.into_iter().map(|(_, text)| text).collect();
+        format!("{}\n\nOutputs of upstream tasks:\n\n{}", input, sections.join("\n\n"))
+    };
+    report.prompt_tokens = fixed + used;
+    // The completion may use whatever the prompt left over
+    report.max_tokens = budget.max_output().min(limit - report.prompt_tokens);
+
+    Ok(AssembledContext {
+        user,
+        max_tokens: report.max_tokens,
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MODEL: &str = "gpt-4o";
+
+    fn budget(context_window: u32, token_limit: Option<u32>) -> ContextBudget {
+        ContextBudget { context_window: Some(context_window), max_output_tokens: Some(1_000), token_limit }
+    }
+
+    fn upstream(task_id: &str, words: usize) -> Value {
+        json!({
+            "task_id": task_id,
+            "capability": "text",
+            "output": { "content": "lorem ipsum dolor sit amet ".repeat(words) },
+        })
+    }
+
+    fn assert_fits(context: &AssembledContext) {
+        let report = &context.report;
+        assert!(report.prompt_tokens + report.max_tokens <= report.limit);
+        assert_eq!(context.max_tokens, report.max_tokens);
+    }
+
+    #[test]
+    fn limit_is_the_window_narrowed_by_token_limit() {
+        assert_eq!(budget(8_000, None).limit(), 8_000);
+        assert_eq!(budget(8_000, Some(2_000)).limit(), 2_000);
+        assert_eq!(budget(8_000, Some(20_000)).limit(), 8_000);
+        let unknown = ContextBudget { context_window: None, max_output_tokens: None, token_limit: None };
+        assert_eq!(unknown.limit(), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn preamble_larger_than_budget_is_refused() {
+        let preamble = "Follow these instructions carefully. ".repeat(100);
+        let err = assemble(MODEL, &preamble, &json!("go"), &[], &budget(8_000, Some(200))).unwrap_err();
+        match err.downcast_ref::<AppError>() {
+            Some(AppError::TokenLimitExceeded { used, limit }) => {
+                assert_eq!(*limit, 200);
+                assert!(used > limit);
+            }
+            other => panic!("expected TokenLimitExceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn prompt_must_leave_room_for_a_minimal_completion() {
+        let preamble = "word ".repeat(300);
+        let fixed = estimate_tokens(MODEL, &format!("{}\n{}", preamble, json!("go")));
+        let limit = fixed + MIN_OUTPUT_TOKENS - 1;
+        assert!(assemble(MODEL, &preamble, &json!("go"), &[], &budget(limit, None)).is_err());
+        let context = assemble(MODEL, &preamble, &json!("go"), &[], &budget(limit + 1, None)).unwrap();
+        assert_eq!(context.max_tokens, MIN_OUTPUT_TOKENS);
+        assert_fits(&context);
+    }
+
+    #[test]
+    fn upstream_outputs_that_fit_are_kept_in_order() {
+        let outputs = [upstream("a", 5), upstream("b", 5)];
+        let context = assemble(MODEL, "Summarise.", &json!({"topic": "x"}), &outputs, &budget(8_000, None)).unwrap();
+        assert_eq!(context.report.included, vec!["b", "a"]);
+        assert!(context.report.truncated.is_empty() && context.report.dropped.is_empty());
+        let a = context.user.find("task a").unwrap();
+        let b = context.user.find("task b").unwrap();
+        assert!(a < b);
+        assert!(context.user.starts_with(r#"{"topic":"x"}"#));
+        assert_fits(&context);
+    }
+
+    #[test]
+    fn newest_output_is_excerpted_when_it_does_not_fit() {
+        let outputs = [upstream("old", 5), upstream("new", 2_000)];
+        let context = assemble(MODEL, "Summarise.", &json!("go"), &outputs, &budget(2_000, None)).unwrap();
+        assert_eq!(context.report.truncated, vec!["new"]);
+        assert!(context.user.contains("tokens omitted"));
+        assert!(context.user.contains("### Output of task new"));
+        assert_fits(&context);
+    }
+
+    #[test]
+    fn outputs_are_dropped_when_no_room_is_left_for_an_excerpt() {
+        let outputs = [upstream("old", 2_000), upstream("new", 2_000)];
+        let context = assemble(MODEL, "Summarise.", &json!("go"), &outputs, &budget(2_000, None)).unwrap();
+        assert_eq!(context.report.truncated, vec!["new"]);
+        assert_eq!(context.report.dropped, vec!["old"]);
+        assert!(!context.user.contains("task old"));
+        assert_fits(&context);
+    }
+
+    #[test]
+    fn tight_budget_drops_everything_without_underflow() {
+        let preamble = "word ".repeat(300);
+        let fixed = estimate_tokens(MODEL, &format!("{}\n{}", preamble, json!("go")));
+        let outputs = [upstream("a", 500)];
+        let context = assemble(MODEL, &preamble, &json!("go"), &outputs, &budget(fixed + MIN_OUTPUT_TOKENS + 50, None)).unwrap();
+        assert_eq!(context.report.dropped, vec!["a"]);
+        assert_eq!(context.user, json!("go").to_string());
+        assert_fits(&context);
+    }
+
+    #[test]
+    fn excerpt_stays_within_its_budget() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(200);
+        let tokens = estimate_tokens(MODEL, &text);
+        let cut = excerpt(MODEL, &text, tokens, 150);
+        assert!(estimate_tokens(MODEL, &cut) <= 150);
+        assert!(cut.starts_with("The quick"));
+        assert!(cut.trim_end().ends_with("lazy dog."));
+        assert!(cut.contains(&format!("about {} tokens omitted", tokens - 150)));
+    }
+
+    #[test]
+    fn excerpt_handles_multibyte_text() {
+        let text = "日本語のテキストです。".repeat(100);
+        let tokens = estimate_tokens(MODEL, &text);
+        let cut = excerpt(MODEL, &text, tokens, 40);
+        assert!(estimate_tokens(MODEL, &cut) <= 40);
+    }
+}
@@ -4,6 +4,11 @@ use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use chrono::{DateTime, Utc};
+use tracing::warn;
+use crate::storage::StorageService;
+
+// Each entry is stored as projects/<project_id>/context_<entry_id>.json
+const ENTRY_FILE_PREFIX: &str = "context_";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContextEntry {
@@ -16,7 +21,9 @@ pub struct ContextEntry {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub references: Vec<String>, // IDs of other context entries this depends on
-    pub ttl_seconds: Option<u64>, // Time to live in cache
+    // Time the entry stays in memory. With storage it is then evicted and reloaded on
+    // demand; without storage eviction is deletion
+    pub ttl_seconds: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,9 +40,14 @@ pub enum ContextType {
 }
 
 pub struct ContextPool {
-    entries: Arc<RwLock<HashMap<String, ContextEntry>>>,
+    entries: Arc<RwLock<HashMap<String, ContextEntry>>>, // Cached entries
     project_contexts: Arc<RwLock<HashMap<String, Vec<String>>>>, // Project ID -> Context IDs
     task_contexts: Arc<RwLock<HashMap<String, Vec<String>>>>, // Task ID -> Context IDs
+    // When each cached entry entered memory; ttl_seconds counts from here
+    cached_at: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+    // Entries evicted from memory but still on disk: Context ID -> Project ID
+    evicted: Arc<RwLock<HashMap<String, String>>>,
+    storage: Option<Arc<StorageService>>,
 }
 
 impl ContextPool {
@@ -44,16 +56,92 @@ impl ContextPool {
             entries: Arc::new(RwLock::new(HashMap::new())),
             project_contexts: Arc::new(RwLock::new(HashMap::new())),
             task_contexts: Arc::new(RwLock::new(HashMap::new())),
+            cached_at: Arc::new(RwLock::new(HashMap::new())),
+            evicted: Arc::new(RwLock::new(HashMap::new())),
+            storage: None,
         }
     }
     
+    /// A pool backed by per-project files, with the indexes rebuilt from what is on disk.
+    pub fn with_storage(storage: Arc<StorageService>) -> Self {
+        let pool = Self {
+            storage: Some(storage),
+            ..Self::new()
+        };
+        pool.load();
+        pool
+    }
+    
+    fn entry_file(id: &str) -> String {
+        format!("{}{}.json", ENTRY_FILE_PREFIX, id)
+    }
+    
+    /// Rebuild the project and task indexes from every stored entry. Entries are only
+    /// indexed here; their content is read back on first use.
+    fn load(&self) {
+        let Some(storage) = &self.storage else { return };
+        let project_ids = match storage.list_project_dirs() {
+            Ok(ids) => ids,
+            Err(e) => {
+                warn!("Failed to list projects for context: {}", e);
+                return;
+            }
+        };
+        
+        for project_id in project_ids {
+            let mut loaded: Vec<ContextEntry> = storage
+                .list_project_files(&project_id, ENTRY_FILE_PREFIX)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|file| file.ends_with(".json"))
+                .filter_map(|file| match self.read_entry(&project_id, &file) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Skipping unreadable context file {}/{}: {}", project_id, file, e);
+                        None
+                    }
+                })
+                .collect();
+            // Keep insertion order, which context chains and prompts rely on
+            loaded.sort_by_key(|entry| entry.created_at);
+            
+            let mut project_contexts = self.project_contexts.write();
+            let mut task_contexts = self.task_contexts.write();
+            let mut evicted = self.evicted.write();
+            for entry in loaded {
+                project_contexts.entry(entry.project_id.clone()).or_default().push(entry.id.clone());
+                task_contexts.entry(entry.task_id.clone()).or_default().push(entry.id.clone());
+                evicted.insert(entry.id, entry.project_id);
+            }
+        }
+    }
+    
+    fn read_entry(&self, project_id: &str, file: &str) -> anyhow::Result<ContextEntry> {
+        let storage = self.storage.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Context pool has no storage"))?;
+        Ok(serde_json::from_value(storage.load_project_data(project_id, file)?)?)
+    }
+    
+    fn persist(&self, entry: &ContextEntry) -> anyhow::Result<()> {
+        if let Some(storage) = &self.storage {
+            storage.save_project_data(&entry.project_id, &Self::entry_file(&entry.id), &serde_json::to_value(entry)?)?;
+        }
+        Ok(())
+    }
+    
+    fn cache(&self, entry: ContextEntry) {
+        self.cached_at.write().insert(entry.id.clone(), Utc::now());
+        self.entries.write().insert(entry.id.clone(), entry);
+    }
+    
     pub fn add_context(&self, entry: ContextEntry) -> anyhow::Result<()> {
         let id = entry.id.clone();
         let project_id = entry.project_id.clone();
         let task_id = entry.task_id.clone();
         
-        // Add to main entries
-        self.entries.write().insert(id.clone(), entry);
+        // Write through before indexing so a failed write leaves no phantom entry
+        self.persist(&entry)?;
+        self.cache(entry);
         
         // Add to project index
         self.project_contexts
@@ -72,56 +160,49 @@ impl ContextPool {
         Ok(())
     }
     
+    /// A cached entry, or an evicted one read back from disk into the cache.
     pub fn get_context(&self, id: &str) -> Option<ContextEntry> {
-        self.entries.read().get(id).cloned()
+        if let Some(entry) = self.entries.read().get(id) {
+            return Some(entry.clone());
+        }
+        let project_id = self.evicted.read().get(id).cloned()?;
+        match self.read_entry(&project_id, &Self::entry_file(id)) {
+            Ok(entry) => {
+                self.evicted.write().remove(id);
+                self.cache(entry.clone());
+                Some(entry)
+            }
+            Err(e) => {
+                warn!("Failed to reload context {} of project {}: {}", id, project_id, e);
+                None
+            }
+        }
     }
     
     pub fn get_project_context(&self, project_id: &str) -> Vec<ContextEntry> {
-        let project_contexts = self.project_contexts.read();
-        let entries = self.entries.read();
-        
-        project_contexts
-            .get(project_id)
-            .map(|ids| {
-                ids.iter()
-                    .filter_map(|id| entries.get(id).cloned())
-                    .collect()
-            })
-            .unwrap_or_default()
+        let ids = self.project_contexts.read().get(project_id).cloned().unwrap_or_default();
+        ids.iter().filter_map(|id| self.get_context(id)).collect()
     }
     
     pub fn get_task_context(&self, task_id: &str) -> Vec<ContextEntry> {
-        let task_contexts = self.task_contexts.read();
-        let entries = self.entries.read();
-        
-        task_contexts
-            .get(task_id)
-            .map(|ids| {
-                ids.iter()
-                    .filter_map(|id| entries.get(id).cloned())
-                    .collect()
-            })
-            .unwrap_or_default()
+        let ids = self.task_contexts.read().get(task_id).cloned().unwrap_or_default();
+        ids.iter().filter_map(|id| self.get_context(id)).collect()
     }
     
     pub fn get_context_chain(&self, task_id: &str, max_depth: usize) -> Vec<ContextEntry> {
         let mut result = Vec::new();
         let mut visited = std::collections::HashSet::new();
-        let entries = self.entries.read();
         
         // Start with direct task context
-        let task_contexts = self.task_contexts.read();
-        if let Some(context_ids) = task_contexts.get(task_id) {
-            for id in context_ids {
-                self.collect_context_recursive(
-                    &entries,
-                    id,
-                    &mut result,
-                    &mut visited,
-                    0,
-                    max_depth,
-                );
-            }
+        let context_ids = self.task_contexts.read().get(task_id).cloned().unwrap_or_default();
+        for id in &context_ids {
+            self.collect_context_recursive(
+                id,
+                &mut result,
+                &mut visited,
+                0,
+                max_depth,
+            );
         }
         
         result
@@ -129,7 +210,6 @@ impl ContextPool {
     
     fn collect_context_recursive(
         &self,
-        entries: &HashMap<String, ContextEntry>,
         id: &str,
         result: &mut Vec<ContextEntry>,
         visited: &mut std::collections::HashSet<String>,
@@ -142,11 +222,10 @@ impl ContextPool {
         
         visited.insert(id.to_string());
         
-        if let Some(entry) = entries.get(id) {
+        if let Some(entry) = self.get_context(id) {
             // Add referenced contexts first (depth-first)
             for ref_id in &entry.references {
                 self.collect_context_recursive(
-                    entries,
                     ref_id,
                     result,
                     visited,
@@ -156,77 +235,99 @@ impl ContextPool {
             }
             
             // Then add this entry
-            result.push(entry.clone());
+            result.push(entry);
         }
     }
     
     pub fn update_context(&self, id: &str, content: Value) -> anyhow::Result<()> {
-        let mut entries = self.entries.write();
-        if let Some(entry) = entries.get_mut(id) {
-            entry.content = content;
-            entry.updated_at = Utc::now();
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Context entry not found"))
-        }
+        let mut entry = self.get_context(id)
+            .ok_or_else(|| anyhow::anyhow!("Context entry not found"))?;
+        entry.content = content;
+        entry.updated_at = Utc::now();
+        self.persist(&entry)?;
+        self.entries.write().insert(entry.id.clone(), entry);
+        Ok(())
     }
     
+    /// Delete an entry from memory and disk. Use `evict_context` to only free memory.
     pub fn remove_context(&self, id: &str) -> anyhow::Result<()> {
-        let mut entries = self.entries.write();
-        if let Some(entry) = entries.remove(id) {
-            // Remove from project index
-            let mut project_contexts = self.project_contexts.write();
-            if let Some(project_ids) = project_contexts.get_mut(&entry.project_id) {
-                project_ids.retain(|pid| pid != id);
-            }
-            
-            // Remove from task index
-            let mut task_contexts = self.task_contexts.write();
-            if let Some(task_ids) = task_contexts.get_mut(&entry.task_id) {
-                task_ids.retain(|tid| tid != id);
-            }
-            
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Context entry not found"))
-        }
+        let project_id = self.entries.read().get(id).map(|entry| entry.project_id.clone())
+            .or_else(|| self.evicted.read().get(id).cloned())
+            .ok_or_else(|| anyhow::anyhow!("Context entry not found"))?;
+        self.delete_entry(&project_id, id)
     }
     
+    /// Delete every entry of a project from memory and disk.
     pub fn clear_project_context(&self, project_id: &str) {
-        let mut project_contexts = self.project_contexts.write();
-        if let Some(context_ids) = project_contexts.remove(project_id) {
-            let mut entries = self.entries.write();
-            let mut task_contexts = self.task_contexts.write();
-            
-            for id in context_ids {
-                if let Some(entry) = entries.remove(&id) {
-                    // Also remove from task index
-                    if let Some(task_ids) = task_contexts.get_mut(&entry.task_id) {
-                        task_ids.retain(|tid| tid != &id);
-                    }
-                }
+        let context_ids = self.project_contexts.read().get(project_id).cloned().unwrap_or_default();
+        for id in context_ids {
+            if let Err(e) = self.delete_entry(project_id, &id) {
+                warn!("Failed to delete context {} of project {}: {}", id, project_id, e);
             }
         }
+        self.project_contexts.write().remove(project_id);
     }
     
-    pub fn cleanup_expired(&self) {
-        let now = Utc::now();
-        let mut entries = self.entries.write();
-        let mut expired_ids = Vec::new();
+    /// Delete an entry's file and drop it from the cache and indexes. Evicted entries are
+    /// not read back, so this also clears entries whose file no longer parses.
+    fn delete_entry(&self, project_id: &str, id: &str) -> anyhow::Result<()> {
+        if let Some(storage) = &self.storage {
+            storage.delete_project_data(project_id, &Self::entry_file(id))?;
+        }
+        self.entries.write().remove(id);
+        self.cached_at.write().remove(id);
+        self.evicted.write().remove(id);
         
-        for (id, entry) in entries.iter() {
-            if let Some(ttl) = entry.ttl_seconds {
-                let age = (now - entry.created_at).num_seconds() as u64;
-                if age > ttl {
-                    expired_ids.push(id.clone());
-                }
-            }
+        if let Some(project_ids) = self.project_contexts.write().get_mut(project_id) {
+            project_ids.retain(|pid| pid != id);
         }
+        // The task of an evicted entry is only on disk, so look in every task's list
+        self.task_contexts.write().retain(|_, task_ids| {
+            task_ids.retain(|tid| tid != id);
+            !task_ids.is_empty()
+        });
         
-        drop(entries);
+        Ok(())
+    }
+    
+    /// Drop an entry from memory, keeping it on disk and in the indexes. Without storage
+    /// there is nothing to reload it from, so it is removed.
+    pub fn evict_context(&self, id: &str) {
+        if self.storage.is_none() {
+            let _ = self.remove_context(id);
+            return;
+        }
+        if let Some(entry) = self.entries.write().remove(id) {
+            self.cached_at.write().remove(id);
+            self.evicted.write().insert(entry.id, entry.project_id);
+        }
+    }
+    
+    /// Free the memory held by a project's entries; they reload on next use.
+    pub fn evict_project_context(&self, project_id: &str) {
+        let context_ids = self.project_contexts.read().get(project_id).cloned().unwrap_or_default();
+        for id in context_ids {
+            self.evict_context(&id);
+        }
+    }
+    
+    /// Evict entries that have been in memory longer than their ttl.
+    pub fn cleanup_expired(&self) {
+        let now = Utc::now();
+        let expired_ids: Vec<String> = {
+            let entries = self.entries.read();
+            let cached_at = self.cached_at.read();
+            entries.iter()
+                .filter(|(id, entry)| match (entry.ttl_seconds, cached_at.get(*id)) {
+                    (Some(ttl), Some(cached)) => (now - *cached).num_seconds() as u64 > ttl,
+                    _ => false,
+                })
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
         
         for id in expired_ids {
-            let _ = self.remove_context(&id);
+            self.evict_context(&id);
         }
     }
     
@@ -244,7 +345,8 @@ impl ContextPool {
         }
         
         ContextPoolStats {
-            total_entries: entries.len(),
+            total_entries: entries.len() + self.evicted.read().len(),
+            cached_entries: entries.len(),
             total_projects: project_contexts.len(),
             total_tasks: task_contexts.len(),
             type_distribution: type_counts,
@@ -256,6 +358,8 @@ impl ContextPool {
 #[derive(Debug, Clone, Serialize)]
 pub struct ContextPoolStats {
     pub total_entries: usize,
+    // Entries in memory; type_distribution and total_size_bytes cover only these
+    pub cached_entries: usize,
     pub total_projects: usize,
     pub total_tasks: usize,
     pub type_distribution: HashMap<String, usize>,
@@ -266,4 +370,90 @@ impl Default for ContextPool {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct TempStorage(std::path::PathBuf);
+
+    impl Drop for TempStorage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_storage() -> (TempStorage, Arc<StorageService>) {
+        let dir = std::env::temp_dir().join(format!("context_pool_{}", uuid::Uuid::new_v4()));
+        let storage = Arc::new(StorageService::at(dir.clone()).unwrap());
+        (TempStorage(dir), storage)
+    }
+
+    fn entry(id: &str, task_id: &str, offset_secs: i64) -> ContextEntry {
+        let created_at = Utc::now() + chrono::Duration::seconds(offset_secs);
+        ContextEntry {
+            id: id.to_string(),
+            project_id: "project".to_string(),
+            task_id: task_id.to_string(),
+            content_type: ContextType::TaskOutput,
+            content: json!({ "content": id }),
+            metadata: HashMap::new(),
+            created_at,
+            updated_at: created_at,
+            references: Vec::new(),
+            ttl_seconds: None,
+        }
+    }
+
+    fn ids(entries: &[ContextEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn entries_survive_restart_and_eviction_in_order() {
+        let (_dir, storage) = temp_storage();
+        let pool = ContextPool::with_storage(Arc::clone(&storage));
+        pool.add_context(entry("a", "t1", 0)).unwrap();
+        pool.add_context(entry("b", "t1", 1)).unwrap();
+        pool.add_context(entry("c", "t2", 2)).unwrap();
+
+        // A fresh pool only indexes what is on disk
+        let pool = ContextPool::with_storage(Arc::clone(&storage));
+        assert_eq!(pool.get_statistics().cached_entries, 0);
+        assert_eq!(ids(&pool.get_project_context("project")), ["a", "b", "c"]);
+        assert_eq!(ids(&pool.get_task_context("t1")), ["a", "b"]);
+
+        pool.evict_project_context("project");
+        let stats = pool.get_statistics();
+        assert_eq!((stats.total_entries, stats.cached_entries), (3, 0));
+
+        pool.update_context("b", json!({ "content": "b2" })).unwrap();
+        let pool = ContextPool::with_storage(storage);
+        assert_eq!(ids(&pool.get_project_context("project")), ["a", "b", "c"]);
+        assert_eq!(pool.get_context("b").unwrap().content, json!({ "content": "b2" }));
+    }
+
+    #[test]
+    fn evicted_and_unreadable_entries_are_deleted_without_reloading() {
+        let (_dir, storage) = temp_storage();
+        let pool = ContextPool::with_storage(Arc::clone(&storage));
+        pool.add_context(entry("a", "t1", 0)).unwrap();
+        pool.add_context(entry("b", "t2", 1)).unwrap();
+        pool.evict_project_context("project");
+
+        pool.remove_context("a").unwrap();
+        assert_eq!(pool.get_statistics().cached_entries, 0);
+        assert!(pool.get_task_context("t1").is_empty());
+
+        // An entry whose file no longer parses is still cleared
+        storage.save_project_data("project", &ContextPool::entry_file("b"), &json!("garbage")).unwrap();
+        pool.clear_project_context("project");
+        let stats = pool.get_statistics();
+        assert_eq!((stats.total_entries, stats.total_projects, stats.total_tasks), (0, 0, 0));
+        assert!(storage.list_project_files("project", ENTRY_FILE_PREFIX).unwrap().is_empty());
+
+        let pool = ContextPool::with_storage(storage);
+        assert!(pool.get_project_context("project").is_empty());
+    }
+}
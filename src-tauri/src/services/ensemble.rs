@@ -0,0 +1,317 @@
+use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::output_contract;
+
+/// One model asked to answer in an ensemble, usually resolved from an agent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnsembleMember {
+    #[serde(default)]
+    pub agent: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub token_limit: Option<u32>,
+}
+
+impl EnsembleMember {
+    pub fn label(&self) -> String {
+        match (&self.agent, &self.provider, &self.model) {
+            (Some(agent), _, _) => agent.clone(),
+            (None, Some(provider), Some(model)) => format!("{}/{}", provider, model),
+            (None, None, Some(model)) => model.clone(),
+            (None, Some(provider), None) => provider.clone(),
+            (None, None, None) => "default".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsembleStrategy {
+    // A judge model picks the best candidate
+    #[default]
+    Judge,
+    // Each structured field takes the value most candidates agree on
+    Vote,
+    // A model writes one answer from all candidates
+    Merge,
+}
+
+/// `metadata.ensemble` on a task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleSpec {
+    pub members: Vec<EnsembleMember>,
+    #[serde(default)]
+    pub strategy: EnsembleStrategy,
+    // Judge for `judge`, writer for `merge`; the first member when absent
+    #[serde(default)]
+    pub judge: Option<EnsembleMember>,
+    // Fields compared by `vote`; every top-level field when empty
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl EnsembleSpec {
+    /// Read `{"agents": [...], "strategy", "judge", "fields"}`. Agents and the judge are
+    /// agent names or `{provider, model}` objects; names go through `resolve`.
+    pub fn from_metadata(value: &Value, resolve: impl Fn(&str) -> Option<EnsembleMember>) -> Option<Self> {
+        let member = |entry: &Value| match entry {
+            Value::String(name) => resolve(name),
+            Value::Object(_) => serde_json::from_value(entry.clone()).ok(),
+            _ => None,
+        };
+        let members: Vec<EnsembleMember> = value["agents"]
+            .as_array()
+            .or_else(|| value["members"].as_array())?
+            .iter()
+            .filter_map(member)
+            .collect();
+        if members.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            members,
+            strategy: serde_json::from_value(value["strategy"].clone()).unwrap_or_default(),
+            judge: member(&value["judge"]),
+            fields: value["fields"]
+                .as_array()
+                .map(|fields| fields.iter().filter_map(|f| f.as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn judge(&self) -> &EnsembleMember {
+        self.judge.as_ref().unwrap_or(&self.members[0])
+    }
+}
+
+/// Structured form of a candidate: the contract-validated JSON, else JSON found in the text.
+pub fn candidate_json(output: &Value) -> Option<Value> {
+    output.get("json").cloned().filter(|j| !j.is_null())
+        .or_else(|| output["content"].as_str().and_then(output_contract::extract_json))
+}
+
+pub fn judge_preamble(count: usize) -> String {
+    format!(
+        "You are reviewing {} candidate answers to the same task. Judge them against the task's \
+         instructions for correctness, completeness and fitness for purpose, and pick the single best one. \
+         Answer with its number and a short reason.",
+        count
+    )
+}
+
+pub fn judge_schema(count: usize) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "choice": {"type": "integer", "minimum": 1, "maximum": count},
+            "reason": {"type": "string"}
+        },
+        "required": ["choice", "reason"]
+    })
+}
+
+pub fn merge_preamble(preamble: &str, count: usize) -> String {
+    format!(
+        "{}\n\n{} models answered this task independently; their answers follow the request. \
+         Write the single best answer, combining their strengths and correcting their mistakes.",
+        preamble, count
+    )
+}
+
+/// Input for the judge or merge call: the original request and numbered candidates.
+pub fn review_input(preamble: &str, input: &Value, candidates: &[String]) -> Value {
+    let candidates: Vec<Value> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, content)| json!({"number": i + 1, "answer": content}))
+        .collect();
+    json!({
+        "task_instructions": preamble,
+        "task_input": input,
+        "candidates": candidates
+    })
+}
+
+/// Outcome of a field-wise majority vote.
+#[derive(Debug, Clone, Serialize)]
+pub struct Vote {
+    pub result: Value,
+    // Per field: the winning value's share of the votes
+    pub tally: Value,
+    // Candidate agreeing with the most winning values
+    pub best: usize,
+}
+
+/// Majority vote per field over candidates that produced JSON objects. Ties go to the
+/// value seen first, so member order breaks them. `None` when no candidate has an object.
+pub fn vote(candidates: &[Option<Value>], fields: &[String]) -> Option<Vote> {
+    let objects: Vec<(usize, &serde_json::Map<String, Value>)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.as_ref().and_then(|v| v.as_object()).map(|o| (i, o)))
+        .collect();
+    if objects.is_empty() {
+        return None;
+    }
+
+    let fields: Vec<String> = if fields.is_empty() {
+        let mut seen = BTreeSet::new();
+        objects
+            .iter()
+            .flat_map(|(_, o)| o.keys())
+            .filter(|k| seen.insert(k.to_string()))
+            .cloned()
+            .collect()
+    } else {
+        fields.to_vec()
+    };
+
+    let mut result = serde_json::Map::new();
+    let mut tally = serde_json::Map::new();
+    let mut agreement = vec![0usize; candidates.len()];
+    for field in &fields {
+        // (value, voters) in first-seen order
+        let mut counts: Vec<(Value, Vec<usize>)> = Vec::new();
+        for (i, object) in &objects {
+            let Some(value) = object.get(field) else { continue };
+            match counts.iter_mut().find(|(v, _)| v == value) {
+                Some((_, voters)) => voters.push(*i),
+                None => counts.push((value.clone(), vec![*i])),
+            }
+        }
+        let Some((value, voters)) = counts.iter().fold(None::<&(Value, Vec<usize>)>, |best, entry| match best {
+            Some(best) if best.1.len() >= entry.1.len() => Some(best),
+            _ => Some(entry),
+        }) else {
+            continue;
+        };
+        for voter in voters {
+            agreement[*voter] += 1;
+        }
+        let votes: usize = counts.iter().map(|(_, v)| v.len()).sum();
+        tally.insert(field.clone(), json!({"votes": voters.len(), "of": votes}));
+        result.insert(field.clone(), value.clone());
+    }
+
+    let best = (0..candidates.len())
+        .filter(|i| candidates[*i].is_some())
+        .max_by(|a, b| agreement[*a].cmp(&agreement[*b]).then(b.cmp(a)))
+        .unwrap_or(0);
+    Some(Vote {
+        result: Value::Object(result),
+        tally: Value::Object(tally),
+        best,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn majority_wins_each_field() {
+        let candidates = vec![
+            Some(json!({"answer": 4, "unit": "m"})),
+            Some(json!({"answer": 5, "unit": "m"})),
+            Some(json!({"answer": 4, "unit": "cm"})),
+        ];
+        let vote = vote(&candidates, &[]).unwrap();
+        assert_eq!(vote.result, json!({"answer": 4, "unit": "m"}));
+        assert_eq!(vote.tally["answer"], json!({"votes": 2, "of": 3}));
+        assert_eq!(vote.tally["unit"], json!({"votes": 2, "of": 3}));
+        assert_eq!(vote.best, 0);
+    }
+
+    #[test]
+    fn tie_goes_to_the_first_member() {
+        let candidates = vec![Some(json!({"answer": "yes"})), Some(json!({"answer": "no"}))];
+        let vote = vote(&candidates, &[]).unwrap();
+        assert_eq!(vote.result, json!({"answer": "yes"}));
+        assert_eq!(vote.tally["answer"], json!({"votes": 1, "of": 2}));
+        assert_eq!(vote.best, 0);
+    }
+
+    #[test]
+    fn tie_between_later_values_still_follows_member_order() {
+        let candidates = vec![
+            None,
+            Some(json!({"answer": "b"})),
+            Some(json!({"answer": "a"})),
+        ];
+        let vote = vote(&candidates, &[]).unwrap();
+        assert_eq!(vote.result, json!({"answer": "b"}));
+        assert_eq!(vote.best, 1);
+    }
+
+    #[test]
+    fn best_is_the_candidate_agreeing_with_most_winners() {
+        let candidates = vec![
+            Some(json!({"a": 1, "b": 1, "c": 1})),
+            Some(json!({"a": 2, "b": 2, "c": 2})),
+            Some(json!({"a": 2, "b": 2, "c": 3})),
+        ];
+        let vote = vote(&candidates, &[]).unwrap();
+        assert_eq!(vote.result, json!({"a": 2, "b": 2, "c": 1}));
+        assert_eq!(vote.best, 1);
+    }
+
+    #[test]
+    fn only_listed_fields_are_compared() {
+        let candidates = vec![
+            Some(json!({"answer": 1, "note": "x"})),
+            Some(json!({"answer": 1, "note": "y"})),
+        ];
+        let vote = vote(&candidates, &fields(&["answer"])).unwrap();
+        assert_eq!(vote.result, json!({"answer": 1}));
+        assert!(vote.tally.get("note").is_none());
+    }
+
+    #[test]
+    fn missing_fields_do_not_vote() {
+        let candidates = vec![
+            Some(json!({"answer": 1})),
+            Some(json!({"answer": 2, "extra": true})),
+            Some(json!({"answer": 2})),
+        ];
+        let vote = vote(&candidates, &fields(&["answer", "extra", "absent"])).unwrap();
+        assert_eq!(vote.result, json!({"answer": 2, "extra": true}));
+        assert_eq!(vote.tally["extra"], json!({"votes": 1, "of": 1}));
+        assert!(vote.result.get("absent").is_none());
+        assert_eq!(vote.best, 1);
+    }
+
+    #[test]
+    fn candidates_without_objects_are_ignored() {
+        assert!(vote(&[None, Some(json!([1, 2])), Some(json!("text"))], &[]).is_none());
+        let vote = vote(&[Some(json!("text")), Some(json!({"answer": 3}))], &[]).unwrap();
+        assert_eq!(vote.result, json!({"answer": 3}));
+        assert_eq!(vote.best, 1);
+    }
+
+    #[test]
+    fn spec_needs_two_members() {
+        let resolve = |name: &str| Some(EnsembleMember { agent: Some(name.to_string()), ..Default::default() });
+        assert!(EnsembleSpec::from_metadata(&json!({"agents": ["one"]}), resolve).is_none());
+
+        let spec = EnsembleSpec::from_metadata(
+            &json!({"agents": ["one", {"provider": "openai", "model": "gpt-4o"}], "strategy": "vote"}),
+            resolve,
+        )
+        .unwrap();
+        assert_eq!(spec.strategy, EnsembleStrategy::Vote);
+        assert_eq!(spec.members[1].label(), "openai/gpt-4o");
+        assert_eq!(spec.judge().label(), "one");
+    }
+}
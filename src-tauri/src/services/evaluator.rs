@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::ensemble::EnsembleMember;
+
+pub const DEFAULT_THRESHOLD: f64 = 0.7;
+pub const DEFAULT_MAX_REWORK: u32 = 1;
+// Points each criterion is scored out of
+const MAX_POINTS: u64 = 10;
+
+const DEFAULT_RUBRIC: &str = "Score each criterion from 0 to 10: 10 fully met, 7 met with minor gaps, \
+    4 partly met, 0 not met or not attempted. An empty, truncated or off-topic answer scores 0 on every criterion.";
+
+/// One acceptance criterion; `weight` sets its share of the overall score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Criterion {
+    pub criterion: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// `metadata.acceptance` on a task: criteria an evaluator model scores the output against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptanceSpec {
+    pub criteria: Vec<Criterion>,
+    // Scoring guidance for the evaluator; a 0-10 scale when absent
+    #[serde(default)]
+    pub rubric: Option<String>,
+    // Overall score, 0 to 1, the output must reach
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    // Rework rounds allowed before the task fails
+    #[serde(default)]
+    pub max_rework: Option<u32>,
+    // Model that scores the output; the task's own model when absent
+    #[serde(default)]
+    pub evaluator: Option<EnsembleMember>,
+}
+
+impl AcceptanceSpec {
+    /// Accepts a list of criteria, or an object with `criteria` and settings. Criteria are
+    /// strings or `{criterion, weight}` objects; the evaluator is an agent name resolved
+    /// through `resolve` or a `{provider, model}` object.
+    pub fn from_metadata(value: &Value, resolve: impl Fn(&str) -> Option<EnsembleMember>) -> Option<Self> {
+        let no_settings = Value::Null;
+        let (criteria, settings) = match value {
+            Value::Array(criteria) => (criteria, &no_settings),
+            Value::Object(_) => (value["criteria"].as_array()?, value),
+            _ => return None,
+        };
+        let criteria: Vec<Criterion> = criteria
+            .iter()
+            .filter_map(|entry| match entry {
+                Value::String(criterion) => Some(Criterion { criterion: criterion.clone(), weight: 1.0 }),
+                Value::Object(_) => serde_json::from_value(entry.clone()).ok(),
+                _ => None,
+            })
+            .filter(|c| !c.criterion.trim().is_empty() && c.weight > 0.0)
+            .collect();
+        if criteria.is_empty() {
+            return None;
+        }
+        Some(Self {
+            criteria,
+            rubric: settings["rubric"].as_str().map(|s| s.to_string()),
+            threshold: settings["threshold"].as_f64(),
+            max_rework: settings["max_rework"].as_u64().map(|n| n as u32),
+            evaluator: match &settings["evaluator"] {
+                Value::String(name) => resolve(name),
+                entry @ Value::Object(_) => serde_json::from_value(entry.clone()).ok(),
+                _ => None,
+            },
+        })
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or(DEFAULT_THRESHOLD).clamp(0.0, 1.0)
+    }
+
+    pub fn max_rework(&self) -> u32 {
+        self.max_rework.unwrap_or(DEFAULT_MAX_REWORK)
+    }
+
+    pub fn evaluator_preamble(&self) -> String {
+        let criteria: Vec<String> = self.criteria
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{}. {}", i + 1, c.criterion))
+            .collect();
+        format!(
+            "You are a strict reviewer checking a model's answer against acceptance criteria. \
+             Judge only the answer, in light of the task it was given.\n\nCriteria:\n{}\n\nRubric: {}\n\n\
+             For each criterion give its number, a score and a finding: what is missing or wrong, \
+             or an empty string when it is fully met.",
+            criteria.join("\n"),
+            self.rubric.as_deref().unwrap_or(DEFAULT_RUBRIC)
+        )
+    }
+
+    pub fn evaluator_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "scores": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "criterion": {"type": "integer", "minimum": 1, "maximum": self.criteria.len()},
+                            "score": {"type": "integer", "minimum": 0, "maximum": MAX_POINTS},
+                            "finding": {"type": "string"}
+                        },
+                        "required": ["criterion", "score", "finding"]
+                    }
+                }
+            },
+            "required": ["scores"]
+        })
+    }
+
+    /// Weighted score and findings from the evaluator's verdict. Criteria the evaluator
+    /// skipped score 0.
+    pub fn score(&self, verdict: &Value) -> Evaluation {
+        let scores = verdict["scores"].as_array().cloned().unwrap_or_default();
+        let total_weight: f64 = self.criteria.iter().map(|c| c.weight).sum();
+        let mut score = 0.0;
+        let mut criteria = Vec::with_capacity(self.criteria.len());
+        let mut findings = Vec::new();
+        for (i, criterion) in self.criteria.iter().enumerate() {
+            let entry = scores.iter().find(|s| s["criterion"].as_u64() == Some(i as u64 + 1));
+            let points = entry.and_then(|s| s["score"].as_u64()).unwrap_or(0).min(MAX_POINTS);
+            let finding = match entry {
+                Some(entry) => entry["finding"].as_str().unwrap_or_default().trim().to_string(),
+                None => "Not assessed by the evaluator".to_string(),
+            };
+            score += criterion.weight * points as f64 / MAX_POINTS as f64;
+            if !finding.is_empty() {
+                findings.push(format!("{}: {}", criterion.criterion, finding));
+            }
+            criteria.push(json!({
+                "criterion": criterion.criterion,
+                "weight": criterion.weight,
+                "score": points,
+                "finding": finding
+            }));
+        }
+        let score = score / total_weight;
+        Evaluation {
+            score,
+            threshold: self.threshold(),
+            passed: score >= self.threshold(),
+            findings,
+            criteria,
+            rework_rounds: 0,
+            history: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+}
+
+/// Input for the evaluator call: the task and the answer under review.
+pub fn evaluation_input(preamble: &str, input: &Value, answer: &str) -> Value {
+    json!({
+        "task_instructions": preamble,
+        "task_input": input,
+        "answer": answer
+    })
+}
+
+/// Preamble for a rework round: the task's own instructions plus what the evaluator found.
+pub fn rework_preamble(preamble: &str, evaluation: &Evaluation) -> String {
+    format!(
+        "{}\n\nA reviewer scored your previous answer {:.2} against a required {:.2}. Produce a complete new \
+         answer that fixes every finding below.\n\nFindings:\n- {}",
+        preamble,
+        evaluation.score,
+        evaluation.threshold,
+        evaluation.findings.join("\n- ")
+    )
+}
+
+/// Input for a rework round: the original input and the answer being reworked.
+pub fn rework_input(input: &Value, previous_answer: &str) -> Value {
+    json!({
+        "original_input": input,
+        "previous_answer": previous_answer
+    })
+}
+
+/// Outcome of the evaluator pass, stored on the task output and on the task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evaluation {
+    pub score: f64,
+    pub threshold: f64,
+    pub passed: bool,
+    pub findings: Vec<String>,
+    // Per criterion: weight, points and finding
+    pub criteria: Vec<Value>,
+    // Rework rounds run before this evaluation
+    pub rework_rounds: u32,
+    // Scores and findings of the answers that were reworked
+    #[serde(default)]
+    pub history: Vec<Value>,
+    // Evaluator passes and rejected answers, each with the model it ran on and its usage
+    #[serde(default)]
+    pub calls: Vec<Value>,
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tokio::time::Duration;
+use crate::state::AppState;
+
+// Share multiplier applied to the project the user is watching in the dashboard
+const FOREGROUND_BOOST: u32 = 4;
+
+// Slots handed out when the enabled agents offer fewer, so provider-only setups still overlap
+const MIN_SLOTS: usize = 4;
+
+// Waiting tasks look again this often even when no slot frees up, to pick up
+// foreground, weight and agent changes
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ProjectShare {
+    pub weight: u32,
+    pub max_concurrent: Option<usize>,
+}
+
+/// Read scheduling weight and concurrency cap from the project's `config_override`.
+///
+/// Recognised keys: `scheduler_weight` (default 1) and `max_concurrent_tasks`. The
+/// foreground project's weight is boosted.
+pub fn project_share(state: &AppState, project_id: &str) -> ProjectShare {
+    let foreground = state.foreground_project.read().clone();
+    let projects = state.projects.read();
+    let overrides = projects.get(project_id).and_then(|p| p.config_override.as_ref());
+
+    let mut weight = overrides
+        .and_then(|o| o.get("scheduler_weight"))
+        .and_then(|v| v.as_u64())
+        .map(|w| w.max(1) as u32)
+        .unwrap_or(1);
+    let max_concurrent = overrides
+        .and_then(|o| o.get("max_concurrent_tasks"))
+        .and_then(|v| v.as_u64())
+        .map(|c| c as usize);
+
+    if foreground.as_deref() == Some(project_id) {
+        weight = weight.saturating_mul(FOREGROUND_BOOST);
+    }
+
+    ProjectShare { weight, max_concurrent }
+}
+
+/// Hands out task slots across projects by weighted fair share.
+///
+/// When a slot frees up, the waiting project with the lowest running-tasks-to-weight
+/// ratio gets it; ties go to the project served least recently, which degrades to
+/// plain round-robin when all weights are equal. Projects at their cap wait.
+pub struct FairShare {
+    state: Arc<AppState>,
+    dispatch: Mutex<Dispatch>,
+    released: Notify,
+}
+
+#[derive(Default)]
+struct Dispatch {
+    running: HashMap<String, usize>,
+    waiting: HashMap<String, usize>,
+    last_served: HashMap<String, u64>,
+    seq: u64,
+}
+
+/// A project's claim on a task slot; dropping it gives the slot back, or withdraws
+/// the claim if it was still waiting.
+pub struct Slot {
+    fair_share: Arc<FairShare>,
+    project_id: String,
+    granted: bool,
+}
+
+impl FairShare {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            state,
+            dispatch: Mutex::new(Dispatch::default()),
+            released: Notify::new(),
+        }
+    }
+
+    /// Wait until fair share gives this project a task slot.
+    pub async fn acquire(self: &Arc<Self>, project_id: &str) -> Slot {
+        *self.dispatch.lock().waiting.entry(project_id.to_string()).or_default() += 1;
+        let mut slot = Slot {
+            fair_share: Arc::clone(self),
+            project_id: project_id.to_string(),
+            granted: false,
+        };
+
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            if self.try_grant(project_id) {
+                slot.granted = true;
+                return slot;
+            }
+            let _ = tokio::time::timeout(RECHECK_INTERVAL, released).await;
+        }
+    }
+
+    /// Task slots across all projects: what the enabled agents can run at once.
+    fn capacity(&self) -> usize {
+        self.state.agents.read()
+            .iter()
+            .filter(|a| a.enabled)
+            .map(|a| a.max_concurrent_tasks)
+            .sum::<usize>()
+            .max(MIN_SLOTS)
+    }
+
+    fn try_grant(&self, project_id: &str) -> bool {
+        let capacity = self.capacity();
+        let mut dispatch = self.dispatch.lock();
+        if dispatch.running.values().sum::<usize>() >= capacity {
+            return false;
+        }
+
+        let mut best: Option<(&str, f64, u64)> = None;
+        for project in dispatch.waiting.keys() {
+            let share = project_share(&self.state, project);
+            let load = dispatch.running.get(project).copied().unwrap_or(0);
+            if share.max_concurrent.is_some_and(|cap| load >= cap) {
+                continue;
+            }
+
+            let ratio = load as f64 / share.weight as f64;
+            let served = dispatch.last_served.get(project).copied().unwrap_or(0);
+            let better = match &best {
+                None => true,
+                Some((_, best_ratio, best_served)) => {
+                    ratio < *best_ratio || (ratio == *best_ratio && served < *best_served)
+                }
+            };
+            if better {
+                best = Some((project, ratio, served));
+            }
+        }
+        if best.map(|(project, _, _)| project) != Some(project_id) {
+            return false;
+        }
+
+        release(&mut dispatch.waiting, project_id);
+        *dispatch.running.entry(project_id.to_string()).or_default() += 1;
+        dispatch.seq += 1;
+        let seq = dispatch.seq;
+        dispatch.last_served.insert(project_id.to_string(), seq);
+        true
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        {
+            let mut dispatch = self.fair_share.dispatch.lock();
+            let counts = if self.granted { &mut dispatch.running } else { &mut dispatch.waiting };
+            release(counts, &self.project_id);
+        }
+        self.fair_share.released.notify_waiters();
+    }
+}
+
+fn release(counts: &mut HashMap<String, usize>, project_id: &str) {
+    if let Some(count) = counts.get_mut(project_id) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            counts.remove(project_id);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, Mutex as AsyncMutex, Semaphore};
+use tracing::{error, warn};
+use crate::models::{Agent, LocalProcessConfig};
+use super::agent_host::{AgentRequest, AgentResponse};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 600;
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+type PendingMap = Arc<Mutex<HashMap<String, oneshot::Sender<AgentResponse>>>>;
+
+struct RunningProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // Requests this process owes an answer; a restarted process gets a fresh map
+    pending: PendingMap,
+}
+
+#[derive(Default)]
+struct RestartState {
+    consecutive_crashes: u32,
+    last_spawn: Option<Instant>,
+}
+
+/// A long-lived local agent process speaking line-delimited JSON over stdio.
+///
+/// Each `AgentRequest` is written to stdin as one JSON line; the process answers
+/// with one `AgentResponse` line on stdout carrying the same `task_id`, so several
+/// requests may be in flight at once up to `max_concurrent_tasks`.
+pub struct LocalProcessAgent {
+    name: String,
+    config: LocalProcessConfig,
+    process: AsyncMutex<Option<RunningProcess>>,
+    permits: Arc<Semaphore>,
+    restarts: Mutex<RestartState>,
+}
+
+impl LocalProcessAgent {
+    pub fn new(agent: &Agent, config: LocalProcessConfig) -> Self {
+        Self {
+            name: agent.name.clone(),
+            config,
+            process: AsyncMutex::new(None),
+            permits: Arc::new(Semaphore::new(agent.max_concurrent_tasks.max(1))),
+            restarts: Mutex::new(RestartState::default()),
+        }
+    }
+
+    pub async fn execute(&self, request: AgentRequest) -> anyhow::Result<AgentResponse> {
+        let _permit = self.permits.acquire().await
+            .map_err(|e| anyhow::anyhow!("Local agent {} is shut down: {}", self.name, e))?;
+
+        let task_id = request.task_id.clone();
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        let (pending, rx) = self.send_request(&task_id, &line).await?;
+
+        let timeout = Duration::from_secs(self.config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(response)) => {
+                self.restarts.lock().consecutive_crashes = 0;
+                Ok(response)
+            }
+            Ok(Err(_)) => Err(anyhow::anyhow!("Local agent {} exited before answering task {}", self.name, task_id)),
+            Err(_) => {
+                pending.lock().remove(&task_id);
+                Err(anyhow::anyhow!("Local agent {} timed out on task {}", self.name, task_id))
+            }
+        }
+    }
+
+    pub fn config(&self) -> &LocalProcessConfig {
+        &self.config
+    }
+
+    /// Start the process if it is not running; the supervisor calls this so agents are
+    /// up before their first task and come back after a crash.
+    pub async fn ensure_running(&self) -> anyhow::Result<()> {
+        let mut process = self.process.lock().await;
+        self.ensure_spawned(&mut process).await
+    }
+
+    pub async fn shutdown(&self) {
+        if let Some(mut running) = self.process.lock().await.take() {
+            let _ = running.child.kill().await;
+        }
+        self.permits.close();
+    }
+
+    /// Write one request line, registering for its answer with the process it went to.
+    async fn send_request(&self, task_id: &str, line: &str) -> anyhow::Result<(PendingMap, oneshot::Receiver<AgentResponse>)> {
+        let mut process = self.process.lock().await;
+        self.ensure_spawned(&mut process).await?;
+
+        let running = process.as_mut().expect("process spawned above");
+        let (tx, rx) = oneshot::channel();
+        running.pending.lock().insert(task_id.to_string(), tx);
+        let written = match running.stdin.write_all(line.as_bytes()).await {
+            Ok(()) => running.stdin.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            running.pending.lock().remove(task_id);
+            // Broken pipe: drop the handle so the next request restarts it
+            *process = None;
+            return Err(anyhow::anyhow!("Failed to write to local agent {}: {}", self.name, e));
+        }
+        Ok((Arc::clone(&running.pending), rx))
+    }
+
+    async fn ensure_spawned(&self, process: &mut Option<RunningProcess>) -> anyhow::Result<()> {
+        let alive = match process.as_mut() {
+            Some(running) => matches!(running.child.try_wait(), Ok(None)),
+            None => false,
+        };
+        if !alive {
+            *process = Some(self.spawn().await?);
+        }
+        Ok(())
+    }
+
+    async fn spawn(&self) -> anyhow::Result<RunningProcess> {
+        // Exponential backoff between restarts after crashes
+        let delay = {
+            let mut restarts = self.restarts.lock();
+            if restarts.last_spawn.is_some() {
+                restarts.consecutive_crashes += 1;
+            }
+            match restarts.consecutive_crashes {
+                0 => Duration::ZERO,
+                n => MIN_RESTART_DELAY
+                    .saturating_mul(2u32.saturating_pow(n - 1))
+                    .min(MAX_RESTART_DELAY),
+            }
+        };
+        if !delay.is_zero() {
+            warn!("Restarting local agent {} in {:?}", self.name, delay);
+            tokio::time::sleep(delay).await;
+        }
+
+        let mut cmd = Command::new(&self.config.command);
+        cmd.args(&self.config.args)
+            .envs(&self.config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.config.working_dir {
+            cmd.current_dir(dir);
+        }
+
+        let mut child = cmd.spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start local agent {} ({}): {}", self.name, self.config.command, e))?;
+        self.restarts.lock().last_spawn = Some(Instant::now());
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("Local agent stdin unavailable"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("Local agent stdout unavailable"))?;
+
+        let pending: PendingMap = Arc::new(Mutex::new(HashMap::new()));
+        let reader_pending = Arc::clone(&pending);
+        let name = self.name.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) if line.trim().is_empty() => continue,
+                    Ok(Some(line)) => match serde_json::from_str::<AgentResponse>(&line) {
+                        Ok(response) => {
+                            if let Some(tx) = reader_pending.lock().remove(&response.task_id) {
+                                let _ = tx.send(response);
+                            }
+                        }
+                        Err(e) => warn!("Local agent {} sent invalid response: {}", name, e),
+                    },
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            // Process exited or stdout closed: fail everything still waiting
+            // Only this process's requests; a replacement keeps its own
+            error!("Local agent {} stopped; {} task(s) pending", name, reader_pending.lock().len());
+            reader_pending.lock().clear();
+        });
+
+        Ok(RunningProcess { child, stdin, pending })
+    }
+}
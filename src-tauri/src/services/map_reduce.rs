@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::tokens::estimate_tokens;
+
+pub const DEFAULT_PARALLELISM: usize = 3;
+// Chunks smaller than this cost more in repeated preamble than they save
+const MIN_CHUNK_TOKENS: u32 = 256;
+
+/// `metadata.chunking` on a task: run the preamble over token-bounded chunks of an
+/// oversized input, then merge the partial results.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkingSpec {
+    // Upstream task whose output is chunked; the task's own input when absent
+    #[serde(default)]
+    pub source: Option<String>,
+    // Tokens per chunk; derived from the context budget when absent
+    #[serde(default)]
+    pub chunk_tokens: Option<u32>,
+    // Chunks in flight at once
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    // Instructions for the merge step; a generic merge instruction follows the preamble otherwise
+    #[serde(default)]
+    pub reduce_preamble: Option<String>,
+}
+
+impl ChunkingSpec {
+    /// Accepts `true` for the defaults or an object of settings.
+    pub fn from_metadata(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(true) => Some(Self::default()),
+            Value::Object(_) => serde_json::from_value(value.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism.unwrap_or(DEFAULT_PARALLELISM).max(1)
+    }
+
+    /// Chunk size: the configured one, else what is left of half the prompt budget once
+    /// the preamble is counted.
+    pub fn chunk_tokens(&self, model: &str, preamble: &str, limit: u32) -> u32 {
+        self.chunk_tokens
+            .unwrap_or_else(|| (limit / 2).saturating_sub(estimate_tokens(model, preamble)))
+            .max(MIN_CHUNK_TOKENS)
+    }
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => match other["content"].as_str() {
+            Some(content) => content.to_string(),
+            None => other.to_string(),
+        },
+    }
+}
+
+/// Text to chunk: the named upstream output, or the task input.
+pub fn source_text(spec: &ChunkingSpec, input: &Value, upstream: &[Value]) -> Result<String> {
+    match &spec.source {
+        Some(task_id) => upstream
+            .iter()
+            .find(|o| o["task_id"].as_str() == Some(task_id.as_str()))
+            .map(|o| as_text(&o["output"]))
+            .ok_or_else(|| anyhow!("Chunking source {} is not an upstream output of this task", task_id)),
+        None => Ok(as_text(input)),
+    }
+}
+
+/// Input for one map step. When an upstream output is chunked the task's own input
+/// rides along so every chunk sees the request.
+pub fn map_input(spec: &ChunkingSpec, input: &Value, chunk: &str, index: usize, count: usize) -> Value {
+    let mut map_input = json!({
+        "chunk": index + 1,
+        "of": count,
+        "content": chunk
+    });
+    if spec.source.is_some() {
+        map_input["request"] = input.clone();
+    }
+    map_input
+}
+
+pub fn reduce_preamble(spec: &ChunkingSpec, preamble: &str, count: usize) -> String {
+    match &spec.reduce_preamble {
+        Some(reduce) => reduce.clone(),
+        None => format!(
+            "{}\n\nThe input was too large to process at once, so it was split into {} consecutive chunks \
+             and the instructions above were applied to each. Merge the partial results below into one \
+             complete result: remove repetition, reconcile overlaps and keep every distinct finding.",
+            preamble, count
+        ),
+    }
+}
+
+pub fn reduce_input(spec: &ChunkingSpec, input: &Value, partials: &[String]) -> Value {
+    let mut reduce_input = json!({ "partial_results": partials });
+    if spec.source.is_some() {
+        reduce_input["request"] = input.clone();
+    }
+    reduce_input
+}
+
+/// Group partial results so each group fits in `max_tokens`; a group of one cannot shrink further.
+pub fn group_partials(model: &str, partials: &[String], max_tokens: u32) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut tokens = 0;
+    for partial in partials {
+        let size = estimate_tokens(model, partial);
+        match groups.last_mut() {
+            Some(group) if tokens + size <= max_tokens => group.push(partial.clone()),
+            _ => {
+                groups.push(vec![partial.clone()]);
+                tokens = 0;
+            }
+        }
+        tokens += size;
+    }
+    groups
+}
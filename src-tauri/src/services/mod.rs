@@ -1,13 +1,37 @@
 // Old services (commented out as they're not being used)
 // pub mod scheduler;
 // pub mod task_shredder;
-// pub mod context_pool;
 // pub mod agent_pool;
 // pub mod execution_engine;
 
 // Active services
 pub mod simple_executor;
+pub mod agent_host;
+pub mod local_agent;
+pub mod ws_agent;
 pub mod task_runner;
+pub mod fair_share;
+pub mod rate_limiter;
+pub mod circuit_breaker;
+pub mod agent_probe;
+pub mod streaming;
+pub mod providers;
+pub mod output_contract;
+pub mod tokens;
+pub mod context_assembler;
+pub mod context_pool;
+pub mod map_reduce;
+pub mod ensemble;
+pub mod evaluator;
+pub mod usage;
+pub mod response_cache;
+pub mod tool_manager;
+pub mod tool_calling;
+pub mod cassette;
+pub mod artifacts;
+pub mod video;
+pub mod speech;
+pub mod ollama;
 
 pub use simple_executor::*;
 pub use task_runner::*;
\ No newline at end of file
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+use crate::models::{Agent, AgentAuth, AgentHealth, Capability, HealthStatus, ModelInfo, ProviderConfig, ProviderKind};
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+// Context Ollama allocates per request; models advertising 128k would otherwise pin
+// gigabytes of memory on every call
+const MAX_CONTEXT_WINDOW: u32 = 32_768;
+// Name fragments of code-tuned model families
+const CODE_MODELS: [&str; 6] = ["code", "coder", "starcoder", "codestral", "devstral", "granite-code"];
+
+/// An installed model as reported by `/api/tags` and `/api/show`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaModel {
+    pub name: String,
+    pub size_bytes: u64,
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization: Option<String>,
+    // Trained context length; the window registered for the model is capped below this
+    pub context_length: Option<u32>,
+    pub capabilities: Vec<Capability>,
+    pub supports_tools: bool,
+}
+
+impl OllamaModel {
+    pub fn agent_name(&self) -> String {
+        agent_name(&self.name)
+    }
+
+    pub fn context_window(&self) -> u32 {
+        self.context_length.unwrap_or(2048).min(MAX_CONTEXT_WINDOW)
+    }
+
+    /// Agent bound to this model through the named provider.
+    pub fn agent(&self, provider: &str, base_url: &str) -> Agent {
+        Agent {
+            name: self.agent_name(),
+            capabilities: self.capabilities.clone(),
+            endpoint_url: Some(base_url.to_string()),
+            auth: Some(AgentAuth {
+                auth_type: "ollama".to_string(),
+                api_key: None,
+                bearer_token: None,
+                custom_headers: HashMap::new(),
+            }),
+            enabled: true,
+            priority: 0,
+            health: AgentHealth {
+                status: HealthStatus::Healthy,
+                last_check: Utc::now(),
+                latency_ms: None,
+                error_rate: 0.0,
+                success_count: 0,
+                failure_count: 0,
+                breaker: Default::default(),
+            },
+            local: true,
+            max_concurrent_tasks: 1,
+            token_limit: Some(self.context_window()),
+            process: None,
+            provider: Some(provider.to_string()),
+            model: Some(self.name.clone()),
+        }
+    }
+
+    pub fn model_info(&self, provider: &str) -> ModelInfo {
+        let window = self.context_window();
+        ModelInfo {
+            provider: Some(provider.to_string()),
+            input_price_per_1k: 0.0,
+            output_price_per_1k: 0.0,
+            context_window: window,
+            max_output_tokens: window / 2,
+        }
+    }
+}
+
+pub fn agent_name(model: &str) -> String {
+    format!("ollama:{}", model)
+}
+
+/// One line of `/api/pull` progress, forwarded to the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct PullProgress {
+    pub model: String,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    pub done: bool,
+}
+
+/// Model management against an Ollama server.
+pub struct OllamaClient {
+    http_client: reqwest::Client,
+    base_url: String,
+}
+
+impl OllamaClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            // No overall timeout: pulls run for as long as the download takes
+            http_client: reqwest::Client::builder()
+                .connect_timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .build()
+                .unwrap(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn from_config(name: &str, config: &ProviderConfig) -> Result<Self> {
+        if config.kind != ProviderKind::Ollama {
+            return Err(anyhow!("Provider {} is not an Ollama server", name));
+        }
+        Ok(Self::new(&config.base_url))
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value> {
+        let response = self
+            .http_client
+            .post(format!("{}{}", self.base_url, path))
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(anyhow!("Ollama {} failed ({}): {}", path, status, body["error"].as_str().unwrap_or_default()));
+        }
+        Ok(body)
+    }
+
+    /// Installed models with their capabilities and context sizes.
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>> {
+        let tags: Value = self
+            .http_client
+            .get(format!("{}/api/tags", self.base_url))
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut models = Vec::new();
+        for tag in tags["models"].as_array().cloned().unwrap_or_default() {
+            let Some(name) = tag["name"].as_str() else { continue };
+            let show = match self.post("/api/show", json!({"model": name})).await {
+                Ok(show) => show,
+                Err(e) => {
+                    debug!("Skipping details for {}: {}", name, e);
+                    Value::Null
+                }
+            };
+            models.push(describe(name, &tag, &show));
+        }
+        Ok(models)
+    }
+
+    pub async fn show(&self, model: &str) -> Result<OllamaModel> {
+        let show = self.post("/api/show", json!({"model": model})).await?;
+        Ok(describe(model, &Value::Null, &show))
+    }
+
+    /// Download a model, reporting each progress line as it arrives.
+    pub async fn pull(&self, model: &str, mut on_progress: impl FnMut(PullProgress)) -> Result<()> {
+        let response = self
+            .http_client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&json!({"model": model, "stream": true}))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut bytes = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = bytes.next().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let data: Value = serde_json::from_str(line.trim())?;
+                if let Some(error) = data["error"].as_str() {
+                    return Err(anyhow!("Pulling {} failed: {}", model, error));
+                }
+                let status = data["status"].as_str().unwrap_or_default().to_string();
+                let (completed, total) = (data["completed"].as_u64(), data["total"].as_u64());
+                let done = status == "success";
+                on_progress(PullProgress {
+                    model: model.to_string(),
+                    percent: match (completed, total) {
+                        (Some(completed), Some(total)) if total > 0 => Some(completed as f64 * 100.0 / total as f64),
+                        _ => None,
+                    },
+                    digest: data["digest"].as_str().map(|s| s.to_string()),
+                    completed,
+                    total,
+                    status,
+                    done,
+                });
+                if done {
+                    info!("Pulled Ollama model {}", model);
+                    return Ok(());
+                }
+            }
+        }
+        Err(anyhow!("Pull of {} ended before completion", model))
+    }
+
+    pub async fn delete(&self, model: &str) -> Result<()> {
+        let response = self
+            .http_client
+            .delete(format!("{}/api/delete", self.base_url))
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .json(&json!({"model": model}))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body: Value = response.json().await.unwrap_or_default();
+            return Err(anyhow!("Deleting {} failed ({}): {}", model, status, body["error"].as_str().unwrap_or_default()));
+        }
+        info!("Deleted Ollama model {}", model);
+        Ok(())
+    }
+}
+
+fn describe(name: &str, tag: &Value, show: &Value) -> OllamaModel {
+    let details = if show["details"].is_object() { &show["details"] } else { &tag["details"] };
+    let context_length = show["model_info"].as_object().and_then(|info| {
+        let architecture = info.get("general.architecture").and_then(|a| a.as_str());
+        architecture
+            .and_then(|arch| info.get(&format!("{}.context_length", arch)))
+            .or_else(|| info.iter().find(|(k, _)| k.ends_with(".context_length")).map(|(_, v)| v))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32)
+    });
+
+    // Servers before 0.6.4 do not report capabilities; assume a chat model
+    let reported: Vec<&str> = show["capabilities"]
+        .as_array()
+        .map(|caps| caps.iter().filter_map(|c| c.as_str()).collect())
+        .unwrap_or_else(|| vec!["completion"]);
+    let mut capabilities = Vec::new();
+    if reported.contains(&"completion") {
+        capabilities.push(Capability::Text);
+        let lower = name.to_lowercase();
+        if CODE_MODELS.iter().any(|fragment| lower.contains(fragment)) {
+            capabilities.push(Capability::Code);
+        }
+    }
+
+    OllamaModel {
+        name: name.to_string(),
+        size_bytes: tag["size"].as_u64().unwrap_or_default(),
+        family: details["family"].as_str().map(|s| s.to_string()),
+        parameter_size: details["parameter_size"].as_str().map(|s| s.to_string()),
+        quantization: details["quantization_level"].as_str().map(|s| s.to_string()),
+        context_length,
+        capabilities,
+        supports_tools: reported.contains(&"tools"),
+    }
+}
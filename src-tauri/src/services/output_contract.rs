@@ -0,0 +1,186 @@
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+
+// Round-trips allowed to fix output that fails validation
+pub const MAX_REPAIR_ATTEMPTS: u32 = 2;
+
+/// Instructions appended to the preamble so the model knows the expected shape.
+pub fn contract_instructions(schema: &Value) -> String {
+    format!(
+        "Respond with a single JSON value that validates against this JSON Schema. \
+         Do not add commentary or markdown fences.\n{}",
+        serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string())
+    )
+}
+
+/// Input for a repair round-trip: the rejected output plus what was wrong with it.
+pub fn repair_input(original_input: &Value, previous_output: &str, errors: &[String]) -> Value {
+    json!({
+        "original_input": original_input,
+        "previous_output": previous_output,
+        "validation_errors": errors,
+        "instruction": "Your previous output did not satisfy the JSON Schema. Return only the corrected JSON.",
+    })
+}
+
+/// Validate against a JSON Schema, returning one message per violation.
+pub fn validate(schema: &Value, instance: &Value) -> Result<(), Vec<String>> {
+    let compiled = JSONSchema::compile(schema)
+        .map_err(|e| vec![format!("Invalid output schema: {}", e)])?;
+
+    let messages: Vec<String> = match compiled.validate(instance) {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() {
+                    e.to_string()
+                } else {
+                    format!("{}: {}", path, e)
+                }
+            })
+            .collect(),
+    };
+    Err(messages)
+}
+
+/// Pull a JSON value out of model output: the whole text, a fenced block, or
+/// the first balanced object/array embedded in prose.
+pub fn extract_json(content: &str) -> Option<Value> {
+    let trimmed = content.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+
+    for block in fenced_blocks(trimmed) {
+        if let Ok(value) = serde_json::from_str(block.trim()) {
+            return Some(value);
+        }
+    }
+
+    let start = trimmed.find(['{', '['])?;
+    let end = balanced_end(&trimmed[start..])?;
+    serde_json::from_str(&trimmed[start..start + end]).ok()
+}
+
+fn fenced_blocks(content: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+    while let Some(open) = rest.find("```") {
+        let after = &rest[open + 3..];
+        // Skip the language tag, e.g. ```json
+        let body_start = after.find('\n').map(|i| i + 1).unwrap_or(0);
+        let body = &after[body_start..];
+        match body.find("```") {
+            Some(close) => {
+                blocks.push(&body[..close]);
+                rest = &body[close + 3..];
+            }
+            None => break,
+        }
+    }
+    blocks
+}
+
+/// Byte length of the object or array at the start of `text`, honouring strings and escapes.
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_plain_json() {
+        assert_eq!(extract_json("  {\"a\": 1}\n"), Some(json!({"a": 1})));
+    }
+
+    #[test]
+    fn extracts_fenced_json() {
+        let content = "Here you go:\n```json\n{\"items\": [1, 2]}\n```\nDone.";
+        assert_eq!(extract_json(content), Some(json!({"items": [1, 2]})));
+    }
+
+    #[test]
+    fn skips_fenced_blocks_that_are_not_json() {
+        let content = "```\nnot json\n```\n```json\n[true]\n```";
+        assert_eq!(extract_json(content), Some(json!([true])));
+    }
+
+    #[test]
+    fn extracts_json_embedded_in_prose() {
+        let content = "The result is {\"ok\": true, \"nested\": {\"n\": [1]}} as requested.";
+        assert_eq!(extract_json(content), Some(json!({"ok": true, "nested": {"n": [1]}})));
+    }
+
+    #[test]
+    fn brace_inside_string_does_not_end_object() {
+        let content = "Answer: {\"text\": \"a } and { b\", \"n\": 2} trailing }";
+        assert_eq!(extract_json(content), Some(json!({"text": "a } and { b", "n": 2})));
+    }
+
+    #[test]
+    fn escaped_quote_stays_inside_string() {
+        let content = r#"Answer: {"quote": "she said \"}\" loudly", "n": 3} end"#;
+        assert_eq!(extract_json(content), Some(json!({"quote": "she said \"}\" loudly", "n": 3})));
+    }
+
+    #[test]
+    fn balanced_end_counts_bytes() {
+        assert_eq!(balanced_end("{\"é\": [1]} rest"), Some(11));
+        assert_eq!(balanced_end("[1, [2, 3]]"), Some(11));
+    }
+
+    #[test]
+    fn unbalanced_json_is_rejected() {
+        assert_eq!(balanced_end("{\"a\": \"}\""), None);
+        assert_eq!(extract_json("prefix {\"a\": 1 and no close"), None);
+        assert_eq!(extract_json("no json here"), None);
+    }
+
+    #[test]
+    fn validate_reports_each_violation() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "count"],
+            "properties": {"count": {"type": "integer"}}
+        });
+        assert!(validate(&schema, &json!({"name": "x", "count": 1})).is_ok());
+        let errors = validate(&schema, &json!({"count": "many"})).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|e| e.starts_with("/count")));
+    }
+
+    #[test]
+    fn invalid_schema_is_reported() {
+        let errors = validate(&json!({"type": 5}), &json!({})).unwrap_err();
+        assert!(errors[0].starts_with("Invalid output schema"));
+    }
+}
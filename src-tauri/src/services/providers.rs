@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde_json::{json, Value};
+use crate::models::{lookup_model, AuthStyle, Capability, JsonMode, ModelInfo, ProviderConfig, ProviderKind};
+use super::streaming::{self, as_u32, StreamAccumulator, StreamUsage};
+
+// Shared registry so config changes reach every executor
+static PROVIDER_REGISTRY: Lazy<Arc<ProviderRegistry>> = Lazy::new(|| {
+    Arc::new(ProviderRegistry::new(crate::models::default_providers()))
+});
+
+pub fn provider_registry() -> Arc<ProviderRegistry> {
+    Arc::clone(&PROVIDER_REGISTRY)
+}
+
+/// A single text-generation call, independent of the provider's wire format.
+#[derive(Debug, Clone)]
+pub struct TextRequest {
+    pub model: String,
+    pub system: String,
+    pub user: String,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    // Output contract, passed to the provider's native JSON mode when it has one
+    pub json_schema: Option<Value>,
+    // Context window to allocate; only Ollama sizes it per request
+    pub context_window: Option<u32>,
+}
+
+/// One image-generation call. Fields the backend does not understand are ignored.
+#[derive(Debug, Clone)]
+pub struct ImageRequest {
+    pub model: Option<String>,
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub steps: Option<u32>,
+    pub seed: Option<i64>,
+    pub batch_count: u32,
+    pub cfg_scale: Option<f32>,
+    // Base64 source image; present for img2img
+    pub init_image: Option<String>,
+    pub denoising_strength: Option<f32>,
+}
+
+impl ImageRequest {
+    /// Read generation settings from a task input: a bare prompt string, or an object with
+    /// `prompt` (else the preamble and whole input), `negative_prompt`, `size` ("768x512") or `width`/`height`, `steps`, `seed`,
+    /// `batch_count`, `cfg_scale` and `denoising_strength`.
+    pub fn from_input(model: Option<String>, preamble: &str, input: &Value) -> Result<Self> {
+        let prompt = match input {
+            Value::String(prompt) => prompt.clone(),
+            _ => match input["prompt"].as_str() {
+                Some(prompt) => prompt.to_string(),
+                None => format!("{}\n{}", preamble, input),
+            },
+        };
+        if prompt.trim().is_empty() {
+            return Err(anyhow!("Image task has no prompt"));
+        }
+
+        let (mut width, mut height) = (512, 512);
+        if let Some(size) = input["size"].as_str() {
+            let (w, h) = size
+                .split_once('x')
+                .ok_or_else(|| anyhow!("Image size must look like 512x512, got '{}'", size))?;
+            width = w.trim().parse()?;
+            height = h.trim().parse()?;
+        }
+        let as_u32 = |key: &str| input[key].as_u64().map(|v| v as u32);
+        let as_f32 = |key: &str| input[key].as_f64().map(|v| v as f32);
+
+        Ok(Self {
+            model,
+            prompt,
+            negative_prompt: input["negative_prompt"].as_str().map(|s| s.to_string()),
+            width: as_u32("width").unwrap_or(width),
+            height: as_u32("height").unwrap_or(height),
+            steps: as_u32("steps"),
+            seed: input["seed"].as_i64(),
+            batch_count: as_u32("batch_count").unwrap_or(1).max(1),
+            cfg_scale: as_f32("cfg_scale"),
+            init_image: None,
+            denoising_strength: as_f32("denoising_strength"),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    pub bytes: Vec<u8>,
+    pub seed: Option<i64>,
+}
+
+/// A function offered to the model in a tool-calling conversation.
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionSpec {
+    pub name: String,
+    pub description: String,
+    // JSON Schema of the arguments object
+    pub parameters: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// Provider-neutral conversation turn; the system prompt travels separately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum ChatMessage {
+    User { content: String },
+    Assistant { content: String, tool_calls: Vec<ToolCall> },
+    Tool { call_id: String, name: String, content: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub system: String,
+    pub messages: Vec<ChatMessage>,
+    pub functions: Vec<FunctionSpec>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub context_window: Option<u32>,
+}
+
+/// The model's reply to a `ChatRequest`: text, tool calls, or both.
+#[derive(Debug, Clone, Default)]
+pub struct ChatTurn {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: StreamUsage,
+}
+
+/// OpenAI-style function arguments arrive as a JSON string, others as an object.
+fn parse_arguments(arguments: &Value) -> Value {
+    match arguments {
+        Value::String(raw) => serde_json::from_str(raw).unwrap_or_else(|_| json!({})),
+        Value::Null => json!({}),
+        other => other.clone(),
+    }
+}
+
+/// Function specs in the OpenAI `tools` shape, which Ollama also accepts.
+fn openai_tools(functions: &[FunctionSpec]) -> Vec<Value> {
+    functions
+        .iter()
+        .map(|f| {
+            json!({
+                "type": "function",
+                "function": {"name": f.name, "description": f.description, "parameters": f.parameters}
+            })
+        })
+        .collect()
+}
+
+/// A model API reachable over HTTP; implementations own request shape and stream parsing.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn config(&self) -> &ProviderConfig;
+
+    fn supports(&self, capability: &Capability) -> bool {
+        self.config().capabilities.contains(capability)
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.config().default_model.as_deref()
+    }
+
+    /// Build a streaming text request, authenticated per the provider's auth style.
+    fn text_request(&self, _client: &reqwest::Client, _api_key: Option<&str>, _request: &TextRequest) -> Result<reqwest::RequestBuilder> {
+        Err(anyhow!("Provider {} does not generate text", self.name()))
+    }
+
+    async fn read_text_stream(&self, _response: reqwest::Response, _acc: &mut StreamAccumulator) -> Result<()> {
+        Err(anyhow!("Provider {} does not generate text", self.name()))
+    }
+
+    fn image_request(&self, _client: &reqwest::Client, _api_key: Option<&str>, _request: &ImageRequest) -> Result<reqwest::RequestBuilder> {
+        Err(anyhow!("Provider {} does not generate images", self.name()))
+    }
+
+    fn parse_images(&self, _body: &Value) -> Result<Vec<GeneratedImage>> {
+        Err(anyhow!("Provider {} does not generate images", self.name()))
+    }
+
+    /// Whether the provider can offer functions to the model.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Build a non-streaming request for one turn of a tool-calling conversation.
+    fn chat_request(&self, _client: &reqwest::Client, _api_key: Option<&str>, _request: &ChatRequest) -> Result<reqwest::RequestBuilder> {
+        Err(anyhow!("Provider {} does not support tool calling", self.name()))
+    }
+
+    fn parse_chat_turn(&self, _body: &Value) -> Result<ChatTurn> {
+        Err(anyhow!("Provider {} does not support tool calling", self.name()))
+    }
+}
+
+fn endpoint(config: &ProviderConfig, path: &str) -> String {
+    format!("{}{}", config.base_url.trim_end_matches('/'), path)
+}
+
+/// Attach the configured auth header and any extra headers.
+pub fn authorize(builder: reqwest::RequestBuilder, config: &ProviderConfig, api_key: Option<&str>) -> reqwest::RequestBuilder {
+    let mut builder = match (&config.auth_style, api_key) {
+        (AuthStyle::Bearer, Some(key)) => builder.header("Authorization", format!("Bearer {}", key)),
+        (AuthStyle::XApiKey, Some(key)) => builder.header("x-api-key", key),
+        _ => builder,
+    };
+    for (name, value) in &config.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    builder
+}
+
+/// OpenAI chat completions, also spoken by vLLM, llama.cpp server and LM Studio.
+pub struct OpenAiProvider {
+    name: String,
+    config: ProviderConfig,
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn text_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &TextRequest) -> Result<reqwest::RequestBuilder> {
+        let mut body = json!({
+            "model": request.model,
+            "messages": [
+                {"role": "system", "content": request.system},
+                {"role": "user", "content": request.user}
+            ],
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "stream": true,
+            "stream_options": {"include_usage": true}
+        });
+        if let Some(schema) = &request.json_schema {
+            match self.config.json_mode {
+                JsonMode::JsonSchema => {
+                    body["response_format"] = json!({
+                        "type": "json_schema",
+                        "json_schema": {"name": "output", "schema": schema}
+                    });
+                }
+                JsonMode::JsonObject => body["response_format"] = json!({"type": "json_object"}),
+                JsonMode::None => {}
+            }
+        }
+        Ok(authorize(client.post(endpoint(&self.config, "/chat/completions")), &self.config, api_key).json(&body))
+    }
+
+    async fn read_text_stream(&self, response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+        streaming::read_openai(response, acc).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn chat_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &ChatRequest) -> Result<reqwest::RequestBuilder> {
+        let mut messages = vec![json!({"role": "system", "content": request.system})];
+        for message in &request.messages {
+            messages.push(match message {
+                ChatMessage::User { content } => json!({"role": "user", "content": content}),
+                ChatMessage::Assistant { content, tool_calls } => {
+                    let mut turn = json!({"role": "assistant", "content": content});
+                    if !tool_calls.is_empty() {
+                        turn["tool_calls"] = tool_calls
+                            .iter()
+                            .map(|call| {
+                                json!({
+                                    "id": call.id,
+                                    "type": "function",
+                                    "function": {"name": call.name, "arguments": call.arguments.to_string()}
+                                })
+                            })
+                            .collect();
+                    }
+                    turn
+                }
+                ChatMessage::Tool { call_id, content, .. } => {
+                    json!({"role": "tool", "tool_call_id": call_id, "content": content})
+                }
+            });
+        }
+        let body = json!({
+            "model": request.model,
+            "messages": messages,
+            "tools": openai_tools(&request.functions),
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens
+        });
+        Ok(authorize(client.post(endpoint(&self.config, "/chat/completions")), &self.config, api_key).json(&body))
+    }
+
+    fn parse_chat_turn(&self, body: &Value) -> Result<ChatTurn> {
+        let message = &body["choices"][0]["message"];
+        if !message.is_object() {
+            return Err(anyhow!("OpenAI response has no message: {}", body));
+        }
+        let tool_calls = message["tool_calls"]
+            .as_array()
+            .map(|calls| {
+                calls
+                    .iter()
+                    .map(|call| ToolCall {
+                        id: call["id"].as_str().unwrap_or_default().to_string(),
+                        name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: parse_arguments(&call["function"]["arguments"]),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(ChatTurn {
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            tool_calls,
+            usage: StreamUsage {
+                prompt_tokens: as_u32(&body["usage"]["prompt_tokens"]),
+                completion_tokens: as_u32(&body["usage"]["completion_tokens"]),
+            },
+        })
+    }
+}
+
+pub struct AnthropicProvider {
+    name: String,
+    config: ProviderConfig,
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn text_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &TextRequest) -> Result<reqwest::RequestBuilder> {
+        let mut body = json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens,
+            "temperature": request.temperature,
+            "messages": [
+                {"role": "user", "content": request.user}
+            ],
+            "stream": true
+        });
+        if !request.system.is_empty() {
+            body["system"] = json!(request.system);
+        }
+        Ok(authorize(client.post(endpoint(&self.config, "/messages")), &self.config, api_key).json(&body))
+    }
+
+    async fn read_text_stream(&self, response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+        streaming::read_anthropic(response, acc).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn chat_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &ChatRequest) -> Result<reqwest::RequestBuilder> {
+        let mut messages: Vec<Value> = Vec::new();
+        for message in &request.messages {
+            match message {
+                ChatMessage::User { content } => messages.push(json!({"role": "user", "content": content})),
+                ChatMessage::Assistant { content, tool_calls } => {
+                    let mut blocks = Vec::new();
+                    if !content.is_empty() {
+                        blocks.push(json!({"type": "text", "text": content}));
+                    }
+                    for call in tool_calls {
+                        blocks.push(json!({"type": "tool_use", "id": call.id, "name": call.name, "input": call.arguments}));
+                    }
+                    messages.push(json!({"role": "assistant", "content": blocks}));
+                }
+                ChatMessage::Tool { call_id, content, .. } => {
+                    let block = json!({"type": "tool_result", "tool_use_id": call_id, "content": content});
+                    // Results for one assistant turn belong in a single user message
+                    match messages.last_mut() {
+                        Some(last) if last["role"] == "user" && last["content"].is_array() => {
+                            if let Some(blocks) = last["content"].as_array_mut() {
+                                blocks.push(block);
+                            }
+                        }
+                        _ => messages.push(json!({"role": "user", "content": [block]})),
+                    }
+                }
+            }
+        }
+        let tools: Vec<Value> = request
+            .functions
+            .iter()
+            .map(|f| json!({"name": f.name, "description": f.description, "input_schema": f.parameters}))
+            .collect();
+        let body = json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens,
+            "temperature": request.temperature,
+            "system": request.system,
+            "messages": messages,
+            "tools": tools
+        });
+        Ok(authorize(client.post(endpoint(&self.config, "/messages")), &self.config, api_key).json(&body))
+    }
+
+    fn parse_chat_turn(&self, body: &Value) -> Result<ChatTurn> {
+        let blocks = body["content"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Anthropic response has no content: {}", body))?;
+        let mut turn = ChatTurn::default();
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") => turn.content.push_str(block["text"].as_str().unwrap_or_default()),
+                Some("tool_use") => turn.tool_calls.push(ToolCall {
+                    id: block["id"].as_str().unwrap_or_default().to_string(),
+                    name: block["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: parse_arguments(&block["input"]),
+                }),
+                _ => {}
+            }
+        }
+        turn.usage = StreamUsage {
+            prompt_tokens: as_u32(&body["usage"]["input_tokens"]),
+            completion_tokens: as_u32(&body["usage"]["output_tokens"]),
+        };
+        Ok(turn)
+    }
+}
+
+/// Without `num_ctx` Ollama loads every model with a 2048-token window and silently
+/// drops the start of longer prompts.
+fn ollama_options(temperature: f32, max_tokens: u32, context_window: Option<u32>) -> Value {
+    let mut options = json!({"temperature": temperature, "num_predict": max_tokens});
+    if let Some(num_ctx) = context_window {
+        options["num_ctx"] = json!(num_ctx);
+    }
+    options
+}
+
+pub struct OllamaProvider {
+    name: String,
+    config: ProviderConfig,
+}
+
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn text_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &TextRequest) -> Result<reqwest::RequestBuilder> {
+        let mut body = json!({
+            "model": request.model,
+            "messages": [
+                {"role": "system", "content": request.system},
+                {"role": "user", "content": request.user}
+            ],
+            "stream": true,
+            "options": ollama_options(request.temperature, request.max_tokens, request.context_window)
+        });
+        if let Some(schema) = &request.json_schema {
+            match self.config.json_mode {
+                JsonMode::JsonSchema => body["format"] = schema.clone(),
+                JsonMode::JsonObject => body["format"] = json!("json"),
+                JsonMode::None => {}
+            }
+        }
+        Ok(authorize(client.post(endpoint(&self.config, "/api/chat")), &self.config, api_key).json(&body))
+    }
+
+    async fn read_text_stream(&self, response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+        streaming::read_ollama(response, acc).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn chat_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &ChatRequest) -> Result<reqwest::RequestBuilder> {
+        let mut messages = vec![json!({"role": "system", "content": request.system})];
+        for message in &request.messages {
+            messages.push(match message {
+                ChatMessage::User { content } => json!({"role": "user", "content": content}),
+                ChatMessage::Assistant { content, tool_calls } => {
+                    let calls: Vec<Value> = tool_calls
+                        .iter()
+                        .map(|call| json!({"function": {"name": call.name, "arguments": call.arguments}}))
+                        .collect();
+                    json!({"role": "assistant", "content": content, "tool_calls": calls})
+                }
+                ChatMessage::Tool { content, .. } => json!({"role": "tool", "content": content}),
+            });
+        }
+        let body = json!({
+            "model": request.model,
+            "messages": messages,
+            "tools": openai_tools(&request.functions),
+            "stream": false,
+            "options": ollama_options(request.temperature, request.max_tokens, request.context_window)
+        });
+        Ok(authorize(client.post(endpoint(&self.config, "/api/chat")), &self.config, api_key).json(&body))
+    }
+
+    fn parse_chat_turn(&self, body: &Value) -> Result<ChatTurn> {
+        if let Some(error) = body["error"].as_str() {
+            return Err(anyhow!("Ollama error: {}", error));
+        }
+        let message = &body["message"];
+        // Ollama does not assign call IDs; number them so results can be matched up
+        let tool_calls = message["tool_calls"]
+            .as_array()
+            .map(|calls| {
+                calls
+                    .iter()
+                    .enumerate()
+                    .map(|(i, call)| ToolCall {
+                        id: format!("call_{}", i),
+                        name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: parse_arguments(&call["function"]["arguments"]),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(ChatTurn {
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            tool_calls,
+            usage: StreamUsage {
+                prompt_tokens: as_u32(&body["prompt_eval_count"]),
+                completion_tokens: as_u32(&body["eval_count"]),
+            },
+        })
+    }
+}
+
+/// AUTOMATIC1111-compatible Stable Diffusion web API (also served by Forge and SD.Next).
+pub struct StableDiffusionProvider {
+    name: String,
+    config: ProviderConfig,
+}
+
+// Local generation at high step counts easily outlasts the client's default timeout
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[async_trait]
+impl Provider for StableDiffusionProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn image_request(&self, client: &reqwest::Client, api_key: Option<&str>, request: &ImageRequest) -> Result<reqwest::RequestBuilder> {
+        let mut body = json!({
+            "prompt": request.prompt,
+            "negative_prompt": request.negative_prompt.as_deref().unwrap_or_default(),
+            "width": request.width,
+            "height": request.height,
+            "seed": request.seed.unwrap_or(-1),
+            "n_iter": request.batch_count,
+            "batch_size": 1
+        });
+        if let Some(steps) = request.steps {
+            body["steps"] = json!(steps);
+        }
+        if let Some(cfg_scale) = request.cfg_scale {
+            body["cfg_scale"] = json!(cfg_scale);
+        }
+        if let Some(model) = request.model.as_deref().or(self.config.default_model.as_deref()) {
+            body["override_settings"] = json!({"sd_model_checkpoint": model});
+        }
+
+        let path = match &request.init_image {
+            Some(image) => {
+                body["init_images"] = json!([image]);
+                body["denoising_strength"] = json!(request.denoising_strength.unwrap_or(0.75));
+                "/sdapi/v1/img2img"
+            }
+            None => "/sdapi/v1/txt2img",
+        };
+        Ok(authorize(client.post(endpoint(&self.config, path)), &self.config, api_key)
+            .timeout(IMAGE_TIMEOUT)
+            .json(&body))
+    }
+
+    fn parse_images(&self, body: &Value) -> Result<Vec<GeneratedImage>> {
+        let images = body["images"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Stable Diffusion response has no images: {}", body))?;
+        // `info` is a JSON document encoded as a string; it carries the seed of each image
+        let info: Value = body["info"].as_str().and_then(|s| serde_json::from_str(s).ok()).unwrap_or_default();
+        images
+            .iter()
+            .enumerate()
+            .map(|(i, image)| {
+                let encoded = image.as_str().ok_or_else(|| anyhow!("Image {} is not base64", i))?;
+                // Some builds prefix a data URL header
+                let encoded = encoded.split_once(',').map_or(encoded, |(_, data)| data);
+                Ok(GeneratedImage {
+                    bytes: BASE64.decode(encoded)?,
+                    seed: info["all_seeds"][i].as_i64().or_else(|| info["seed"].as_i64()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Local text-to-speech; synthesis goes through ToolManager, so no requests are built here.
+pub struct LocalTtsProvider {
+    name: String,
+    config: ProviderConfig,
+}
+
+#[async_trait]
+impl Provider for LocalTtsProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+}
+
+pub fn build_provider(name: &str, config: ProviderConfig) -> Arc<dyn Provider> {
+    let name = name.to_string();
+    match config.kind {
+        ProviderKind::OpenAi => Arc::new(OpenAiProvider { name, config }),
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider { name, config }),
+        ProviderKind::Ollama => Arc::new(OllamaProvider { name, config }),
+        ProviderKind::StableDiffusion => Arc::new(StableDiffusionProvider { name, config }),
+        ProviderKind::LocalTts => Arc::new(LocalTtsProvider { name, config }),
+    }
+}
+
+/// Providers from `AppConfig.providers`, looked up by name.
+pub struct ProviderRegistry {
+    providers: RwLock<HashMap<String, Arc<dyn Provider>>>,
+    // `AppConfig.models`, consulted to find the provider serving a model
+    models: RwLock<HashMap<String, ModelInfo>>,
+    default_provider: RwLock<String>,
+}
+
+impl ProviderRegistry {
+    pub fn new(configs: HashMap<String, ProviderConfig>) -> Self {
+        let registry = Self {
+            providers: RwLock::new(HashMap::new()),
+            models: RwLock::new(crate::models::default_models()),
+            default_provider: RwLock::new(crate::models::default_provider()),
+        };
+        registry.configure(configs);
+        registry
+    }
+
+    pub fn register_models(&self, models: HashMap<String, ModelInfo>) {
+        *self.models.write() = models;
+    }
+
+    /// The registered model's info, matched by name or longest prefix.
+    pub fn model_info(&self, model: &str) -> Option<ModelInfo> {
+        lookup_model(&self.models.read(), model).cloned()
+    }
+
+    pub fn configure(&self, configs: HashMap<String, ProviderConfig>) {
+        let providers = configs
+            .into_iter()
+            .map(|(name, config)| {
+                let provider = build_provider(&name, config);
+                (name, provider)
+            })
+            .collect();
+        *self.providers.write() = providers;
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Provider>> {
+        self.providers.read().get(name).cloned()
+    }
+
+    pub fn set_default_provider(&self, name: &str) {
+        *self.default_provider.write() = name.to_string();
+    }
+
+    /// The named provider, or for tasks that only name a model, the provider registered
+    /// for it in the model registry, else the built-in provider whose models share its prefix.
+    /// Tasks naming neither, or a model nothing claims, go to the default provider.
+    pub fn resolve(&self, provider: Option<&str>, model: Option<&str>) -> Result<Arc<dyn Provider>> {
+        if let Some(name) = provider {
+            return self.get(name).ok_or_else(|| anyhow!("Unknown provider: {}", name));
+        }
+
+        let model = match model {
+            Some(model) => model,
+            None => return self.default(),
+        };
+        let registered = lookup_model(&self.models.read(), model).and_then(|info| info.provider.clone());
+        if let Some(name) = registered {
+            return self
+                .get(&name)
+                .ok_or_else(|| anyhow!("Provider {} for model {} is not configured", name, model));
+        }
+        let inferred = if model.starts_with("gpt") || model.starts_with("o1") {
+            "openai"
+        } else if model.starts_with("claude") {
+            "anthropic"
+        } else if model.starts_with("llama") || model.starts_with("mistral") {
+            "ollama"
+        } else {
+            return self.default();
+        };
+        self.get(inferred)
+            .ok_or_else(|| anyhow!("Provider {} for model {} is not configured", inferred, model))
+    }
+
+    fn default(&self) -> Result<Arc<dyn Provider>> {
+        let name = self.default_provider.read().clone();
+        self.get(&name).ok_or_else(|| anyhow!("Default provider {} is not configured", name))
+    }
+}
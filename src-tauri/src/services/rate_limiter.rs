@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::{Mutex, RwLock};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use crate::models::RateLimitConfig;
+
+// Shared by every executor and the scheduler so they see the same buckets
+static PROVIDER_LIMITER: Lazy<Arc<ProviderRateLimiter>> = Lazy::new(|| {
+    Arc::new(ProviderRateLimiter::new(crate::models::default_rate_limits()))
+});
+
+pub fn provider_limiter() -> Arc<ProviderRateLimiter> {
+    Arc::clone(&PROVIDER_LIMITER)
+}
+
+/// Error returned when a provider answered 429 or the limiter is in a penalty window.
+#[derive(Debug, Clone)]
+pub struct RateLimitedError {
+    pub provider: String,
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimitedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} rate limited, retry after {}s", self.provider, self.retry_after.as_secs())
+    }
+}
+
+impl std::error::Error for RateLimitedError {}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32) -> Self {
+        Self {
+            capacity: limit as f64,
+            available: limit as f64,
+            refill_per_sec: limit as f64 / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until `amount` units are available; zero when they already are.
+    fn wait_for(&self, amount: f64) -> Duration {
+        // A single request larger than the bucket can only wait for a full bucket
+        let amount = amount.min(self.capacity);
+        if self.available >= amount || self.refill_per_sec <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.available) / self.refill_per_sec)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    requests: TokenBucket,
+    tokens: Option<TokenBucket>,
+    blocked_until: Option<Instant>,
+}
+
+impl LimiterState {
+    fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            requests: TokenBucket::per_minute(config.requests_per_minute.max(1)),
+            tokens: config.tokens_per_minute.map(TokenBucket::per_minute),
+            blocked_until: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitSnapshot {
+    pub provider: String,
+    pub key_id: String,
+    pub requests_available: u32,
+    pub requests_per_minute: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_available: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_for_ms: Option<u64>,
+}
+
+/// Token-bucket limiter keyed by provider and API key, covering requests and tokens per minute.
+pub struct ProviderRateLimiter {
+    configs: RwLock<HashMap<String, RateLimitConfig>>,
+    states: Mutex<HashMap<(String, String), LimiterState>>,
+}
+
+impl ProviderRateLimiter {
+    pub fn new(configs: HashMap<String, RateLimitConfig>) -> Self {
+        Self {
+            configs: RwLock::new(configs),
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replace provider limits; existing buckets are rebuilt on next use.
+    pub fn configure(&self, configs: HashMap<String, RateLimitConfig>) {
+        *self.configs.write() = configs;
+        self.states.lock().clear();
+    }
+
+    pub fn key_id(api_key: Option<&str>) -> String {
+        match api_key {
+            Some(key) if !key.is_empty() => {
+                let digest = Sha256::digest(key.as_bytes());
+                digest.iter().take(4).map(|b| format!("{:02x}", b)).collect()
+            }
+            _ => "local".to_string(),
+        }
+    }
+
+    /// Wait until one request and `estimated_tokens` tokens can be spent, then spend them.
+    pub async fn acquire(&self, provider: &str, api_key: Option<&str>, estimated_tokens: u32) {
+        let key = (provider.to_string(), Self::key_id(api_key));
+        loop {
+            let wait = {
+                let config = match self.configs.read().get(provider) {
+                    Some(config) => config.clone(),
+                    None => return, // Unconfigured providers are not limited
+                };
+                let mut states = self.states.lock();
+                let state = states.entry(key.clone()).or_insert_with(|| LimiterState::new(&config));
+                let now = Instant::now();
+
+                let blocked = state.blocked_until
+                    .map(|until| until.saturating_duration_since(now))
+                    .unwrap_or(Duration::ZERO);
+                state.requests.refill(now);
+                let mut wait = blocked.max(state.requests.wait_for(1.0));
+                if let Some(tokens) = state.tokens.as_mut() {
+                    tokens.refill(now);
+                    wait = wait.max(tokens.wait_for(estimated_tokens as f64));
+                }
+
+                if wait.is_zero() {
+                    state.blocked_until = None;
+                    state.requests.available -= 1.0;
+                    if let Some(tokens) = state.tokens.as_mut() {
+                        tokens.available -= (estimated_tokens as f64).min(tokens.capacity);
+                    }
+                    return;
+                }
+                wait
+            };
+            debug!("Rate limiter waiting {:?} for {}", wait, provider);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Settle the token bucket once the provider reports actual usage.
+    pub fn record_usage(&self, provider: &str, api_key: Option<&str>, estimated_tokens: u32, actual_tokens: u32) {
+        let key = (provider.to_string(), Self::key_id(api_key));
+        let mut states = self.states.lock();
+        if let Some(tokens) = states.get_mut(&key).and_then(|s| s.tokens.as_mut()) {
+            let estimated = (estimated_tokens as f64).min(tokens.capacity);
+            tokens.available = (tokens.available + estimated - actual_tokens as f64).min(tokens.capacity);
+        }
+    }
+
+    /// Block a provider key after a 429 until the provider's `Retry-After` has passed.
+    pub fn throttle(&self, provider: &str, api_key: Option<&str>, retry_after: Duration) {
+        warn!("{} returned 429, pausing for {:?}", provider, retry_after);
+        let config = self.configs.read().get(provider).cloned().unwrap_or_default();
+        let key = (provider.to_string(), Self::key_id(api_key));
+        let mut states = self.states.lock();
+        let state = states.entry(key).or_insert_with(|| LimiterState::new(&config));
+        let until = Instant::now() + retry_after;
+        if state.blocked_until.is_none_or(|current| current < until) {
+            state.blocked_until = Some(until);
+        }
+        state.requests.available = state.requests.available.min(0.0);
+    }
+
+    /// True when no key for this provider could send a request right now.
+    pub fn is_throttled(&self, provider: &str) -> bool {
+        let now = Instant::now();
+        let mut states = self.states.lock();
+        let mut seen = false;
+        for ((p, _), state) in states.iter_mut() {
+            if p != provider {
+                continue;
+            }
+            seen = true;
+            state.requests.refill(now);
+            let blocked = state.blocked_until.is_some_and(|until| until > now);
+            if !blocked && state.requests.available >= 1.0 {
+                return false;
+            }
+        }
+        seen
+    }
+
+    pub fn snapshot(&self) -> Vec<RateLimitSnapshot> {
+        let now = Instant::now();
+        let mut states = self.states.lock();
+        let mut result: Vec<RateLimitSnapshot> = states
+            .iter_mut()
+            .map(|((provider, key_id), state)| {
+                state.requests.refill(now);
+                if let Some(tokens) = state.tokens.as_mut() {
+                    tokens.refill(now);
+                }
+                RateLimitSnapshot {
+                    provider: provider.clone(),
+                    key_id: key_id.clone(),
+                    requests_available: state.requests.available.max(0.0) as u32,
+                    requests_per_minute: state.requests.capacity as u32,
+                    tokens_available: state.tokens.as_ref().map(|t| t.available.max(0.0) as u32),
+                    tokens_per_minute: state.tokens.as_ref().map(|t| t.capacity as u32),
+                    blocked_for_ms: state.blocked_until
+                        .filter(|until| *until > now)
+                        .map(|until| until.duration_since(now).as_millis() as u64),
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| (&a.provider, &a.key_id).cmp(&(&b.provider, &b.key_id)));
+        result
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds or as an HTTP-date; values that are
+/// negative, not finite or unparseable fall back to `default`.
+pub fn parse_retry_after(headers: &reqwest::header::HeaderMap, default: Duration) -> Duration {
+    let value = match headers.get(reqwest::header::RETRY_AFTER).and_then(|v| v.to_str().ok()) {
+        Some(value) => value.trim(),
+        None => return default,
+    };
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).unwrap_or(default);
+    }
+    // A date already in the past means the provider will take requests again now
+    chrono::DateTime::parse_from_rfc2822(value)
+        .map(|at| (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+        .unwrap_or(default)
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use crate::models::{ResponseCacheConfig, TokenUsage};
+
+// Shared so every executor sees the same configuration and directory
+static RESPONSE_CACHE: Lazy<Arc<ResponseCache>> = Lazy::new(|| Arc::new(ResponseCache::new()));
+
+pub fn response_cache() -> Arc<ResponseCache> {
+    Arc::clone(&RESPONSE_CACHE)
+}
+
+/// Everything that determines a text response; its SHA-256 is the cache key.
+#[derive(Debug, Serialize)]
+pub struct CacheKeyInput<'a> {
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub preamble: &'a str,
+    pub input: &'a Value,
+    pub full_context: Option<&'a Value>,
+    pub related_outputs: Option<&'a Vec<Value>>,
+    pub output_schema: Option<&'a Value>,
+    pub temperature: f32,
+    pub max_tokens: u32,
+}
+
+impl CacheKeyInput<'_> {
+    pub fn key(&self) -> String {
+        let canonical = serde_json::to_vec(self).unwrap_or_default();
+        Sha256::digest(&canonical).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+    pub output: Value,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+pub struct ResponseCache {
+    config: RwLock<ResponseCacheConfig>,
+    dir: RwLock<Option<PathBuf>>,
+    // Serializes writes and eviction
+    write_lock: Mutex<()>,
+}
+
+impl ResponseCache {
+    fn new() -> Self {
+        Self {
+            config: RwLock::new(ResponseCacheConfig::default()),
+            dir: RwLock::new(None),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Set limits and the storage directory; the cache stays inert until this is called.
+    pub fn configure(&self, config: ResponseCacheConfig, dir: PathBuf) {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("Response cache directory unavailable: {}", e);
+            return;
+        }
+        *self.config.write() = config;
+        *self.dir.write() = Some(dir);
+    }
+
+    /// Whether a call may be served from or stored in the cache.
+    ///
+    /// `task_override` is the task's own setting: `Some(false)` bypasses the cache,
+    /// `Some(true)` caches even sampled output.
+    pub fn cacheable(&self, temperature: f32, task_override: Option<bool>) -> bool {
+        let config = self.config.read();
+        if !config.enabled || self.dir.read().is_none() {
+            return false;
+        }
+        match task_override {
+            Some(explicit) => explicit,
+            None => temperature == 0.0 || config.cache_nondeterministic,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let path = self.entry_path(key)?;
+        let contents = fs::read_to_string(&path).ok()?;
+        let entry: CachedResponse = serde_json::from_str(&contents).ok()?;
+
+        let ttl = Duration::hours(self.config.read().ttl_hours as i64);
+        if Utc::now() - entry.created_at > ttl {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        debug!("Response cache hit {}", key);
+        Some(entry)
+    }
+
+    pub fn put(&self, key: &str, output: &Value, usage: Option<&TokenUsage>) {
+        let path = match self.entry_path(key) {
+            Some(path) => path,
+            None => return,
+        };
+        let entry = json!({
+            "key": key,
+            "created_at": Utc::now(),
+            "output": output,
+            "usage": usage,
+        });
+
+        let _guard = self.write_lock.lock();
+        if let Err(e) = fs::write(&path, entry.to_string()) {
+            warn!("Failed to write response cache entry: {}", e);
+            return;
+        }
+        self.evict();
+    }
+
+    pub fn clear(&self) -> usize {
+        let _guard = self.write_lock.lock();
+        self.entries()
+            .into_iter()
+            .filter(|(path, _, _)| fs::remove_file(path).is_ok())
+            .count()
+    }
+
+    fn entry_path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.read().as_ref().map(|dir| dir.join(format!("{}.json", key)))
+    }
+
+    fn entries(&self) -> Vec<(PathBuf, u64, std::time::SystemTime)> {
+        let dir = match self.dir.read().clone() {
+            Some(dir) => dir,
+            None => return Vec::new(),
+        };
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(_) => return Vec::new(),
+        };
+        read_dir
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                let modified = meta.modified().ok()?;
+                Some((entry.path(), meta.len(), modified))
+            })
+            .collect()
+    }
+
+    /// Drop expired entries, then the oldest ones until the cache fits its size limit.
+    fn evict(&self) {
+        let (max_bytes, ttl) = {
+            let config = self.config.read();
+            (config.max_size_mb * 1024 * 1024, std::time::Duration::from_secs(config.ttl_hours * 3600))
+        };
+        let now = std::time::SystemTime::now();
+
+        let mut entries = self.entries();
+        entries.retain(|(path, _, modified)| {
+            let expired = now.duration_since(*modified).is_ok_and(|age| age > ttl);
+            if expired {
+                let _ = fs::remove_file(path);
+            }
+            !expired
+        });
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= max_bytes {
+            return;
+        }
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in entries {
+            if total <= max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+    }
+}
@@ -1,25 +1,89 @@
 use std::process::Command;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::{RwLock, Semaphore};
+use tokio::sync::{broadcast, RwLock};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use anyhow::{Result, anyhow};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use backoff::{ExponentialBackoff, future::retry};
+use futures::StreamExt;
 use tracing::{info, warn, error, debug};
-use tiktoken_rs::p50k_base;
 use std::time::Duration;
-use chrono::{DateTime, Utc};
+use super::agent_host::{agent_host, AgentHost, AgentRequest};
+use super::ws_agent::WsAgentEvent;
+use super::rate_limiter::{provider_limiter, parse_retry_after, ProviderRateLimiter, RateLimitedError};
+use super::streaming::{StreamAccumulator, StreamEvent};
+use super::providers::{provider_registry, ChatMessage, ChatRequest, ChatTurn, ImageRequest, Provider, ProviderRegistry, TextRequest};
+use super::output_contract;
+use super::tokens::{estimate_prompt_tokens, estimate_tokens, split_by_tokens};
+use super::map_reduce::{self, ChunkingSpec};
+use super::ensemble::{self, EnsembleMember, EnsembleSpec, EnsembleStrategy};
+use super::evaluator::{self, AcceptanceSpec, Evaluation};
+use super::context_assembler::{self, AssembledContext, ContextBudget};
+use super::response_cache::{response_cache, CacheKeyInput, ResponseCache};
+use super::tool_calling::{self, ToolsRanError};
+use super::tool_manager::ToolManager;
+use super::cassette::{cassettes, CassetteMissError, CassetteMode, Cassettes};
+use super::artifacts;
+use super::video::{self, Storyboard};
+use super::speech::{self, SpeechSpec};
+use crate::models::tool::Tool;
+use crate::models::TokenUsage;
+use crate::models::{AuthStyle, Capability, ProviderKind};
+use crate::utils::AppError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskExecution {
     pub task_id: String,
+    // Selects the project's cassette when provider calls are being recorded or replayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
     pub preamble: String,
     pub input: Value,
     pub capability: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_type: Option<String>,
+    // Agent the task runs on; hosted agents take the request instead of a provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
     pub tool: Option<ToolConfig>,
     pub api_key: Option<String>,
     pub model: Option<String>,
+    // Named entry in the provider registry; inferred from the model when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    // Model's context window and output cap from the model registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    // Smaller of the task's and agent's token limits; unset when limits are ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_limit: Option<u32>,
+    // JSON Schema the text output must satisfy; invalid output is sent back for repair
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    // Some(false) bypasses the response cache, Some(true) caches even sampled output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<bool>,
+    // ToolManager tool IDs the model may call; the tool loop runs only when some are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tool_steps: Option<u32>,
+    // Map-reduce over chunks of an input too large for one call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<ChunkingSpec>,
+    // Several models answer and their answers are combined into one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ensemble: Option<EnsembleSpec>,
+    // Criteria an evaluator model scores the output against; low scores trigger rework
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acceptance: Option<AcceptanceSpec>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_retries: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -49,62 +113,37 @@ pub struct ExecutionResult {
     pub tool_output: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tokens_used: Option<u32>,
+    // Prompt/completion split behind `tokens_used`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution_time_ms: Option<u64>,
     #[serde(default)]
     pub needs_user_input: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry_strategy: Option<String>,
-}
-
-#[derive(Debug, Clone)]
-struct RateLimiter {
-    semaphore: Arc<Semaphore>,
-    requests_per_minute: u32,
-    last_reset: Arc<RwLock<DateTime<Utc>>>,
-}
-
-impl RateLimiter {
-    fn new(requests_per_minute: u32) -> Self {
-        Self {
-            semaphore: Arc::new(Semaphore::new(requests_per_minute as usize)),
-            requests_per_minute,
-            last_reset: Arc::new(RwLock::new(Utc::now())),
-        }
-    }
-
-    async fn acquire(&self) -> Result<()> {
-        let now = Utc::now();
-        let mut last_reset = self.last_reset.write().await;
-        
-        if (now - *last_reset).num_seconds() >= 60 {
-            *last_reset = now;
-            let available = self.semaphore.available_permits();
-            if available < self.requests_per_minute as usize {
-                self.semaphore.add_permits(self.requests_per_minute as usize - available);
-            }
-        }
-        
-        let _permit = self.semaphore.acquire().await
-            .map_err(|e| anyhow!("Failed to acquire rate limit permit: {}", e))?;
-        Ok(())
-    }
+    // Served from the response cache; nothing was billed
+    #[serde(default)]
+    pub cache_hit: bool,
 }
 
 pub struct SimpleExecutor {
     api_keys: Arc<RwLock<HashMap<String, String>>>,
     http_client: reqwest::Client,
-    rate_limiters: Arc<RwLock<HashMap<String, RateLimiter>>>,
+    rate_limiter: Arc<ProviderRateLimiter>,
+    providers: Arc<ProviderRegistry>,
+    agents: Arc<AgentHost>,
+    response_cache: Arc<ResponseCache>,
+    cassettes: Arc<Cassettes>,
+    tool_manager: Option<Arc<ToolManager>>,
+    // Projects directory; generated media is stored under `<project>/artifacts`
+    artifact_root: Option<PathBuf>,
     token_counter: Arc<RwLock<HashMap<String, u32>>>,
+    stream_tx: broadcast::Sender<StreamEvent>,
 }
 
 impl SimpleExecutor {
     pub fn new() -> Self {
-        let mut rate_limiters = HashMap::new();
-        rate_limiters.insert("openai".to_string(), RateLimiter::new(60));
-        rate_limiters.insert("anthropic".to_string(), RateLimiter::new(50));
-        rate_limiters.insert("ollama".to_string(), RateLimiter::new(100));
-        
         Self {
             api_keys: Arc::new(RwLock::new(HashMap::new())),
             http_client: reqwest::Client::builder()
@@ -113,11 +152,39 @@ impl SimpleExecutor {
                 .pool_idle_timeout(Duration::from_secs(90))
                 .build()
                 .unwrap(),
-            rate_limiters: Arc::new(RwLock::new(rate_limiters)),
+            rate_limiter: provider_limiter(),
+            providers: provider_registry(),
+            agents: agent_host(),
+            response_cache: response_cache(),
+            cassettes: cassettes(),
+            tool_manager: None,
+            artifact_root: None,
             token_counter: Arc::new(RwLock::new(HashMap::new())),
+            stream_tx: broadcast::channel(1024).0,
         }
     }
 
+    /// Tools the model may call from text tasks that list them.
+    pub fn with_tool_manager(mut self, tool_manager: Arc<ToolManager>) -> Self {
+        self.tool_manager = Some(tool_manager);
+        self
+    }
+
+    /// Store generated images and audio under `<projects_dir>/<project_id>/artifacts`.
+    pub fn with_artifact_root(mut self, projects_dir: PathBuf) -> Self {
+        self.artifact_root = Some(projects_dir);
+        self
+    }
+
+    fn artifact_dir(&self, task: &TaskExecution) -> PathBuf {
+        artifacts::artifact_dir(self.artifact_root.as_deref(), task.project_id.as_deref())
+    }
+
+    /// Partial output of every text call, tagged with the task ID it belongs to.
+    pub fn subscribe_stream(&self) -> broadcast::Receiver<StreamEvent> {
+        self.stream_tx.subscribe()
+    }
+
     pub async fn set_api_key(&mut self, provider: String, key: String) {
         let mut keys = self.api_keys.write().await;
         keys.insert(provider.clone(), key.clone());
@@ -145,23 +212,47 @@ impl SimpleExecutor {
         }
 
         let entry = keyring::Entry::new("supercollider", provider)?;
-        match entry.get_password() {
-            Ok(key) => Ok(key),
-            Err(_) => Err(anyhow!("No API key configured for {}", provider))
+        if let Ok(key) = entry.get_password() {
+            return Ok(key);
+        }
+        
+        self.providers.get(provider)
+            .and_then(|p| p.config().api_key_env.clone())
+            .and_then(|var| std::env::var(var).ok())
+            .ok_or_else(|| anyhow!("No API key configured for {}", provider))
+    }
+
+    /// API key for a call; replayed calls never reach the provider and need none.
+    async fn resolve_api_key(&self, provider: &str, task: &TaskExecution) -> Result<String> {
+        if self.cassettes.mode(task.project_id.as_deref()) == CassetteMode::Replay {
+            return Ok(String::new());
         }
+        self.get_api_key(provider, task.api_key.as_ref()).await
+    }
+
+    /// Send a provider request through the project's cassette when one is active.
+    async fn send(&self, task: &TaskExecution, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.cassettes.send(&self.http_client, task.project_id.as_deref(), request).await
     }
 
     pub async fn execute_task(&self, mut task: TaskExecution) -> Result<ExecutionResult> {
         let start_time = std::time::Instant::now();
         info!("Executing task {} with capability {} (attempt {})", task.task_id, task.capability, task.retry_count + 1);
+        // Usage of attempts that were thrown away; they were billed all the same
+        let mut spent: Option<TokenUsage> = None;
+        let mut discard = |res: &ExecutionResult| {
+            if let Some(usage) = &res.usage {
+                spent.get_or_insert_with(TokenUsage::default).add(usage);
+            }
+        };
         
         // First attempt with sliced context
-        let result = self.execute_with_context(&task, false).await;
-        
-        if let Ok(res) = result {
-            if res.success {
-                return Ok(res);
-            }
+        match self.execute_with_context(&task, false).await {
+            Ok(res) if res.success || Self::is_final(&res) => return self.finish(&task, res, start_time, None).await,
+            // Tools with side effects already ran; another attempt would repeat them
+            Err(e) if e.downcast_ref::<ToolsRanError>().is_some() => return Err(e),
+            Ok(res) => discard(&res),
+            Err(_) => {}
         }
         
         // If first attempt failed and we have full context, retry with full context
@@ -169,12 +260,11 @@ impl SimpleExecutor {
             warn!("First attempt failed for task {}, retrying with full context", task.task_id);
             task.retry_count += 1;
             
-            let result = self.execute_with_context(&task, true).await;
-            
-            if let Ok(res) = result {
-                if res.success {
-                    return Ok(res);
-                }
+            match self.execute_with_context(&task, true).await {
+                Ok(res) if res.success || Self::is_final(&res) => return self.finish(&task, res, start_time, spent).await,
+                Err(e) if e.downcast_ref::<ToolsRanError>().is_some() => return Err(e),
+                Ok(res) => discard(&res),
+                Err(_) => {}
             }
         }
         
@@ -186,10 +276,12 @@ impl SimpleExecutor {
                 output: None,
                 error: Some("Task failed after multiple attempts. User input required for clarification.".to_string()),
                 tool_output: None,
-                tokens_used: None,
+                tokens_used: spent.as_ref().map(|u| u.total()),
+                usage: spent,
                 execution_time_ms: Some(start_time.elapsed().as_millis() as u64),
                 needs_user_input: true,
                 retry_strategy: Some("exhausted".to_string()),
+                cache_hit: false,
             });
         }
         
@@ -205,48 +297,86 @@ impl SimpleExecutor {
         let result = retry(backoff, || async {
             self.execute_with_context(&task, false).await
                 .map_err(|e| {
+                    // A replay miss will miss again; surface it instead of retrying
+                    if e.downcast_ref::<CassetteMissError>().is_some() {
+                        return backoff::Error::Permanent(e);
+                    }
+                    if e.downcast_ref::<ToolsRanError>().is_some() {
+                        return backoff::Error::Permanent(e);
+                    }
+                    // The same prompt will not shrink on its own
+                    if matches!(e.downcast_ref::<AppError>(), Some(AppError::TokenLimitExceeded { .. })) {
+                        return backoff::Error::Permanent(e);
+                    }
                     warn!("API call failed, retrying: {}", e);
+                    // Honour the provider's Retry-After instead of the exponential schedule
+                    let retry_after = e.downcast_ref::<RateLimitedError>().map(|r| r.retry_after);
                     backoff::Error::Transient {
                         err: e,
-                        retry_after: None,
+                        retry_after,
                     }
                 })
         }).await.map_err(|e| anyhow!("All retries exhausted: {}", e))?;
         
+        self.finish(&task, result, start_time, spent).await
+    }
+    
+    /// Failed results another attempt must not repeat: the tool loop has already run
+    /// tools, which can have side effects, or the output already went through its
+    /// contract repairs or acceptance rework, which a fresh attempt would only pay for again.
+    fn is_final(result: &ExecutionResult) -> bool {
+        matches!(result.retry_strategy.as_deref(), Some("output_contract") | Some("acceptance"))
+            || result.output.as_ref().is_some_and(|o| o.get("tool_transcript").is_some())
+    }
+    
+    /// Post-process the result every attempt path returns: run the task's tool on the
+    /// output and account for time and tokens, including those of discarded attempts.
+    async fn finish(&self, task: &TaskExecution, result: ExecutionResult, start_time: std::time::Instant, spent: Option<TokenUsage>) -> Result<ExecutionResult> {
         let mut final_result = if let Some(tool) = &task.tool {
             self.apply_tool(tool, &result).await?
         } else {
             result
         };
+        if let Some(mut usage) = spent {
+            if let Some(last) = &final_result.usage {
+                usage.add(last);
+            }
+            final_result.tokens_used = Some(usage.total());
+            final_result.usage = Some(usage);
+        }
         
         final_result.execution_time_ms = Some(start_time.elapsed().as_millis() as u64);
         
-        let tokens = self.count_tokens(&task.preamble, &task.input.to_string()).await?;
+        // Keep provider-reported usage; fall back to an estimate only when there is none
+        let model = task.model.as_deref().unwrap_or_default();
+        let tokens = match final_result.tokens_used {
+            Some(tokens) => tokens,
+            None => estimate_prompt_tokens(model, &task.preamble, &task.input.to_string()),
+        };
         final_result.tokens_used = Some(tokens);
         
         let mut counter = self.token_counter.write().await;
         *counter.entry(task.task_id.clone()).or_insert(0) += tokens;
         
-        info!("Task {} completed successfully in {}ms", task.task_id, final_result.execution_time_ms.unwrap());
+        if final_result.success {
+            info!("Task {} completed successfully in {}ms", task.task_id, final_result.execution_time_ms.unwrap());
+        } else {
+            warn!("Task {} failed in {}ms: {}", task.task_id, final_result.execution_time_ms.unwrap(), final_result.error.as_deref().unwrap_or_default());
+        }
         Ok(final_result)
     }
     
     async fn execute_with_context(&self, task: &TaskExecution, use_full_context: bool) -> Result<ExecutionResult> {
-        let enhanced_task = if use_full_context && task.full_context.is_some() {
+        let mut enhanced_task = if use_full_context && task.full_context.is_some() {
             let mut enhanced = task.clone();
             
             // Build enhanced input with full context and related outputs
-            let mut enhanced_input = json!({
+            // Related outputs reach the prompt through the context assembler
+            enhanced.input = json!({
                 "original_input": task.input,
                 "full_context": task.full_context,
                 "retry_attempt": task.retry_count + 1,
             });
-            
-            if let Some(related) = &task.related_outputs {
-                enhanced_input["related_agent_outputs"] = json!(related);
-            }
-            
-            enhanced.input = enhanced_input;
             enhanced.preamble = format!(
                 "{}\n\nNote: This is retry attempt {} with full context. Previous attempt with sliced context failed. Please carefully consider all provided context and related agent outputs.",
                 task.preamble,
@@ -258,190 +388,960 @@ impl SimpleExecutor {
             task.clone()
         };
         
-        match enhanced_task.capability.as_str() {
-            "text" | "code" => self.call_text_api(&enhanced_task).await,
-            "image" => self.call_image_api(&enhanced_task).await,
-            "sound" => self.call_audio_api(&enhanced_task).await,
-            "video" => self.call_video_api(&enhanced_task).await,
-            _ => Err(anyhow!("Unknown capability: {}", enhanced_task.capability)),
+        let acceptance = enhanced_task.acceptance.take()
+            .filter(|_| matches!(enhanced_task.capability.as_str(), "text" | "code"));
+        let result = self.answer(&enhanced_task).await?;
+        match acceptance {
+            Some(spec) => self.accept(&enhanced_task, &spec, result).await,
+            None => Ok(result),
         }
     }
-
-    async fn count_tokens(&self, preamble: &str, input: &str) -> Result<u32> {
-        let bpe = p50k_base()?;
-        let combined = format!("{}\n{}", preamble, input);
-        let tokens = bpe.encode_with_special_tokens(&combined);
-        Ok(tokens.len() as u32)
+    
+    async fn answer(&self, task: &TaskExecution) -> Result<ExecutionResult> {
+        match &task.ensemble {
+            Some(spec) if matches!(task.capability.as_str(), "text" | "code") => self.run_ensemble(task, spec).await,
+            _ => self.run_single(task).await,
+        }
     }
-
-    async fn call_text_api(&self, task: &TaskExecution) -> Result<ExecutionResult> {
-        let model = task.model.as_deref().unwrap_or("gpt-4");
-        
-        if model.starts_with("gpt") || model.starts_with("o1") {
-            self.call_openai(task, model).await
-        } else if model.starts_with("claude") {
-            self.call_anthropic(task, model).await
-        } else if model.starts_with("llama") || model.starts_with("mistral") {
-            self.call_ollama(task, model).await
-        } else {
-            self.call_openai(task, model).await
+    
+    /// Score the answer against the task's acceptance criteria and rework it with the
+    /// findings while it falls short. An answer still short after the last round fails
+    /// the task; either way the evaluation is kept under `output.evaluation`.
+    async fn accept(&self, task: &TaskExecution, spec: &AcceptanceSpec, mut result: ExecutionResult) -> Result<ExecutionResult> {
+        let mut usage = result.usage.clone().unwrap_or_default();
+        let mut history = Vec::new();
+        let mut calls = Vec::new();
+        let mut rounds = 0;
+        loop {
+            if !result.success {
+                result.tokens_used = Some(usage.total());
+                result.usage = Some(usage);
+                return Ok(result);
+            }
+            let content = result.output.as_ref()
+                .and_then(|o| o["content"].as_str())
+                .unwrap_or_default()
+                .to_string();
+            let mut evaluation = self.evaluate(task, spec, &content, &mut usage, &mut calls).await?;
+            evaluation.rework_rounds = rounds;
+            evaluation.history = history.clone();
+            evaluation.calls = calls.clone();
+            
+            if evaluation.passed || rounds >= spec.max_rework() {
+                if evaluation.passed {
+                    info!("Task {} accepted with score {:.2} after {} rework rounds", task.task_id, evaluation.score, rounds);
+                } else {
+                    warn!("Task {} scored {:.2} after {} rework rounds, below {:.2}", task.task_id, evaluation.score, rounds, evaluation.threshold);
+                    result.success = false;
+                    result.error = Some(format!(
+                        "Output scored {:.2}, below the acceptance threshold of {:.2}: {}",
+                        evaluation.score,
+                        evaluation.threshold,
+                        evaluation.findings.join("; ")
+                    ));
+                    result.retry_strategy = Some("acceptance".to_string());
+                }
+                if let Some(output) = result.output.as_mut() {
+                    output["evaluation"] = json!(evaluation);
+                }
+                result.tokens_used = Some(usage.total());
+                result.usage = Some(usage);
+                return Ok(result);
+            }
+            
+            rounds += 1;
+            info!("Task {} scored {:.2}, below {:.2}; rework round {}", task.task_id, evaluation.score, evaluation.threshold, rounds);
+            history.push(json!({
+                "score": evaluation.score,
+                "findings": evaluation.findings,
+                "content": content
+            }));
+            calls.extend(Self::answer_calls(task, &result));
+            let mut rework = task.clone();
+            rework.preamble = evaluator::rework_preamble(&task.preamble, &evaluation);
+            // Chunked input is re-read from its source; the findings alone steer the rework
+            if task.chunking.is_none() {
+                rework.input = evaluator::rework_input(&task.input, &content);
+            }
+            result = self.answer(&rework).await?;
+            if let Some(call_usage) = &result.usage {
+                usage.add(call_usage);
+            }
         }
     }
-
-    async fn call_openai(&self, task: &TaskExecution, model: &str) -> Result<ExecutionResult> {
-        let limiters = self.rate_limiters.read().await;
-        if let Some(limiter) = limiters.get("openai") {
-            limiter.acquire().await?;
+    
+    /// One evaluator pass over an answer, noted in `calls`. Empty answers fail every
+    /// criterion without a call.
+    async fn evaluate(&self, task: &TaskExecution, spec: &AcceptanceSpec, content: &str, usage: &mut TokenUsage, calls: &mut Vec<Value>) -> Result<Evaluation> {
+        if content.trim().is_empty() {
+            let mut evaluation = spec.score(&json!({"scores": []}));
+            evaluation.findings = vec!["The answer is empty".to_string()];
+            return Ok(evaluation);
         }
+        let evaluator = spec.evaluator.clone().unwrap_or_default();
+        let mut eval_task = self.member_task(task, &evaluator, format!("{}#evaluate", task.task_id));
+        eval_task.preamble = spec.evaluator_preamble();
+        // An input large enough to need chunking would not fit beside the answer
+        let input = match task.chunking {
+            Some(_) => json!("(input too large to repeat; it was processed in chunks)"),
+            None => task.input.clone(),
+        };
+        eval_task.input = evaluator::evaluation_input(&task.preamble, &input, content);
+        eval_task.output_schema = Some(spec.evaluator_schema());
+        eval_task.tools = None;
+        eval_task.chunking = None;
+        eval_task.related_outputs = None;
+        eval_task.temperature = Some(0.0);
         
-        let api_key = self.get_api_key("openai", task.api_key.as_ref()).await?;
+        let verdict = self.run_single(&eval_task).await?;
+        if let Some(call_usage) = &verdict.usage {
+            usage.add(call_usage);
+        }
+        let mut call = json!({
+            "task_id": eval_task.task_id,
+            "agent": eval_task.agent,
+            "provider": eval_task.provider,
+            "model": eval_task.model,
+        });
+        Self::record_call(&mut call, &verdict);
+        calls.push(call);
+        let verdict = verdict.output
+            .filter(|_| verdict.success)
+            .and_then(|o| o.get("json").cloned())
+            .ok_or_else(|| anyhow!("Evaluator for task {} gave no verdict: {}", task.task_id, verdict.error.unwrap_or_default()))?;
+        Ok(spec.score(&verdict))
+    }
+    
+    /// One answer to a task: chunked through map-reduce when configured, then held to
+    /// its output contract.
+    async fn run_single(&self, task: &TaskExecution) -> Result<ExecutionResult> {
+        let mut task = task.clone();
+        let chunking = task.chunking.clone()
+            .filter(|_| matches!(task.capability.as_str(), "text" | "code"));
+        let base_preamble = task.preamble.clone();
+        if let Some(schema) = &task.output_schema {
+            task.preamble = format!("{}\n\n{}", task.preamble, output_contract::contract_instructions(schema));
+        }
         
-        debug!("Calling OpenAI API with model {}", model);
+        let (result, reduce_task) = match &chunking {
+            Some(spec) => self.map_reduce(&task, spec, &base_preamble).await?,
+            None => (self.dispatch(&task).await?, None),
+        };
+        // Repairs of a merged result go through the reduce step, not the oversized input
+        let map_reduce_info = result.output.as_ref().and_then(|o| o.get("map_reduce").cloned());
+        let mut result = self.enforce_output_contract(reduce_task.as_ref().unwrap_or(&task), result).await?;
+        if let (Some(info), Some(output)) = (map_reduce_info, result.output.as_mut()) {
+            output["map_reduce"] = info;
+        }
+        Ok(result)
+    }
+    
+    /// Ask every ensemble member the same task concurrently, then combine the answers
+    /// with the configured strategy. Every candidate, and the judge or merge call, is
+    /// recorded under `output.ensemble` with its own model and usage so each is priced apart.
+    async fn run_ensemble(&self, task: &TaskExecution, spec: &EnsembleSpec) -> Result<ExecutionResult> {
+        let candidate_tasks: Vec<TaskExecution> = spec.members.iter()
+            .enumerate()
+            .map(|(i, member)| self.member_task(task, member, format!("{}#candidate-{}", task.task_id, i + 1)))
+            .collect();
+        info!("Task {} asking {} ensemble members ({:?})", task.task_id, candidate_tasks.len(), spec.strategy);
+        let results = futures::future::join_all(candidate_tasks.iter().map(|t| self.run_single(t))).await;
         
-        let request_body = json!({
-            "model": model,
-            "messages": [
-                {"role": "system", "content": task.preamble},
-                {"role": "user", "content": task.input.to_string()}
-            ],
-            "temperature": 0.7,
-            "max_tokens": 4000,
-            "stream": false
+        let mut usage = TokenUsage::default();
+        let mut candidates = Vec::with_capacity(results.len());
+        // (candidate index, result) for members that answered
+        let mut answers: Vec<(usize, ExecutionResult)> = Vec::new();
+        for (i, ((t, member), result)) in candidate_tasks.iter().zip(&spec.members).zip(results).enumerate() {
+            let mut record = json!({
+                "task_id": t.task_id,
+                "member": member.label(),
+                "agent": member.agent,
+                "provider": t.provider,
+                "model": t.model,
+            });
+            match result {
+                Ok(result) => {
+                    if let Some(call_usage) = &result.usage {
+                        usage.add(call_usage);
+                    }
+                    let output = result.output.clone().unwrap_or_default();
+                    Self::record_call(&mut record, &result);
+                    record["success"] = json!(result.success);
+                    record["content"] = output["content"].clone();
+                    record["json"] = output.get("json").cloned().unwrap_or(Value::Null);
+                    record["error"] = json!(result.error);
+                    record["tokens_used"] = json!(result.tokens_used);
+                    if result.success {
+                        answers.push((i, result));
+                    }
+                }
+                Err(e) => {
+                    warn!("Ensemble candidate {} failed: {}", t.task_id, e);
+                    record["success"] = json!(false);
+                    record["error"] = json!(e.to_string());
+                }
+            }
+            candidates.push(record);
+        }
+        if answers.is_empty() {
+            return Err(anyhow!("Every ensemble member failed for task {}", task.task_id));
+        }
+        
+        let contents: Vec<String> = answers.iter()
+            .map(|(_, r)| r.output.as_ref().and_then(|o| o["content"].as_str()).unwrap_or_default().to_string())
+            .collect();
+        let mut summary = json!({
+            "strategy": spec.strategy,
+            "candidates": candidates,
         });
         
-        let response = self.http_client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+        let mut result = match spec.strategy {
+            EnsembleStrategy::Vote => {
+                let structured: Vec<Option<Value>> = answers.iter()
+                    .map(|(_, r)| r.output.as_ref().and_then(ensemble::candidate_json))
+                    .collect();
+                let vote = ensemble::vote(&structured, &spec.fields)
+                    .ok_or_else(|| anyhow!("No ensemble candidate for task {} produced JSON to vote on", task.task_id))?;
+                let valid = task.output_schema.as_ref()
+                    .is_none_or(|schema| output_contract::validate(schema, &vote.result).is_ok());
+                summary["votes"] = vote.tally.clone();
+                if valid {
+                    summary["chosen"] = json!("vote");
+                    let (_, mut result) = answers.swap_remove(vote.best);
+                    let output = result.output.get_or_insert_with(|| json!({}));
+                    output["content"] = json!(serde_json::to_string_pretty(&vote.result)?);
+                    output["json"] = vote.result;
+                    result
+                } else {
+                    // Field-wise winners can combine into an object the schema rejects
+                    warn!("Voted result for task {} fails its schema; using the candidate closest to it", task.task_id);
+                    summary["chosen"] = json!(candidate_tasks[answers[vote.best].0].task_id);
+                    summary["reason"] = json!("voted result does not match the output schema");
+                    answers.swap_remove(vote.best).1
+                }
+            }
+            EnsembleStrategy::Judge if answers.len() == 1 => {
+                summary["chosen"] = json!(candidate_tasks[answers[0].0].task_id);
+                summary["reason"] = json!("only candidate that succeeded");
+                answers.swap_remove(0).1
+            }
+            EnsembleStrategy::Judge => {
+                let schema = ensemble::judge_schema(answers.len());
+                let mut judge_task = self.member_task(task, spec.judge(), format!("{}#judge", task.task_id));
+                judge_task.preamble = ensemble::judge_preamble(answers.len());
+                judge_task.input = ensemble::review_input(&task.preamble, &task.input, &contents);
+                judge_task.output_schema = Some(schema);
+                judge_task.tools = None;
+                judge_task.chunking = None;
+                judge_task.related_outputs = None;
+                judge_task.temperature = Some(0.0);
+                let verdict = self.run_single(&judge_task).await?;
+                if let Some(call_usage) = &verdict.usage {
+                    usage.add(call_usage);
+                }
+                summary["judge"] = json!({
+                    "task_id": judge_task.task_id,
+                    "agent": spec.judge().agent,
+                    "provider": judge_task.provider,
+                    "model": judge_task.model,
+                });
+                Self::record_call(&mut summary["judge"], &verdict);
+                let verdict = verdict.output
+                    .filter(|_| verdict.success)
+                    .and_then(|o| o.get("json").cloned())
+                    .ok_or_else(|| anyhow!("Ensemble judge for task {} gave no verdict: {}", task.task_id, verdict.error.unwrap_or_default()))?;
+                let choice = verdict["choice"].as_u64().unwrap_or(1).clamp(1, answers.len() as u64) as usize - 1;
+                summary["chosen"] = json!(candidate_tasks[answers[choice].0].task_id);
+                summary["reason"] = verdict["reason"].clone();
+                answers.swap_remove(choice).1
+            }
+            EnsembleStrategy::Merge => {
+                let mut merge_task = self.member_task(task, spec.judge(), format!("{}#merge", task.task_id));
+                merge_task.preamble = ensemble::merge_preamble(&task.preamble, contents.len());
+                merge_task.input = ensemble::review_input(&task.preamble, &task.input, &contents);
+                // The candidates already drew on upstream outputs and chunks
+                merge_task.chunking = None;
+                merge_task.related_outputs = None;
+                let merged = self.run_single(&merge_task).await?;
+                if let Some(call_usage) = &merged.usage {
+                    usage.add(call_usage);
+                }
+                summary["judge"] = json!({
+                    "task_id": merge_task.task_id,
+                    "agent": spec.judge().agent,
+                    "provider": merge_task.provider,
+                    "model": merge_task.model,
+                });
+                Self::record_call(&mut summary["judge"], &merged);
+                summary["chosen"] = json!("merge");
+                merged
+            }
+        };
         
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("OpenAI API error: {}", error_text);
-            return Err(anyhow!("OpenAI API error: {}", error_text));
+        if let Some(output) = result.output.as_mut() {
+            output["ensemble"] = summary;
         }
-        
-        let response_json: Value = response.json().await?;
-        let content = response_json["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
+        result.tokens_used = Some(usage.total());
+        result.usage = Some(usage);
+        Ok(result)
+    }
+    
+    /// Note the model that answered one ensemble call and what the call used.
+    fn record_call(record: &mut Value, result: &ExecutionResult) {
+        if let Some(output) = &result.output {
+            for key in ["provider", "model"] {
+                if output[key].is_string() {
+                    record[key] = output[key].clone();
+                }
+            }
+        }
+        record["usage"] = json!(result.usage);
+        record["cache_hit"] = json!(result.cache_hit);
+    }
+    
+    /// The calls behind one answer: each ensemble call, else the answer's own call.
+    fn answer_calls(task: &TaskExecution, result: &ExecutionResult) -> Vec<Value> {
+        let ensemble = result.output.as_ref().map(|o| &o["ensemble"]).filter(|e| e.is_object());
+        if let Some(ensemble) = ensemble {
+            return ensemble["candidates"].as_array().into_iter().flatten()
+                .chain(ensemble.get("judge"))
+                .map(|call| json!({
+                    "task_id": call["task_id"],
+                    "agent": call["agent"],
+                    "provider": call["provider"],
+                    "model": call["model"],
+                    "usage": call["usage"],
+                    "cache_hit": call["cache_hit"],
+                }))
+                .collect();
+        }
+        let mut call = json!({
+            "task_id": task.task_id,
+            "agent": task.agent,
+            "provider": task.provider,
+            "model": task.model,
+        });
+        Self::record_call(&mut call, result);
+        vec![call]
+    }
+    
+    /// The task as sent to one ensemble member: its provider, model and limits.
+    fn member_task(&self, task: &TaskExecution, member: &EnsembleMember, task_id: String) -> TaskExecution {
+        let mut member_task = task.clone();
+        member_task.task_id = task_id;
+        member_task.ensemble = None;
+        member_task.acceptance = None;
+        if member.agent.is_some() {
+            member_task.agent = member.agent.clone();
+        }
+        if member.provider.is_some() || member.model.is_some() {
+            // A bare `{provider, model}` member must not run on the parent's agent
+            member_task.agent = member.agent.clone();
+            member_task.provider = member.provider.clone();
+            member_task.model = member.model.clone();
+            member_task.context_window = member.context_window;
+            member_task.max_output_tokens = member.max_output_tokens;
+            member_task.token_limit = member.token_limit;
+        }
+        member_task
+    }
+    
+    /// A derived call for part of a task: same model and settings, no tools, chunking,
+    /// contract or upstream outputs.
+    fn sub_task(&self, task: &TaskExecution, task_id: String, preamble: String, input: Value) -> TaskExecution {
+        let mut sub = task.clone();
+        sub.task_id = task_id;
+        sub.preamble = preamble;
+        sub.input = input;
+        sub.output_schema = None;
+        sub.tools = None;
+        sub.chunking = None;
+        sub.related_outputs = None;
+        sub
+    }
+    
+    /// Dispatch calls with at most `parallelism` in flight, failing on the first that fails.
+    async fn dispatch_all(&self, tasks: &[TaskExecution], parallelism: usize) -> Result<Vec<ExecutionResult>> {
+        let calls: Vec<_> = tasks.iter().map(|t| self.dispatch(t)).collect();
+        let results: Vec<Result<ExecutionResult>> = futures::stream::iter(calls)
+            .buffered(parallelism)
+            .collect()
+            .await;
+        tasks.iter()
+            .zip(results)
+            .map(|(t, result)| match result {
+                Ok(result) if result.success => Ok(result),
+                Ok(result) => Err(anyhow!("{} failed: {}", t.task_id, result.error.unwrap_or_default())),
+                Err(e) => Err(anyhow!("{} failed: {}", t.task_id, e)),
+            })
+            .collect()
+    }
+    
+    /// Run the preamble over token-bounded chunks of the input, then merge the partial
+    /// results, in rounds when they do not fit one call. Inputs that fit in one chunk run
+    /// as a normal call. Returns the final reduce call alongside its result.
+    async fn map_reduce(&self, task: &TaskExecution, spec: &ChunkingSpec, preamble: &str) -> Result<(ExecutionResult, Option<TaskExecution>)> {
+        let provider = self.providers.resolve(task.provider.as_deref(), task.model.as_deref())?;
+        let model = task.model.as_deref()
+            .or_else(|| provider.default_model())
+            .unwrap_or_default()
             .to_string();
+        let budget = ContextBudget {
+            context_window: task.context_window,
+            max_output_tokens: task.max_output_tokens,
+            token_limit: task.token_limit,
+        };
+        let upstream = task.related_outputs.as_deref().unwrap_or_default();
+        let text = map_reduce::source_text(spec, &task.input, upstream)?;
+        let chunk_tokens = spec.chunk_tokens(&model, preamble, budget.limit());
+        let chunks = split_by_tokens(&model, &text, chunk_tokens);
+        if chunks.len() <= 1 {
+            return Ok((self.dispatch(task).await?, None));
+        }
+        
+        let count = chunks.len();
+        let parallelism = spec.parallelism();
+        info!("Task {} split into {} chunks of up to {} tokens", task.task_id, count, chunk_tokens);
+        let map_tasks: Vec<TaskExecution> = chunks.iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let input = map_reduce::map_input(spec, &task.input, chunk, i, count);
+                self.sub_task(task, format!("{}#chunk-{}", task.task_id, i + 1), preamble.to_string(), input)
+            })
+            .collect();
+        
+        let mut usage = TokenUsage::default();
+        let mut sub_outputs = Vec::new();
+        let mut partials = Vec::new();
+        let mut collect = |tasks: &[TaskExecution], results: Vec<ExecutionResult>, stage: &str| {
+            let mut contents = Vec::with_capacity(results.len());
+            for (t, result) in tasks.iter().zip(results) {
+                if let Some(call_usage) = &result.usage {
+                    usage.add(call_usage);
+                }
+                let content = result.output.as_ref()
+                    .and_then(|o| o["content"].as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                sub_outputs.push(json!({
+                    "task_id": t.task_id,
+                    "stage": stage,
+                    "content": content,
+                    "tokens_used": result.tokens_used
+                }));
+                contents.push(content);
+            }
+            contents
+        };
+        let results = self.dispatch_all(&map_tasks, parallelism).await?;
+        partials.extend(collect(&map_tasks, results, "map"));
+        
+        // Merge groups of partials until they fit in one reduce call
+        let mut rounds = 0;
+        loop {
+            let groups = map_reduce::group_partials(&model, &partials, chunk_tokens);
+            // Every partial alone fills a call; another round cannot shrink them
+            if groups.len() <= 1 || groups.len() == partials.len() {
+                break;
+            }
+            rounds += 1;
+            debug!("Task {} reduce round {}: {} partials in {} groups", task.task_id, rounds, partials.len(), groups.len());
+            let reduce_tasks: Vec<TaskExecution> = groups.iter()
+                .enumerate()
+                .map(|(i, group)| {
+                    let preamble = map_reduce::reduce_preamble(spec, preamble, group.len());
+                    let input = map_reduce::reduce_input(spec, &task.input, group);
+                    self.sub_task(task, format!("{}#reduce-{}-{}", task.task_id, rounds, i + 1), preamble, input)
+                })
+                .collect();
+            let results = self.dispatch_all(&reduce_tasks, parallelism).await?;
+            partials = collect(&reduce_tasks, results, "reduce");
+        }
         
-        let usage = response_json["usage"]["total_tokens"].as_u64().unwrap_or(0) as u32;
+        let mut reduce_preamble = map_reduce::reduce_preamble(spec, preamble, partials.len());
+        if let Some(schema) = &task.output_schema {
+            reduce_preamble = format!("{}\n\n{}", reduce_preamble, output_contract::contract_instructions(schema));
+        }
+        let mut reduce_task = self.sub_task(
+            task,
+            task.task_id.clone(),
+            reduce_preamble,
+            map_reduce::reduce_input(spec, &task.input, &partials),
+        );
+        reduce_task.output_schema = task.output_schema.clone();
+        // Other upstream outputs still inform the merge; the chunked one is already in it
+        reduce_task.related_outputs = task.related_outputs.as_ref().map(|outputs| {
+            outputs.iter()
+                .filter(|o| spec.source.as_deref().is_none_or(|source| o["task_id"].as_str() != Some(source)))
+                .cloned()
+                .collect()
+        });
         
+        let mut result = self.dispatch(&reduce_task).await?;
+        if let Some(call_usage) = &result.usage {
+            usage.add(call_usage);
+        }
+        if let Some(output) = result.output.as_mut() {
+            output["map_reduce"] = json!({
+                "source": spec.source.as_deref().unwrap_or("input"),
+                "chunks": count,
+                "chunk_tokens": chunk_tokens,
+                "parallelism": parallelism,
+                "reduce_rounds": rounds + 1,
+                "sub_outputs": sub_outputs
+            });
+        }
+        result.tokens_used = Some(usage.total());
+        result.usage = Some(usage);
+        Ok((result, Some(reduce_task)))
+    }
+    
+    async fn dispatch(&self, task: &TaskExecution) -> Result<ExecutionResult> {
+        if let Some(agent) = task.agent.as_deref().filter(|a| self.agents.hosts(a)) {
+            return self.call_hosted_agent(task, agent).await;
+        }
+        match task.capability.as_str() {
+            "text" | "code" => self.call_text_api(task).await,
+            "image" => self.call_image_api(task).await,
+            "sound" => self.call_audio_api(task).await,
+            "video" => self.call_video_api(task).await,
+            _ => Err(anyhow!("Unknown capability: {}", task.capability)),
+        }
+    }
+    
+    /// Send the task to an agent the app hosts, such as a local subprocess. Its output
+    /// is read like a provider's: a string, or an object whose `content` is the answer.
+    async fn call_hosted_agent(&self, task: &TaskExecution, agent: &str) -> Result<ExecutionResult> {
+        let request = AgentRequest {
+            task_id: task.task_id.clone(),
+            task_type: task.task_type.clone().unwrap_or_else(|| task.capability.clone()),
+            capability: serde_json::from_value::<Capability>(json!(task.capability))?,
+            input: task.input.clone(),
+            preamble: task.preamble.clone(),
+            // 0 when the task has no limit
+            token_limit: task.token_limit.unwrap_or(0),
+            context: task.related_outputs.clone().unwrap_or_default(),
+        };
+        // WebSocket agents send partial output as they go; mirror it onto the task's stream
+        let forwarder = self.agents.subscribe(agent).map(|mut events| {
+            let mut stream = StreamAccumulator::start(&self.stream_tx, &task.task_id, "agent", agent);
+            let task_id = task.task_id.clone();
+            tokio::spawn(async move {
+                loop {
+                    match events.recv().await {
+                        Ok(WsAgentEvent::PartialOutput { task_id: id, delta, .. }) if id == task_id => match delta {
+                            Value::String(text) => stream.push(&text),
+                            other => stream.push(&other.to_string()),
+                        },
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            })
+        });
+        let response = self.agents.execute(agent, request).await;
+        if let Some(forwarder) = forwarder {
+            forwarder.abort();
+        }
+        let response = response?;
+        
+        let mut output = match response.output {
+            Some(Value::String(content)) => json!({ "content": content }),
+            Some(output @ Value::Object(_)) if output["content"].is_string() => output,
+            Some(other) => json!({ "content": serde_json::to_string_pretty(&other)?, "result": other }),
+            None => json!({ "content": "" }),
+        };
+        output["agent"] = json!(agent);
         Ok(ExecutionResult {
-            success: true,
-            output: Some(json!({
-                "type": "text",
-                "content": content,
-                "model": model,
-                "provider": "openai"
-            })),
-            error: None,
+            success: response.success,
+            output: Some(output),
+            error: response.error,
             tool_output: None,
-            tokens_used: Some(usage),
-            execution_time_ms: None,
+            tokens_used: response.tokens_used,
+            usage: None,
+            execution_time_ms: Some(response.execution_time_ms),
             needs_user_input: false,
             retry_strategy: None,
+            cache_hit: false,
         })
     }
+    
+    /// Extract and validate JSON output against the task's schema, asking the model
+    /// to repair it with the validation errors when it does not conform.
+    async fn enforce_output_contract(&self, task: &TaskExecution, mut result: ExecutionResult) -> Result<ExecutionResult> {
+        let schema = match &task.output_schema {
+            Some(schema) if result.success => schema,
+            _ => return Ok(result),
+        };
+        
+        let mut repairs = 0;
+        loop {
+            let content = result.output.as_ref()
+                .and_then(|o| o["content"].as_str())
+                .unwrap_or("")
+                .to_string();
+            
+            let errors = match output_contract::extract_json(&content) {
+                Some(parsed) => match output_contract::validate(schema, &parsed) {
+                    Ok(()) => {
+                        if let Some(output) = result.output.as_mut() {
+                            output["json"] = parsed;
+                            output["repairs"] = json!(repairs);
+                        }
+                        return Ok(result);
+                    }
+                    Err(errors) => errors,
+                },
+                None => vec!["Output does not contain a JSON value".to_string()],
+            };
+            
+            if repairs >= output_contract::MAX_REPAIR_ATTEMPTS {
+                warn!("Task {} output failed its contract after {} repairs", task.task_id, repairs);
+                return Ok(ExecutionResult {
+                    success: false,
+                    output: result.output,
+                    error: Some(format!("Output does not match schema: {}", errors.join("; "))),
+                    tool_output: None,
+                    tokens_used: result.tokens_used,
+                    usage: result.usage,
+                    execution_time_ms: None,
+                    needs_user_input: false,
+                    retry_strategy: Some("output_contract".to_string()),
+                    cache_hit: result.cache_hit,
+                });
+            }
+            
+            repairs += 1;
+            debug!("Repairing output of task {} (attempt {}): {:?}", task.task_id, repairs, errors);
+            let mut repair_task = task.clone();
+            repair_task.input = output_contract::repair_input(&task.input, &content, &errors);
+            
+            let previous_tokens = result.tokens_used.unwrap_or(0);
+            let previous_usage = result.usage.take();
+            result = self.dispatch(&repair_task).await?;
+            result.tokens_used = Some(previous_tokens + result.tokens_used.unwrap_or(0));
+            if let Some(mut usage) = previous_usage {
+                if let Some(repair_usage) = &result.usage {
+                    usage.add(repair_usage);
+                }
+                result.usage = Some(usage);
+            }
+        }
+    }
 
-    async fn call_anthropic(&self, task: &TaskExecution, model: &str) -> Result<ExecutionResult> {
-        let limiters = self.rate_limiters.read().await;
-        if let Some(limiter) = limiters.get("anthropic") {
-            limiter.acquire().await?;
+    /// Record a 429 with the limiter so later calls and the scheduler back off.
+    fn rate_limited(&self, provider: &str, api_key: Option<&str>, response: &reqwest::Response) -> RateLimitedError {
+        let retry_after = parse_retry_after(response.headers(), Duration::from_secs(20));
+        self.rate_limiter.throttle(provider, api_key, retry_after);
+        RateLimitedError {
+            provider: provider.to_string(),
+            retry_after,
         }
+    }
+
+    async fn call_text_api(&self, task: &TaskExecution) -> Result<ExecutionResult> {
+        let provider = self.providers.resolve(task.provider.as_deref(), task.model.as_deref())?;
+        let model = task.model.as_deref()
+            .or_else(|| provider.default_model())
+            .ok_or_else(|| anyhow!("No model set for task and provider {} has no default", provider.name()))?
+            .to_string();
         
-        let api_key = self.get_api_key("anthropic", task.api_key.as_ref()).await?;
+        let temperature = task.temperature.unwrap_or(0.7);
+        let budget = ContextBudget {
+            context_window: task.context_window,
+            max_output_tokens: task.max_output_tokens,
+            token_limit: task.token_limit,
+        };
+        let upstream = task.related_outputs.as_deref().unwrap_or_default();
+        let context = context_assembler::assemble(&model, &task.preamble, &task.input, upstream, &budget)?;
+        let max_tokens = context.max_tokens;
+        if !context.report.truncated.is_empty() || !context.report.dropped.is_empty() {
+            info!(
+                "Task {} context cut to fit {} tokens: truncated {:?}, dropped {:?}",
+                task.task_id, context.report.limit, context.report.truncated, context.report.dropped
+            );
+        }
         
-        debug!("Calling Anthropic API with model {}", model);
+        // Tool calls have side effects, so this path never touches the response cache
+        if task.tools.as_ref().is_some_and(|tools| !tools.is_empty()) {
+            return self.call_with_tools(task, provider, &model, temperature, &context).await;
+        }
         
-        let request_body = json!({
-            "model": model,
-            "max_tokens": 4000,
-            "messages": [
-                {"role": "user", "content": format!("{}\n\n{}", task.preamble, task.input)}
-            ]
+        // Cache hits would leave holes in a recording and mask replay misses
+        let cacheable = self.response_cache.cacheable(temperature, task.cache)
+            && self.cassettes.mode(task.project_id.as_deref()) == CassetteMode::Off;
+        let cache_key = cacheable.then(|| {
+            CacheKeyInput {
+                provider: provider.name(),
+                model: &model,
+                preamble: &task.preamble,
+                input: &task.input,
+                full_context: task.full_context.as_ref(),
+                related_outputs: task.related_outputs.as_ref(),
+                output_schema: task.output_schema.as_ref(),
+                temperature,
+                max_tokens,
+            }
+            .key()
         });
+        if let Some(cached) = cache_key.as_deref().and_then(|key| self.response_cache.get(key)) {
+            info!("Task {} served from response cache", task.task_id);
+            let content = cached.output["content"].as_str().unwrap_or("");
+            StreamAccumulator::start(&self.stream_tx, &task.task_id, provider.name(), &model).replay(content);
+            
+            let mut output = cached.output.clone();
+            output["cache_key"] = json!(cached.key);
+            output["cached_at"] = json!(cached.created_at);
+            return Ok(ExecutionResult {
+                success: true,
+                output: Some(output),
+                error: None,
+                tool_output: None,
+                tokens_used: cached.usage.as_ref().map(|u| u.total()),
+                usage: cached.usage,
+                execution_time_ms: None,
+                needs_user_input: false,
+                retry_strategy: None,
+                cache_hit: true,
+            });
+        }
         
-        let response = self.http_client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+        let api_key = match provider.config().auth_style {
+            AuthStyle::None => None,
+            _ => Some(self.resolve_api_key(provider.name(), task).await?),
+        };
+        let prompt_estimate = context.report.prompt_tokens;
+        let estimate = prompt_estimate + max_tokens;
+        self.rate_limiter.acquire(provider.name(), api_key.as_deref(), estimate).await;
+        
+        debug!("Calling provider {} with model {}", provider.name(), model);
+        
+        let request = TextRequest {
+            model: model.clone(),
+            system: task.preamble.clone(),
+            user: context.user.clone(),
+            max_tokens,
+            temperature,
+            json_schema: task.output_schema.clone(),
+            context_window: task.context_window,
+        };
+        let response = self
+            .send(task, provider.text_request(&self.http_client, api_key.as_deref(), &request)?)
             .await?;
         
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("Anthropic API error: {}", error_text);
-            return Err(anyhow!("Anthropic API error: {}", error_text));
+        let response = self.check_response(provider.name(), api_key.as_deref(), response).await?;
+        
+        let mut stream = StreamAccumulator::start(&self.stream_tx, &task.task_id, provider.name(), &model);
+        provider.read_text_stream(response, &mut stream).await?;
+        
+        // Some OpenAI-compatible servers and older Ollama builds omit usage; estimate locally then
+        let usage = TokenUsage {
+            estimated_prompt_tokens: prompt_estimate,
+            estimated_completion_tokens: stream.usage.completion_tokens
+                .is_none()
+                .then(|| estimate_tokens(&model, &stream.content)),
+            prompt_tokens: stream.usage.prompt_tokens,
+            completion_tokens: stream.usage.completion_tokens,
+        };
+        if !stream.usage.is_empty() {
+            self.rate_limiter.record_usage(provider.name(), api_key.as_deref(), estimate, stream.usage.total());
         }
         
-        let response_json: Value = response.json().await?;
-        let content = response_json["content"][0]["text"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
+        if stream.content.trim().is_empty() {
+            return Err(anyhow!("{} returned an empty response for task {}", provider.name(), task.task_id));
+        }
         
-        let usage = response_json["usage"]["total_tokens"].as_u64().unwrap_or(0) as u32;
+        let mut output = json!({
+            "type": "text",
+            "content": stream.content,
+            "model": model,
+            "provider": provider.name()
+        });
+        if !context.report.truncated.is_empty() || !context.report.dropped.is_empty() {
+            output["context"] = json!(context.report);
+        }
+        if let Some(key) = &cache_key {
+            self.response_cache.put(key, &output, Some(&usage));
+        }
         
         Ok(ExecutionResult {
             success: true,
-            output: Some(json!({
-                "type": "text",
-                "content": content,
-                "model": model,
-                "provider": "anthropic"
-            })),
+            output: Some(output),
             error: None,
             tool_output: None,
-            tokens_used: Some(usage),
+            tokens_used: Some(usage.total()),
+            usage: Some(usage),
             execution_time_ms: None,
             needs_user_input: false,
             retry_strategy: None,
+            cache_hit: false,
         })
     }
 
-    async fn call_ollama(&self, task: &TaskExecution, model: &str) -> Result<ExecutionResult> {
-        let limiters = self.rate_limiters.read().await;
-        if let Some(limiter) = limiters.get("ollama") {
-            limiter.acquire().await?;
+    /// Pass through a successful response; record 429s and turn other failures into errors.
+    async fn check_response(&self, provider: &str, api_key: Option<&str>, response: reqwest::Response) -> Result<reqwest::Response> {
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.rate_limited(provider, api_key, &response).into());
         }
         
-        debug!("Calling Ollama API with model {}", model);
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("{} API error ({}): {}", provider, status, error_text);
+            return Err(anyhow!("{} API error ({}): {}", provider, status, error_text));
+        }
+        Ok(response)
+    }
+
+    async fn chat_turn(&self, task: &TaskExecution, provider: &dyn Provider, api_key: Option<&str>, request: &ChatRequest) -> Result<ChatTurn> {
+        let response = self
+            .send(task, provider.chat_request(&self.http_client, api_key, request)?)
+            .await?;
+        let response = self.check_response(provider.name(), api_key, response).await?;
+        provider.parse_chat_turn(&response.json().await?)
+    }
+    
+    /// Let the model call the task's tools, feeding each result back, until it answers
+    /// without a tool call or runs out of steps. The transcript is kept in the output.
+    async fn call_with_tools(
+        &self,
+        task: &TaskExecution,
+        provider: Arc<dyn Provider>,
+        model: &str,
+        temperature: f32,
+        context: &AssembledContext,
+    ) -> Result<ExecutionResult> {
+        if !provider.supports_tools() {
+            return Err(anyhow!("Provider {} does not support tool calling", provider.name()));
+        }
+        let tool_manager = self.tool_manager.as_ref()
+            .ok_or_else(|| anyhow!("Task {} lists tools but no tool manager is configured", task.task_id))?;
+        let tools = task.tools.iter()
+            .flatten()
+            .map(|id| {
+                tool_manager.get_tool(id)
+                    .filter(|tool| tool.is_available)
+                    .ok_or_else(|| anyhow!("Tool '{}' is not available", id))
+            })
+            .collect::<Result<Vec<Tool>>>()?;
         
-        let request_body = json!({
-            "model": model,
-            "prompt": format!("{}\n\n{}", task.preamble, task.input),
-            "stream": false,
-            "options": {
-                "temperature": 0.7,
-                "num_predict": 4000
-            }
-        });
+        let api_key = match provider.config().auth_style {
+            AuthStyle::None => None,
+            _ => Some(self.resolve_api_key(provider.name(), task).await?),
+        };
+        let max_steps = task.max_tool_steps.unwrap_or(tool_calling::DEFAULT_MAX_TOOL_STEPS);
+        let mut request = ChatRequest {
+            model: model.to_string(),
+            system: task.preamble.clone(),
+            messages: vec![ChatMessage::User { content: context.user.clone() }],
+            functions: tools.iter().map(tool_calling::function_spec).collect(),
+            max_tokens: context.max_tokens,
+            temperature,
+            context_window: task.context_window,
+        };
+        let mut usage = TokenUsage::default();
+        let mut steps = 0;
         
-        let response = self.http_client
-            .post("http://localhost:11434/api/generate")
-            .json(&request_body)
-            .send()
-            .await?;
+        let content = loop {
+            let transcript = serde_json::to_string(&request.messages)?;
+            let prompt_estimate = estimate_prompt_tokens(model, &request.system, &transcript);
+            let estimate = prompt_estimate + request.max_tokens;
+            self.rate_limiter.acquire(provider.name(), api_key.as_deref(), estimate).await;
+            
+            debug!("Tool-calling turn {} for task {} on {}", steps + 1, task.task_id, provider.name());
+            let turn = match self.chat_turn(task, provider.as_ref(), api_key.as_deref(), &request).await {
+                Ok(turn) => turn,
+                Err(e) if steps > 0 => return Err(ToolsRanError { steps, message: e.to_string() }.into()),
+                Err(e) => return Err(e),
+            };
+            
+            if !turn.usage.is_empty() {
+                self.rate_limiter.record_usage(provider.name(), api_key.as_deref(), estimate, turn.usage.total());
+            }
+            usage.add(&TokenUsage {
+                estimated_prompt_tokens: prompt_estimate,
+                estimated_completion_tokens: turn.usage.completion_tokens
+                    .is_none()
+                    .then(|| estimate_tokens(model, &turn.content)),
+                prompt_tokens: turn.usage.prompt_tokens,
+                completion_tokens: turn.usage.completion_tokens,
+            });
+            request.messages.push(ChatMessage::Assistant {
+                content: turn.content.clone(),
+                tool_calls: turn.tool_calls.clone(),
+            });
+            
+            if turn.tool_calls.is_empty() {
+                break turn.content;
+            }
+            if steps >= max_steps {
+                warn!("Task {} still calling tools after {} steps", task.task_id, steps);
+                return Ok(ExecutionResult {
+                    success: false,
+                    output: Some(json!({
+                        "type": "text",
+                        "content": turn.content,
+                        "model": model,
+                        "provider": provider.name(),
+                        "tool_steps": steps,
+                        "tool_transcript": request.messages
+                    })),
+                    error: Some(format!("Tool calling did not finish within {} steps", max_steps)),
+                    tool_output: None,
+                    tokens_used: Some(usage.total()),
+                    usage: Some(usage),
+                    execution_time_ms: None,
+                    needs_user_input: false,
+                    retry_strategy: Some("max_tool_steps".to_string()),
+                    cache_hit: false,
+                });
+            }
+            steps += 1;
+            
+            for call in turn.tool_calls {
+                let tool = tools.iter().find(|tool| tool_calling::function_name(&tool.id) == call.name);
+                let result = match tool {
+                    Some(tool) => match tool_calling::tool_execution(tool, &call.arguments) {
+                        Ok(execution) => {
+                            info!("Task {} calling tool {} {:?}", task.task_id, tool.id, execution.arguments);
+                            tool_manager.execute_tool(execution).await
+                        }
+                        Err(e) => Err(e),
+                    },
+                    None => Err(anyhow!("Unknown tool '{}'", call.name)),
+                };
+                request.messages.push(ChatMessage::Tool {
+                    call_id: call.id,
+                    name: call.name,
+                    content: tool_calling::result_message(&result),
+                });
+            }
+        };
         
-        if !response.status().is_success() {
-            error!("Ollama not running or model not available");
-            return Err(anyhow!("Ollama not running or model not available"));
+        // Tools have already run, so an empty answer fails the task; `is_final` keeps it from being retried
+        if content.trim().is_empty() {
+            return Ok(ExecutionResult {
+                success: false,
+                output: Some(json!({
+                    "type": "text",
+                    "content": content,
+                    "model": model,
+                    "provider": provider.name(),
+                    "tool_steps": steps,
+                    "tool_transcript": request.messages
+                })),
+                error: Some(format!("{} returned an empty answer after {} tool steps", provider.name(), steps)),
+                tool_output: None,
+                tokens_used: Some(usage.total()),
+                usage: Some(usage),
+                execution_time_ms: None,
+                needs_user_input: false,
+                retry_strategy: Some("empty_response".to_string()),
+                cache_hit: false,
+            });
         }
         
-        let response_json: Value = response.json().await?;
-        let content = response_json["response"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        
-        let tokens = self.count_tokens(&task.preamble, &content).await?;
+        StreamAccumulator::start(&self.stream_tx, &task.task_id, provider.name(), model).replay(&content);
         
         Ok(ExecutionResult {
             success: true,
@@ -449,24 +1349,31 @@ impl SimpleExecutor {
                 "type": "text",
                 "content": content,
                 "model": model,
-                "provider": "ollama"
+                "provider": provider.name(),
+                "tool_steps": steps,
+                "tool_transcript": request.messages
             })),
             error: None,
             tool_output: None,
-            tokens_used: Some(tokens),
+            tokens_used: Some(usage.total()),
+            usage: Some(usage),
             execution_time_ms: None,
             needs_user_input: false,
             retry_strategy: None,
+            cache_hit: false,
         })
     }
 
     async fn call_image_api(&self, task: &TaskExecution) -> Result<ExecutionResult> {
-        let limiters = self.rate_limiters.read().await;
-        if let Some(limiter) = limiters.get("openai") {
-            limiter.acquire().await?;
+        if let Some(name) = task.provider.as_deref() {
+            let provider = self.providers.resolve(Some(name), None)?;
+            if provider.config().kind != ProviderKind::OpenAi {
+                return self.call_image_provider(task, provider).await;
+            }
         }
         
-        let api_key = self.get_api_key("openai", task.api_key.as_ref()).await?;
+        let api_key = self.resolve_api_key("openai", task).await?;
+        self.rate_limiter.acquire("openai", Some(&api_key), 0).await;
         
         debug!("Calling DALL-E 3 for image generation");
         
@@ -478,13 +1385,16 @@ impl SimpleExecutor {
             "quality": "standard"
         });
         
-        let response = self.http_client
+        let request = self.http_client
             .post("https://api.openai.com/v1/images/generations")
             .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+            .json(&request_body);
+        let response = self.send(task, request).await?;
+        
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.rate_limited("openai", Some(&api_key), &response).into());
+        }
         
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -495,32 +1405,117 @@ impl SimpleExecutor {
         let response_json: Value = response.json().await?;
         let image_url = response_json["data"][0]["url"]
             .as_str()
-            .unwrap_or("")
+            .ok_or_else(|| anyhow!("Image API returned no image URL"))?
             .to_string();
         
+        // The URL expires within hours; keep the image with the project instead
+        let download = self.send(task, self.http_client.get(&image_url)).await?.error_for_status()?;
+        let content_type = download.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let bytes = download.bytes().await?;
+        let mut output = artifacts::save_image(&self.artifact_dir(task), &task.task_id, content_type.as_deref(), &bytes)?;
+        output["source_url"] = json!(image_url);
+        output["provider"] = json!("dall-e-3");
+        
         Ok(ExecutionResult {
             success: true,
-            output: Some(json!({
-                "type": "image",
-                "url": image_url,
-                "provider": "dall-e-3"
-            })),
+            output: Some(output),
             error: None,
             tool_output: None,
             tokens_used: Some(100),
+            usage: None,
             execution_time_ms: None,
             needs_user_input: false,
             retry_strategy: None,
+            cache_hit: false,
         })
     }
 
+    /// Image generation through a configured provider such as a local Stable Diffusion server.
+    async fn call_image_provider(&self, task: &TaskExecution, provider: Arc<dyn Provider>) -> Result<ExecutionResult> {
+        if !provider.supports(&Capability::Image) {
+            return Err(anyhow!("Provider {} is not configured for image generation", provider.name()));
+        }
+        let api_key = match provider.config().auth_style {
+            AuthStyle::None => None,
+            _ => Some(self.resolve_api_key(provider.name(), task).await?),
+        };
+        
+        let mut request = ImageRequest::from_input(task.model.clone(), &task.preamble, &task.input)?;
+        if let Some(reference) = task.input["init_image"].as_str() {
+            request.init_image = Some(self.init_image(task, reference)?);
+        }
+        self.rate_limiter.acquire(provider.name(), api_key.as_deref(), 0).await;
+        
+        debug!(
+            "Generating {} image(s) at {}x{} with provider {}",
+            request.batch_count, request.width, request.height, provider.name()
+        );
+        let response = self
+            .send(task, provider.image_request(&self.http_client, api_key.as_deref(), &request)?)
+            .await?;
+        let response = self.check_response(provider.name(), api_key.as_deref(), response).await?;
+        let images = provider.parse_images(&response.json().await?)?;
+        if images.is_empty() {
+            return Err(anyhow!("Provider {} returned no images", provider.name()));
+        }
+        
+        let dir = self.artifact_dir(task);
+        let mut saved = Vec::with_capacity(images.len());
+        for (i, image) in images.iter().enumerate() {
+            let stem = if images.len() == 1 { task.task_id.clone() } else { format!("{}-{}", task.task_id, i + 1) };
+            let mut entry = artifacts::save_image(&dir, &stem, None, &image.bytes)?;
+            entry["seed"] = json!(image.seed);
+            saved.push(entry);
+        }
+        
+        // The first image stands for the task; the rest of a batch rides along
+        let mut output = saved[0].clone();
+        output["provider"] = json!(provider.name());
+        output["prompt"] = json!(request.prompt);
+        if saved.len() > 1 {
+            output["images"] = json!(saved);
+        }
+        
+        Ok(ExecutionResult {
+            success: true,
+            output: Some(output),
+            error: None,
+            tool_output: None,
+            tokens_used: Some(0),
+            usage: None,
+            execution_time_ms: None,
+            needs_user_input: false,
+            retry_strategy: None,
+            cache_hit: false,
+        })
+    }
+    
+    /// Base64 source image for img2img: an upstream task ID whose output is an image, or a path.
+    fn init_image(&self, task: &TaskExecution, reference: &str) -> Result<String> {
+        let upstream = task.related_outputs.as_ref()
+            .and_then(|outputs| outputs.iter().find(|o| o["task_id"].as_str() == Some(reference)))
+            .and_then(|o| o["output"]["path"].as_str());
+        let path = upstream.unwrap_or(reference);
+        let bytes = std::fs::read(path)
+            .map_err(|e| anyhow!("Cannot read init image {}: {}", path, e))?;
+        Ok(BASE64.encode(bytes))
+    }
+
     async fn call_audio_api(&self, task: &TaskExecution) -> Result<ExecutionResult> {
-        let limiters = self.rate_limiters.read().await;
-        if let Some(limiter) = limiters.get("openai") {
-            limiter.acquire().await?;
+        if let Some(name) = task.provider.as_deref() {
+            let provider = self.providers.resolve(Some(name), None)?;
+            match provider.config().kind {
+                ProviderKind::OpenAi => {}
+                ProviderKind::LocalTts => return self.call_local_speech(task, provider).await,
+                _ => return Err(anyhow!("Provider {} cannot generate speech", provider.name())),
+            }
         }
         
-        let api_key = self.get_api_key("openai", task.api_key.as_ref()).await?;
+        let api_key = self.resolve_api_key("openai", task).await?;
+        self.rate_limiter.acquire("openai", Some(&api_key), 0).await;
         
         debug!("Calling OpenAI TTS for audio generation");
         
@@ -530,13 +1525,16 @@ impl SimpleExecutor {
             "voice": "alloy"
         });
         
-        let response = self.http_client
+        let request = self.http_client
             .post("https://api.openai.com/v1/audio/speech")
             .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+            .json(&request_body);
+        let response = self.send(task, request).await?;
+        
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.rate_limited("openai", Some(&api_key), &response).into());
+        }
         
         if !response.status().is_success() {
             error!("Audio generation failed");
@@ -544,27 +1542,69 @@ impl SimpleExecutor {
         }
         
         let audio_bytes = response.bytes().await?;
-        let temp_path = std::env::temp_dir().join(format!("{}.mp3", task.task_id));
-        std::fs::write(&temp_path, audio_bytes)?;
+        let mut output = artifacts::save_audio(&self.artifact_dir(task), &task.task_id, "mp3", &audio_bytes).await?;
+        output["provider"] = json!("openai-tts");
         
         Ok(ExecutionResult {
             success: true,
-            output: Some(json!({
-                "type": "audio",
-                "path": temp_path.to_string_lossy(),
-                "provider": "openai-tts"
-            })),
+            output: Some(output),
             error: None,
             tool_output: None,
             tokens_used: Some(50),
+            usage: None,
+            execution_time_ms: None,
+            needs_user_input: false,
+            retry_strategy: None,
+            cache_hit: false,
+        })
+    }
+
+    /// Speech from a TTS engine installed on this machine; no network involved.
+    async fn call_local_speech(&self, task: &TaskExecution, provider: Arc<dyn Provider>) -> Result<ExecutionResult> {
+        let tool_manager = self.tool_manager.as_ref()
+            .ok_or_else(|| anyhow!("Local speech needs the tool manager, which is not configured"))?;
+        let default_voice = task.model.as_deref().or_else(|| provider.default_model());
+        let spec = SpeechSpec::from_input(&task.input, default_voice)?;
+        
+        debug!("Synthesizing {} characters for task {}", spec.text.chars().count(), task.task_id);
+        let output = speech::synthesize(tool_manager, &spec, &self.artifact_dir(task), &task.task_id).await?;
+        
+        Ok(ExecutionResult {
+            success: true,
+            output: Some(output),
+            error: None,
+            tool_output: None,
+            tokens_used: Some(0),
+            usage: None,
             execution_time_ms: None,
             needs_user_input: false,
             retry_strategy: None,
+            cache_hit: false,
         })
     }
 
-    async fn call_video_api(&self, _task: &TaskExecution) -> Result<ExecutionResult> {
-        Err(anyhow!("Video generation not yet implemented"))
+    /// Compose upstream images, audio and subtitles into a video with the local ffmpeg.
+    async fn call_video_api(&self, task: &TaskExecution) -> Result<ExecutionResult> {
+        let tool_manager = self.tool_manager.as_ref()
+            .ok_or_else(|| anyhow!("Video composition needs the tool manager, which is not configured"))?;
+        let upstream = task.related_outputs.as_deref().unwrap_or_default();
+        let storyboard = Storyboard::from_task(&task.input, upstream).await?;
+        
+        debug!("Composing {} shots for task {}", storyboard.shots.len(), task.task_id);
+        let output = video::compose(tool_manager, &storyboard, &self.artifact_dir(task), &task.task_id).await?;
+        
+        Ok(ExecutionResult {
+            success: true,
+            output: Some(output),
+            error: None,
+            tool_output: None,
+            tokens_used: Some(0),
+            usage: None,
+            execution_time_ms: None,
+            needs_user_input: false,
+            retry_strategy: None,
+            cache_hit: false,
+        })
     }
 
     async fn apply_tool(&self, tool: &ToolConfig, result: &ExecutionResult) -> Result<ExecutionResult> {
@@ -576,8 +1616,8 @@ impl SimpleExecutor {
         
         let content = match output["type"].as_str() {
             Some("text") => output["content"].as_str().unwrap_or(""),
-            Some("image") => output["url"].as_str().unwrap_or(""),
-            Some("audio") => output["path"].as_str().unwrap_or(""),
+            Some("image") => output["path"].as_str().or_else(|| output["url"].as_str()).unwrap_or(""),
+            Some("audio") | Some("video") => output["path"].as_str().unwrap_or(""),
             _ => return Ok(result.clone()),
         };
         
@@ -622,9 +1662,11 @@ impl SimpleExecutor {
                     error: None,
                     tool_output: Some(tool_output),
                     tokens_used: result.tokens_used,
+                    usage: result.usage.clone(),
                     execution_time_ms: result.execution_time_ms,
                     needs_user_input: false,
                     retry_strategy: None,
+                    cache_hit: result.cache_hit,
                 })
             } else {
                 let error = String::from_utf8_lossy(&output.stderr).to_string();
@@ -648,9 +1690,11 @@ impl SimpleExecutor {
                     error: None,
                     tool_output: Some(tool_output),
                     tokens_used: result.tokens_used,
+                    usage: result.usage.clone(),
                     execution_time_ms: result.execution_time_ms,
                     needs_user_input: false,
                     retry_strategy: None,
+                    cache_hit: result.cache_hit,
                 })
             } else {
                 Ok(result.clone())
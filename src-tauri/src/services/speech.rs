@@ -0,0 +1,261 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use tracing::debug;
+use crate::models::tool::ToolExecution;
+use super::artifacts::{self, file_stem};
+use super::tool_manager::ToolManager;
+
+// Piper and eSpeak both degrade on very long inputs; sentences are packed up to this size
+const MAX_CHUNK_CHARS: usize = 800;
+const FORMATS: [&str; 5] = ["mp3", "wav", "ogg", "flac", "opus"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtsEngine {
+    Piper,
+    EspeakNg,
+}
+
+impl TtsEngine {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "piper" => Ok(Self::Piper),
+            "espeak" | "espeak-ng" => Ok(Self::EspeakNg),
+            other => Err(anyhow!("Unknown TTS engine '{}'; expected piper or espeak-ng", other)),
+        }
+    }
+
+    pub fn tool_id(&self) -> &'static str {
+        match self {
+            Self::Piper => "piper",
+            Self::EspeakNg => "espeak-ng",
+        }
+    }
+
+    /// Arguments writing one chunk, read from stdin, to a WAV file.
+    fn arguments(&self, spec: &SpeechSpec, output: &Path) -> Result<Vec<String>> {
+        let output = output.to_string_lossy().into_owned();
+        let mut args = Vec::new();
+        match self {
+            Self::Piper => {
+                let voice = spec
+                    .voice
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Piper needs a voice model (.onnx); set voice on the task or default_model on the provider"))?;
+                args.extend(["--model".to_string(), voice.clone(), "--output_file".to_string(), output]);
+                // Piper stretches phoneme length rather than speeding up
+                args.extend(["--length_scale".to_string(), format!("{:.3}", 1.0 / spec.speed)]);
+                if let Some(speaker) = spec.speaker {
+                    args.extend(["--speaker".to_string(), speaker.to_string()]);
+                }
+            }
+            Self::EspeakNg => {
+                if let Some(voice) = &spec.voice {
+                    args.extend(["-v".to_string(), voice.clone()]);
+                }
+                // 175 words per minute is eSpeak's default rate
+                args.extend(["-s".to_string(), ((175.0 * spec.speed).round() as u32).to_string()]);
+                args.extend(["-w".to_string(), output, "--stdin".to_string()]);
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// What a Sound task asks to be spoken and how.
+#[derive(Debug, Clone)]
+pub struct SpeechSpec {
+    pub text: String,
+    pub voice: Option<String>,
+    pub speaker: Option<u32>,
+    pub speed: f32,
+    pub format: String,
+    pub engine: Option<TtsEngine>,
+}
+
+impl SpeechSpec {
+    /// Read a task input: the text itself, or an object with `text` (or `script`), `voice`
+    /// (or `model`), `speaker`, `speed`, `format` and `engine`.
+    pub fn from_input(input: &Value, default_voice: Option<&str>) -> Result<Self> {
+        let text = match input {
+            Value::String(text) => text.clone(),
+            _ => input["text"]
+                .as_str()
+                .or_else(|| input["script"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+        };
+        if text.trim().is_empty() {
+            return Err(anyhow!("Sound task has no text to speak"));
+        }
+
+        let format = input["format"].as_str().unwrap_or("mp3").to_lowercase();
+        if !FORMATS.contains(&format.as_str()) {
+            return Err(anyhow!("Unsupported audio format '{}'; expected one of {}", format, FORMATS.join(", ")));
+        }
+
+        Ok(Self {
+            text,
+            voice: input["voice"]
+                .as_str()
+                .or_else(|| input["model"].as_str())
+                .or(default_voice)
+                .map(|s| s.to_string()),
+            speaker: input["speaker"].as_u64().map(|s| s as u32),
+            speed: input["speed"].as_f64().map_or(1.0, |s| s as f32).clamp(0.25, 4.0),
+            format,
+            engine: input["engine"].as_str().map(TtsEngine::parse).transpose()?,
+        })
+    }
+}
+
+/// Split text at sentence ends into chunks of at most `max_chars`, breaking overlong
+/// sentences at whitespace.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        current.push(c);
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    sentences.push(current);
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for sentence in sentences.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        for word in split_long(sentence, max_chars) {
+            if !chunk.is_empty() && chunk.chars().count() + word.chars().count() + 1 > max_chars {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            if !chunk.is_empty() {
+                chunk.push(' ');
+            }
+            chunk.push_str(&word);
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+fn split_long(sentence: &str, max_chars: usize) -> Vec<String> {
+    if sentence.chars().count() <= max_chars {
+        return vec![sentence.to_string()];
+    }
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    for word in sentence.split_whitespace() {
+        if !part.is_empty() && part.chars().count() + word.chars().count() + 1 > max_chars {
+            parts.push(std::mem::take(&mut part));
+        }
+        if !part.is_empty() {
+            part.push(' ');
+        }
+        part.push_str(word);
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+async fn run(tool_manager: &ToolManager, tool_id: &str, arguments: Vec<String>, stdin: Option<String>, outputs: Vec<PathBuf>) -> Result<()> {
+    debug!("Running {} {:?}", tool_id, arguments);
+    let result = tool_manager
+        .execute_tool(ToolExecution {
+            tool_id: tool_id.to_string(),
+            command: tool_id.to_string(),
+            arguments,
+            input_files: Vec::new(),
+            output_files: outputs,
+            parameters: Default::default(),
+            stdin_data: stdin,
+            expected_exit_codes: Vec::new(),
+            capture_stdout: true,
+            capture_stderr: true,
+            timeout_override: None,
+        })
+        .await?;
+    if !result.success {
+        let stderr: Vec<&str> = result.stderr.lines().rev().take(20).collect();
+        return Err(anyhow!(
+            "{} failed with exit code {}: {}",
+            tool_id,
+            result.exit_code,
+            stderr.into_iter().rev().collect::<Vec<_>>().join("\n")
+        ));
+    }
+    Ok(())
+}
+
+fn available(tool_manager: &ToolManager, tool_id: &str) -> bool {
+    tool_manager.get_tool(tool_id).is_some_and(|tool| tool.is_available)
+}
+
+/// Speak the text with a local TTS engine, one chunk at a time, then join and encode the
+/// chunks with ffmpeg. Describes the result as a task output.
+pub async fn synthesize(tool_manager: &ToolManager, spec: &SpeechSpec, dir: &Path, task_id: &str) -> Result<Value> {
+    let engine = match spec.engine {
+        Some(engine) if available(tool_manager, engine.tool_id()) => engine,
+        Some(engine) => return Err(anyhow!("TTS engine {} was not detected", engine.tool_id())),
+        None => [TtsEngine::Piper, TtsEngine::EspeakNg]
+            .into_iter()
+            .find(|engine| available(tool_manager, engine.tool_id()))
+            .ok_or_else(|| anyhow!("No local TTS engine detected; install piper or espeak-ng"))?,
+    };
+
+    let stem = file_stem(task_id);
+    let work_dir = dir.join(format!("{}-chunks", stem));
+    fs::create_dir_all(&work_dir)?;
+    let chunks = chunk_text(&spec.text, MAX_CHUNK_CHARS);
+    let mut wavs = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let wav = work_dir.join(format!("{:04}.wav", i));
+        run(tool_manager, engine.tool_id(), engine.arguments(spec, &wav)?, Some(chunk.clone()), vec![wav.clone()]).await?;
+        wavs.push(wav);
+    }
+
+    let output = dir.join(format!("{}.{}", stem, spec.format));
+    if wavs.len() == 1 && spec.format == "wav" {
+        fs::rename(&wavs[0], &output)?;
+    } else {
+        if !available(tool_manager, "ffmpeg") {
+            return Err(anyhow!("Joining or encoding speech needs ffmpeg, which was not detected"));
+        }
+        // Concat demuxer list; single quotes inside paths are closed, escaped and reopened
+        let list: String = wavs
+            .iter()
+            .map(|wav| format!("file '{}'\n", wav.to_string_lossy().replace('\'', "'\\''")))
+            .collect();
+        let list_path = work_dir.join("concat.txt");
+        fs::write(&list_path, list)?;
+        let arguments = vec![
+            "-y".to_string(),
+            "-f".to_string(),
+            "concat".to_string(),
+            "-safe".to_string(),
+            "0".to_string(),
+            "-i".to_string(),
+            list_path.to_string_lossy().into_owned(),
+            output.to_string_lossy().into_owned(),
+        ];
+        run(tool_manager, "ffmpeg", arguments, None, vec![output.clone()]).await?;
+    }
+    let _ = fs::remove_dir_all(&work_dir);
+
+    Ok(json!({
+        "type": "audio",
+        "path": output.to_string_lossy(),
+        "format": spec.format,
+        "duration_secs": artifacts::media_duration(&output).await,
+        "bytes": fs::metadata(&output)?.len(),
+        "chunks": chunks.len(),
+        "voice": spec.voice,
+        "speed": spec.speed,
+        "provider": engine.tool_id()
+    }))
+}
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+/// Incremental output of a provider call, broadcast to the runner and the UI.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A new attempt began; listeners should discard earlier partial content
+    Started { task_id: String, provider: String, model: String },
+    /// `offset` is the byte length of the content before this delta, so a listener can
+    /// tell when it missed some
+    Delta { task_id: String, delta: String, offset: usize },
+    /// Carries the full content for listeners that fell behind
+    Finished { task_id: String, usage: StreamUsage, content: String },
+}
+
+impl StreamEvent {
+    pub fn task_id(&self) -> &str {
+        match self {
+            StreamEvent::Started { task_id, .. }
+            | StreamEvent::Delta { task_id, .. }
+            | StreamEvent::Finished { task_id, .. } => task_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamUsage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u32>,
+}
+
+impl StreamUsage {
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens.unwrap_or(0) + self.completion_tokens.unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prompt_tokens.is_none() && self.completion_tokens.is_none()
+    }
+}
+
+/// Collects streamed deltas into the final content while forwarding each one to listeners.
+pub struct StreamAccumulator {
+    task_id: String,
+    tx: broadcast::Sender<StreamEvent>,
+    pub content: String,
+    pub usage: StreamUsage,
+}
+
+impl StreamAccumulator {
+    pub fn start(tx: &broadcast::Sender<StreamEvent>, task_id: &str, provider: &str, model: &str) -> Self {
+        // Nobody listening is fine; send only fails without receivers
+        let _ = tx.send(StreamEvent::Started {
+            task_id: task_id.to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+        });
+        Self {
+            task_id: task_id.to_string(),
+            tx: tx.clone(),
+            content: String::new(),
+            usage: StreamUsage::default(),
+        }
+    }
+
+    /// Emit a complete response in one delta, e.g. one served from the response cache.
+    pub fn replay(mut self, content: &str) {
+        self.push(content);
+        self.finish();
+    }
+
+    pub fn push(&mut self, delta: &str) {
+        if delta.is_empty() {
+            return;
+        }
+        let offset = self.content.len();
+        self.content.push_str(delta);
+        let _ = self.tx.send(StreamEvent::Delta {
+            task_id: self.task_id.clone(),
+            delta: delta.to_string(),
+            offset,
+        });
+    }
+
+    pub fn finish(&self) {
+        let _ = self.tx.send(StreamEvent::Finished {
+            task_id: self.task_id.clone(),
+            usage: self.usage.clone(),
+            content: self.content.clone(),
+        });
+    }
+}
+
+pub fn as_u32(value: &Value) -> Option<u32> {
+    value.as_u64().map(|v| v as u32)
+}
+
+/// OpenAI chat completions SSE; usage arrives in the last chunk when `include_usage` is set.
+pub async fn read_openai(response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+    let mut events = response.bytes_stream().eventsource();
+    while let Some(event) = events.next().await {
+        let event = event.map_err(|e| anyhow!("OpenAI stream error: {}", e))?;
+        if event.data.trim() == "[DONE]" {
+            acc.finish();
+            return Ok(());
+        }
+
+        let chunk: Value = serde_json::from_str(&event.data)?;
+        if let Some(error) = chunk.get("error") {
+            return Err(anyhow!("OpenAI API error: {}", error));
+        }
+        if let Some(delta) = chunk["choices"][0]["delta"]["content"].as_str() {
+            acc.push(delta);
+        }
+        if chunk["usage"].is_object() {
+            acc.usage.prompt_tokens = as_u32(&chunk["usage"]["prompt_tokens"]);
+            acc.usage.completion_tokens = as_u32(&chunk["usage"]["completion_tokens"]);
+        }
+    }
+    Err(anyhow!("OpenAI stream ended before completion"))
+}
+
+/// Anthropic messages SSE; input tokens come with `message_start`, output tokens with `message_delta`.
+pub async fn read_anthropic(response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+    let mut events = response.bytes_stream().eventsource();
+    while let Some(event) = events.next().await {
+        let event = event.map_err(|e| anyhow!("Anthropic stream error: {}", e))?;
+        let data: Value = match serde_json::from_str(&event.data) {
+            Ok(data) => data,
+            Err(_) => continue, // ping events may carry no JSON body
+        };
+
+        match data["type"].as_str().unwrap_or(event.event.as_str()) {
+            "message_start" => {
+                let usage = &data["message"]["usage"];
+                acc.usage.prompt_tokens = as_u32(&usage["input_tokens"]);
+                acc.usage.completion_tokens = as_u32(&usage["output_tokens"]);
+            }
+            "content_block_delta" => {
+                if let Some(text) = data["delta"]["text"].as_str() {
+                    acc.push(text);
+                }
+            }
+            "message_delta" => {
+                if let Some(output) = as_u32(&data["usage"]["output_tokens"]) {
+                    acc.usage.completion_tokens = Some(output);
+                }
+            }
+            "message_stop" => {
+                acc.finish();
+                return Ok(());
+            }
+            "error" => {
+                let message = data["error"]["message"].as_str().unwrap_or("unknown error");
+                return Err(anyhow!("Anthropic API error: {}", message));
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("Anthropic stream ended before completion"))
+}
+
+/// Ollama newline-delimited JSON; the final object has `done: true` and the eval counts.
+pub async fn read_ollama(response: reqwest::Response, acc: &mut StreamAccumulator) -> Result<()> {
+    let mut bytes = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = bytes.next().await {
+        buffer.extend_from_slice(&chunk?);
+
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let data: Value = serde_json::from_str(line.trim())?;
+            if let Some(error) = data["error"].as_str() {
+                return Err(anyhow!("Ollama error: {}", error));
+            }
+            // /api/generate streams `response`, /api/chat streams `message.content`
+            if let Some(delta) = data["response"].as_str().or_else(|| data["message"]["content"].as_str()) {
+                acc.push(delta);
+            }
+            if data["done"].as_bool() == Some(true) {
+                acc.usage.prompt_tokens = as_u32(&data["prompt_eval_count"]);
+                acc.usage.completion_tokens = as_u32(&data["eval_count"]);
+                acc.finish();
+                return Ok(());
+            }
+        }
+    }
+    Err(anyhow!("Ollama stream ended before completion"))
+}
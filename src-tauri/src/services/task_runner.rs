@@ -1,27 +1,65 @@
 use std::sync::Arc;
 use std::collections::HashMap;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, oneshot, RwLock};
 use serde_json::{json, Value};
 use anyhow::Result;
-use crate::models::{ProjectStatus, TaskStatus};
+use tauri::Manager;
+use tracing::{debug, warn};
+use chrono::{DateTime, Utc};
+use crate::models::{lookup_model, Capability, CircuitBreaker, ProjectStatus, TaskStatus, TokenUsage};
 use crate::state::AppState;
-use super::simple_executor::{SimpleExecutor, TaskExecution, ToolConfig};
+use super::simple_executor::{ExecutionResult, SimpleExecutor, TaskExecution, ToolConfig};
+use super::agent_host::{agent_host, preferred_agents};
+use super::fair_share::FairShare;
+use super::providers::provider_registry;
+use super::rate_limiter::provider_limiter;
+use super::context_pool::{ContextEntry, ContextType};
+use super::streaming::StreamEvent;
+use super::map_reduce::ChunkingSpec;
+use super::ensemble::{EnsembleMember, EnsembleSpec};
+use super::evaluator::AcceptanceSpec;
+use super::tool_manager::ToolManager;
+use super::usage::{record_usage, UsageRecord};
+use super::tool_calling::ToolsRanError;
+
+// How often a task held back by a throttled provider looks again
+const THROTTLE_RECHECK: std::time::Duration = std::time::Duration::from_secs(1);
 
 pub struct TaskRunner {
     executor: Arc<RwLock<SimpleExecutor>>,
     state: Arc<AppState>,
+    fair_share: Arc<FairShare>,
     running_tasks: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
+    app_handle: Option<tauri::AppHandle>,
 }
 
 impl TaskRunner {
     pub fn new(state: Arc<AppState>) -> Self {
+        // Same TOOLS/tool_definitions.json the shredder advertises to the model
+        let tools_root = std::env::current_dir().unwrap_or_default();
+        let executor = match ToolManager::new(tools_root) {
+            Ok(tool_manager) => SimpleExecutor::new().with_tool_manager(Arc::new(tool_manager)),
+            Err(e) => {
+                warn!("Tool manager unavailable, tasks cannot call tools: {}", e);
+                SimpleExecutor::new()
+            }
+        }
+        .with_artifact_root(state.storage.get_base_path().join("projects"));
         Self {
-            executor: Arc::new(RwLock::new(SimpleExecutor::new())),
+            executor: Arc::new(RwLock::new(executor)),
+            fair_share: Arc::new(FairShare::new(Arc::clone(&state))),
             state,
             running_tasks: Arc::new(RwLock::new(HashMap::new())),
+            app_handle: None,
         }
     }
     
+    /// Emit streamed output to the frontend as `task-stream:<task_id>` events.
+    pub fn with_app_handle(mut self, app_handle: tauri::AppHandle) -> Self {
+        self.app_handle = Some(app_handle);
+        self
+    }
+    
     pub async fn set_api_key(&self, provider: String, key: String) {
         let mut executor = self.executor.write().await;
         executor.set_api_key(provider, key).await;
@@ -81,45 +119,102 @@ impl TaskRunner {
     pub async fn run_task(&self, project_id: String, task: Value) -> Result<()> {
         let task_id = task["task_id"].as_str().unwrap_or("").to_string();
         
+        // Concurrent projects share the agents; hold a slot until the task is settled
+        self.wait_for_provider(&task).await;
+        let _slot = self.fair_share.acquire(&project_id).await;
+        
         // Update task status to running
         self.update_task_status(&project_id, &task_id, TaskStatus::Running).await;
         
-        // Build task execution request
-        let execution = TaskExecution {
-            task_id: task_id.clone(),
-            preamble: task["preamble"].as_str().unwrap_or("").to_string(),
-            input: task["input"].clone(),
-            capability: task["capability"].as_str().unwrap_or("text").to_string(),
-            tool: self.extract_tool_config(&task),
-            api_key: None, // Will use default from executor
-            model: task["model"].as_str().map(|s| s.to_string()),
-            max_retries: None,
-            timeout_secs: None,
-            full_context: None,
-            related_outputs: None,
-            retry_count: 0,
-            requires_user_input: false,
-        };
-        
-        // Execute the task
-        let executor = self.executor.clone();
-        let result = executor.read().await.execute_task(execution).await;
+        // Walk the agent fallback chain; the first agent to succeed produces the output
+        let chain = self.agent_chain(&project_id, &task);
+        let last = chain.len() - 1;
+        let mut attempts = Vec::new();
+        let mut outcome = None;
+        for (i, agent) in chain.into_iter().enumerate() {
+            // Resolve provider, model and limits as if the task named this agent
+            let mut attempt = task.clone();
+            if let Some(name) = &agent {
+                attempt["metadata"]["agent"] = json!(name);
+            }
+            let execution = self.build_execution(&project_id, &task_id, &attempt);
+            if let Some(name) = &agent {
+                self.with_breaker(name, |breaker, now| breaker.on_dispatch(now));
+            }
+            let started = std::time::Instant::now();
+            let result = self.execute_streamed(&project_id, &task_id, execution).await;
+            let error = match &result {
+                Ok(r) if r.success => None,
+                Ok(r) => Some(r.error.clone().unwrap_or_else(|| "Unknown error".to_string())),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(name) = &agent {
+                let latency_ms = started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+                self.with_breaker(name, |breaker, now| breaker.record(error.is_none(), latency_ms, now));
+            }
+            // Tools with side effects already ran; another agent would repeat them
+            let tools_ran = matches!(&result, Err(e) if e.downcast_ref::<ToolsRanError>().is_some());
+            if error.is_some() && !tools_ran && i < last {
+                warn!("Agent {} failed task {}, trying next in chain",
+                    agent.as_deref().unwrap_or("default"), task_id);
+                // The discarded attempt was billed all the same
+                if let Ok(r) = &result {
+                    self.record_run_usage(&project_id, &task_id, &attempt, r);
+                }
+                attempts.push(json!({ "agent": agent, "success": false, "error": error }));
+                continue;
+            }
+            attempts.push(json!({ "agent": agent, "success": error.is_none(), "error": error }));
+            outcome = Some((attempt, agent, result));
+            break;
+        }
+        let (task, agent, result) = outcome.expect("agent chain is never empty");
         
         match result {
             Ok(execution_result) => {
+                // Failed runs were billed too; price the calls before the output moves into the task
+                let usage_records = self.record_run_usage(&project_id, &task_id, &task, &execution_result);
+                let priced = !usage_records.is_empty();
+                let cost_usd = priced.then(|| usage_records.iter().map(|r| r.cost_usd).sum::<f64>());
+                let saved_cost_usd = priced.then(|| usage_records.iter().map(|r| r.saved_cost_usd).sum::<f64>());
                 if execution_result.success {
                     // Store output
+                    let evaluation = execution_result.output.as_ref().and_then(|o| o.get("evaluation").cloned());
+                    if let Some(output) = &execution_result.output {
+                        self.store_output_context(&project_id, &task_id, &task, output);
+                    }
                     self.update_task_output(&project_id, &task_id, execution_result.output).await;
                     self.update_task_status(&project_id, &task_id, TaskStatus::Completed).await;
-                    // Increment oneshot if task was not user_edited and had no prior retries/errors
+                    // Increment oneshot if task was not user_edited, had no prior retries/errors
+                    // and, when it has acceptance criteria, passed them without rework
+                    let first_pass = evaluation.as_ref()
+                        .is_none_or(|e| e["passed"].as_bool() == Some(true) && e["rework_rounds"].as_u64() == Some(0));
                     {
                         let mut tasks = self.state.tasks.write();
                         if let Some(project_tasks) = tasks.get_mut(&project_id) {
                             if let Some(task) = project_tasks.iter_mut().find(|t| t.id == task_id) {
-                                if !task.user_edited && task.retry_count == 0 && task.error.is_none() {
+                                if !task.user_edited && task.retry_count == 0 && task.error.is_none() && first_pass {
                                     task.oneshot_count = task.oneshot_count.saturating_add(1);
                                 }
-                                // No agent info in simple runner; leave last_agent as-is
+                                task.evaluation = evaluation;
+                                task.token_usage = execution_result.usage.clone();
+                                task.cost_usd = cost_usd;
+                                // Per-task record of the last run's cache outcome; `cache` stays the user's switch
+                                let cache_result = json!({
+                                    "hit": execution_result.cache_hit,
+                                    "saved_cost_usd": saved_cost_usd,
+                                    "at": chrono::Utc::now(),
+                                });
+                                match task.metadata.as_mut().and_then(|m| m.as_object_mut()) {
+                                    Some(metadata) => {
+                                        metadata.insert("response_cache".to_string(), cache_result);
+                                    }
+                                    None => task.metadata = Some(json!({ "response_cache": cache_result })),
+                                }
+                                if let Some(metadata) = task.metadata.as_mut().and_then(|m| m.as_object_mut()) {
+                                    metadata.insert("agent_attempts".to_string(), json!(attempts));
+                                }
+                                task.last_agent = agent.clone();
                                 let _ = self.state.storage.save_json(
                                     &format!("task_{}_{}.json", project_id, task_id),
                                     &task,
@@ -128,7 +223,9 @@ impl TaskRunner {
                         }
                     }
                 } else {
-                    // Store error
+                    // Store error, and the scores behind it when the output failed acceptance
+                    let evaluation = execution_result.output.as_ref().and_then(|o| o.get("evaluation").cloned());
+                    self.update_task_failure(&project_id, &task_id, evaluation, &execution_result, cost_usd);
                     self.update_task_error(&project_id, &task_id, execution_result.error).await;
                     self.update_task_status(&project_id, &task_id, TaskStatus::Failed).await;
                 }
@@ -159,9 +256,67 @@ impl TaskRunner {
         if let Some(handle) = running.remove(task_id) {
             handle.abort();
         }
+        // Aborting only drops our side; tell a WebSocket agent to stop too
+        agent_host().cancel(task_id);
         Ok(())
     }
     
+    fn forward_stream(
+        &self,
+        project_id: String,
+        task_id: String,
+        mut events: broadcast::Receiver<StreamEvent>,
+        mut stop: oneshot::Receiver<()>,
+    ) -> tokio::task::JoinHandle<()> {
+        let state = Arc::clone(&self.state);
+        let app_handle = self.app_handle.clone();
+        
+        tokio::spawn(async move {
+            loop {
+                // Deliver everything already queued before honouring the stop signal
+                let event = tokio::select! {
+                    biased;
+                    event = events.recv() => match event {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Stream listener for task {} skipped {} events", task_id, skipped);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = &mut stop => break,
+                };
+                
+                if event.task_id() != task_id {
+                    continue;
+                }
+                
+                {
+                    let mut tasks = state.tasks.write();
+                    if let Some(task) = tasks.get_mut(&project_id)
+                        .and_then(|project_tasks| project_tasks.iter_mut().find(|t| t.id == task_id))
+                    {
+                        match &event {
+                            StreamEvent::Started { .. } => task.partial_output = Some(String::new()),
+                            StreamEvent::Delta { delta, offset, .. } => {
+                                let partial = task.partial_output.get_or_insert_with(String::new);
+                                // After skipped events, keep the intact prefix until `Finished` resyncs
+                                if partial.len() == *offset {
+                                    partial.push_str(delta);
+                                }
+                            }
+                            StreamEvent::Finished { content, .. } => task.partial_output = Some(content.clone()),
+                        }
+                    }
+                }
+                
+                if let Some(app) = &app_handle {
+                    let _ = app.emit_all(&format!("task-stream:{}", task_id), &event);
+                }
+            }
+        })
+    }
+    
     async fn check_dependencies(&self, project_id: &str, task: &Value) -> bool {
         let dependencies = task["dependencies"]
             .as_array()
@@ -188,6 +343,315 @@ impl TaskRunner {
         true
     }
     
+    /// Outputs of the task's dependencies, as `{task_id, capability, output}`.
+    fn upstream_outputs(&self, project_id: &str, task: &Value) -> Option<Vec<Value>> {
+        let mut outputs: Vec<Value> = Vec::new();
+        for dep_id in Self::dependencies(task) {
+            let in_memory = self.state.tasks.read()
+                .get(project_id)
+                .and_then(|project_tasks| project_tasks.iter().find(|t| t.id == dep_id))
+                .and_then(|t| t.output.as_ref().map(|output| json!({
+                    "task_id": t.id,
+                    "capability": t.capability,
+                    "output": output,
+                })));
+            // Outputs are not reloaded into tasks after a restart; the context pool keeps them
+            let stored = || self.stored_outputs(project_id, dep_id).pop().map(|entry| json!({
+                "task_id": dep_id,
+                "capability": entry.metadata.get("capability").cloned().unwrap_or(Value::Null),
+                "output": entry.content,
+            }));
+            outputs.extend(in_memory.or_else(stored));
+        }
+        // Clarification answers apply to every task of the project
+        for entry in self.state.context_pool.get_project_context(project_id) {
+            if entry.content["type"] == "clarification" {
+                outputs.push(json!({ "clarification": entry.content["answers"] }));
+            }
+        }
+        (!outputs.is_empty()).then_some(outputs)
+    }
+    
+    fn dependencies(task: &Value) -> impl Iterator<Item = &str> {
+        task["dependencies"].as_array().into_iter().flatten().filter_map(|dep| dep.as_str())
+    }
+    
+    /// Output entries a task stored in the context pool, oldest first.
+    fn stored_outputs(&self, project_id: &str, task_id: &str) -> Vec<ContextEntry> {
+        self.state.context_pool.get_task_context(task_id)
+            .into_iter()
+            .filter(|e| e.project_id == project_id && matches!(e.content_type, ContextType::TaskOutput))
+            .collect()
+    }
+    
+    /// Keep a completed task's output in the context pool in place of its previous
+    /// run's, referencing the outputs it built on.
+    fn store_output_context(&self, project_id: &str, task_id: &str, task: &Value, output: &Value) {
+        let pool = &self.state.context_pool;
+        for previous in self.stored_outputs(project_id, task_id) {
+            if let Err(e) = pool.remove_context(&previous.id) {
+                warn!("Failed to replace stored output of task {}: {}", task_id, e);
+            }
+        }
+        let references = Self::dependencies(task)
+            .flat_map(|dep_id| self.stored_outputs(project_id, dep_id))
+            .map(|entry| entry.id)
+            .collect();
+        let now = chrono::Utc::now();
+        let entry = ContextEntry {
+            id: format!("ctx-{}", uuid::Uuid::new_v4()),
+            project_id: project_id.to_string(),
+            task_id: task_id.to_string(),
+            content_type: ContextType::TaskOutput,
+            content: output.clone(),
+            metadata: HashMap::from([
+                ("capability".to_string(), task["capability"].clone()),
+                ("agent".to_string(), json!(self.task_agent(task))),
+            ]),
+            created_at: now,
+            updated_at: now,
+            references,
+            // Cache residency only; the entry itself is persisted
+            ttl_seconds: Some(3600),
+        };
+        if let Err(e) = pool.add_context(entry) {
+            warn!("Failed to store output of task {} in the context pool: {}", task_id, e);
+        }
+    }
+    
+    /// Provider named on the task, else the one configured on its agent.
+    fn resolve_provider(&self, task: &Value) -> Option<String> {
+        let explicit = task["provider"].as_str()
+            .or_else(|| task["metadata"]["provider"].as_str());
+        if let Some(provider) = explicit {
+            return Some(provider.to_string());
+        }
+        
+        let agent_name = self.task_agent(task)?;
+        self.state.agents.read()
+            .iter()
+            .find(|a| a.name == agent_name)
+            .and_then(|a| a.provider.clone())
+    }
+    
+    /// Hold the task back while its provider is in a 429 penalty window, so it does not
+    /// sit on a fair-share slot another project's task could use.
+    async fn wait_for_provider(&self, task: &Value) {
+        let provider = match provider_registry().resolve(self.resolve_provider(task).as_deref(), self.resolve_model(task).as_deref()) {
+            Ok(provider) => provider.name().to_string(),
+            Err(_) => return,
+        };
+        let limiter = provider_limiter();
+        while limiter.is_throttled(&provider) {
+            debug!("Deferring task {} while {} is throttled", task["task_id"], provider);
+            tokio::time::sleep(THROTTLE_RECHECK).await;
+        }
+    }
+    
+    /// The task's model, else the one its agent is bound to.
+    fn resolve_model(&self, task: &Value) -> Option<String> {
+        if let Some(model) = task["model"].as_str() {
+            return Some(model.to_string());
+        }
+        let agent_name = self.task_agent(task)?;
+        self.state.agents.read()
+            .iter()
+            .find(|a| a.name == agent_name)
+            .and_then(|a| a.model.clone())
+    }
+    
+    /// Smaller of the task's and its agent's token limits, unless the config says to
+    /// ignore them.
+    fn token_limit(&self, task: &Value) -> Option<u32> {
+        let agent_limit = self.task_agent(task).and_then(|name| {
+            self.state.agents.read().iter().find(|a| a.name == name).and_then(|a| a.token_limit)
+        });
+        self.limit_for(task, agent_limit)
+    }
+    
+    /// The smaller of the task's and the agent's token limits; none when the config
+    /// says to ignore token limits.
+    fn limit_for(&self, task: &Value, agent_limit: Option<u32>) -> Option<u32> {
+        if self.state.config.read().ignore_task_token_limits {
+            return None;
+        }
+        match (task["token_limit"].as_u64().map(|l| l as u32), agent_limit) {
+            (Some(task), Some(agent)) => Some(task.min(agent)),
+            (limit, None) | (None, limit) => limit,
+        }
+    }
+    
+    /// Ensemble members from `metadata.ensemble`, with agent names resolved to their
+    /// provider, model and limits.
+    fn resolve_ensemble(&self, task: &Value) -> Option<EnsembleSpec> {
+        let mut spec = EnsembleSpec::from_metadata(&task["metadata"]["ensemble"], |name| self.resolve_member(task, name))?;
+        for member in spec.members.iter_mut().chain(spec.judge.iter_mut()) {
+            self.complete_member(task, member);
+        }
+        Some(spec)
+    }
+    
+    /// Acceptance criteria from `metadata.acceptance`, with the evaluator resolved like
+    /// an ensemble member.
+    fn resolve_acceptance(&self, task: &Value) -> Option<AcceptanceSpec> {
+        let mut spec = AcceptanceSpec::from_metadata(&task["metadata"]["acceptance"], |name| self.resolve_member(task, name))?;
+        if let Some(evaluator) = spec.evaluator.as_mut() {
+            self.complete_member(task, evaluator);
+        }
+        Some(spec)
+    }
+    
+    /// An enabled agent as a model to call, limited by the smaller of its and the task's
+    /// token limits.
+    fn resolve_member(&self, task: &Value, name: &str) -> Option<EnsembleMember> {
+        let agents = self.state.agents.read();
+        let agent = agents.iter().find(|a| a.name == name && a.enabled)?;
+        Some(EnsembleMember {
+            agent: Some(agent.name.clone()),
+            provider: agent.provider.clone(),
+            model: agent.model.clone(),
+            token_limit: self.limit_for(task, agent.token_limit),
+            ..Default::default()
+        })
+    }
+    
+    /// Fill a member's window and output cap from the model registry; members given as
+    /// `{provider, model}` take the task's token limit.
+    fn complete_member(&self, task: &Value, member: &mut EnsembleMember) {
+        if let Some(info) = member.model.as_deref().and_then(|m| lookup_model(&self.state.config.read().models, m).cloned()) {
+            member.context_window = member.context_window.or(Some(info.context_window));
+            member.max_output_tokens = member.max_output_tokens.or(Some(info.max_output_tokens));
+        }
+        if member.agent.is_none() {
+            member.token_limit = member.token_limit.or_else(|| self.limit_for(task, None));
+        }
+    }
+    
+    /// Agents to try in turn: the one the task names, then the configured priorities for
+    /// its capability, then any other enabled agent with that capability by priority.
+    /// Agents whose circuit breaker is open are skipped; a task pinned to a provider or
+    /// model runs once as it is.
+    fn agent_chain(&self, project_id: &str, task: &Value) -> Vec<Option<String>> {
+        let pinned = task["model"].is_string()
+            || task["provider"].is_string()
+            || task["metadata"]["provider"].is_string();
+        let capability = serde_json::from_value::<Capability>(task["capability"].clone()).ok();
+        let capability = match capability {
+            Some(capability) if !pinned => capability,
+            _ => return vec![self.task_agent(task)],
+        };
+        
+        let now = chrono::Utc::now();
+        let agents = self.state.agents.read();
+        // A named agent leads the chain unless its breaker is open
+        let mut chain: Vec<String> = task["metadata"]["agent"].as_str()
+            .filter(|name| agents.iter().find(|a| a.name == *name).is_none_or(|a| a.health.breaker.admits(now)))
+            .map(|s| s.to_string())
+            .into_iter()
+            .collect();
+        let mut capable: Vec<_> = agents.iter()
+            .filter(|a| a.enabled && a.capabilities.contains(&capability) && a.health.breaker.admits(now))
+            .collect();
+        for name in preferred_agents(&self.state, project_id, &capability) {
+            if capable.iter().any(|a| a.name == name) && !chain.contains(&name) {
+                chain.push(name);
+            }
+        }
+        capable.sort_by_key(|a| std::cmp::Reverse(a.priority));
+        for agent in capable {
+            if !chain.contains(&agent.name) {
+                chain.push(agent.name.clone());
+            }
+        }
+        
+        if chain.is_empty() {
+            return vec![self.task_agent(task)];
+        }
+        chain.into_iter().map(Some).collect()
+    }
+    
+    /// Update the agent's circuit breaker and the health derived from it, so live
+    /// runs trip and recover agents the same way agent tests do.
+    fn with_breaker(&self, agent: &str, update: impl FnOnce(&mut CircuitBreaker, DateTime<Utc>)) {
+        let now = Utc::now();
+        let mut agents = self.state.agents.write();
+        if let Some(agent) = agents.iter_mut().find(|a| a.name == agent) {
+            update(&mut agent.health.breaker, now);
+            agent.health.error_rate = agent.health.breaker.failure_rate();
+            agent.health.status = agent.health.breaker.health_status();
+        }
+    }
+    
+    fn build_execution(&self, project_id: &str, task_id: &str, task: &Value) -> TaskExecution {
+        let model = self.resolve_model(task);
+        let model_info = model.as_deref().and_then(|m| lookup_model(&self.state.config.read().models, m).cloned());
+        TaskExecution {
+            task_id: task_id.to_string(),
+            project_id: Some(project_id.to_string()),
+            preamble: task["preamble"].as_str().unwrap_or("").to_string(),
+            input: task["input"].clone(),
+            capability: task["capability"].as_str().unwrap_or("text").to_string(),
+            task_type: task["task_type"].as_str().map(|s| s.to_string()),
+            agent: self.task_agent(task),
+            tool: self.extract_tool_config(task),
+            api_key: None, // Will use default from executor
+            model: model.clone(),
+            provider: self.resolve_provider(task),
+            context_window: model_info.as_ref().map(|info| info.context_window),
+            max_output_tokens: model_info.as_ref().map(|info| info.max_output_tokens),
+            token_limit: self.token_limit(task),
+            output_schema: self.resolve_output_schema(task),
+            temperature: task["metadata"]["temperature"].as_f64().map(|t| t as f32),
+            cache: task["metadata"]["cache"].as_bool(),
+            tools: self.extract_function_tools(task),
+            max_tool_steps: task["metadata"]["max_tool_steps"].as_u64().map(|n| n as u32),
+            chunking: ChunkingSpec::from_metadata(&task["metadata"]["chunking"]),
+            ensemble: self.resolve_ensemble(task),
+            acceptance: self.resolve_acceptance(task),
+            max_retries: None,
+            timeout_secs: None,
+            full_context: None,
+            related_outputs: self.upstream_outputs(project_id, task),
+            retry_count: 0,
+            requires_user_input: false,
+        }
+    }
+    
+    /// Execute the task, mirroring streamed output onto the task while it runs.
+    async fn execute_streamed(&self, project_id: &str, task_id: &str, execution: TaskExecution) -> Result<ExecutionResult> {
+        let executor = self.executor.clone();
+        let events = executor.read().await.subscribe_stream();
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let forwarder = self.forward_stream(project_id.to_string(), task_id.to_string(), events, stop_rx);
+        
+        let result = executor.read().await.execute_task(execution).await;
+        let _ = stop_tx.send(());
+        let _ = forwarder.await;
+        result
+    }
+    
+    fn task_agent(&self, task: &Value) -> Option<String> {
+        task["metadata"]["agent"].as_str()
+            .or_else(|| task["last_agent"].as_str())
+            .map(|s| s.to_string())
+    }
+    
+    /// Schema from the task's metadata, else the one configured for its task type.
+    fn resolve_output_schema(&self, task: &Value) -> Option<Value> {
+        if let Some(schema) = task["metadata"].get("output_schema").filter(|s| s.is_object()) {
+            return Some(schema.clone());
+        }
+        let task_type = task["task_type"].as_str()?;
+        self.state.config.read().output_schemas.get(task_type).cloned()
+    }
+    
+    /// Tool IDs from `metadata.tools` that the model may call while working on the task.
+    fn extract_function_tools(&self, task: &Value) -> Option<Vec<String>> {
+        task["metadata"]["tools"].as_array().map(|tools| {
+            tools.iter().filter_map(|t| t.as_str().map(|s| s.to_string())).collect()
+        })
+    }
+    
     fn extract_tool_config(&self, task: &Value) -> Option<ToolConfig> {
         task["metadata"]["tool"].as_object().map(|tool_obj| {
             ToolConfig {
@@ -220,6 +684,7 @@ impl TaskRunner {
             for task in project_tasks.iter_mut() {
                 if task.id == task_id {
                     task.output = output;
+                    task.partial_output = None;
                     task.completed_at = Some(chrono::Utc::now());
                     break;
                 }
@@ -227,12 +692,81 @@ impl TaskRunner {
         }
     }
     
+    /// Price a run and append it to the project's usage ledger. Ensemble, evaluator and
+    /// rejected rework calls may run on other models, so each gets its own record; the
+    /// rest of the run is priced at the model that produced the output.
+    fn record_run_usage(&self, project_id: &str, task_id: &str, task: &Value, result: &ExecutionResult) -> Vec<UsageRecord> {
+        let Some(usage) = result.usage.as_ref() else { return Vec::new() };
+        let text = |value: &Value| value.as_str().map(|s| s.to_string());
+        let output = result.output.as_ref().cloned().unwrap_or_default();
+        let ensemble = &output["ensemble"];
+        let calls = ensemble["candidates"].as_array().into_iter().flatten()
+            .chain(ensemble.get("judge"))
+            .chain(output["evaluation"]["calls"].as_array().into_iter().flatten());
+        
+        let config = self.state.config.read();
+        let mut records = Vec::new();
+        let mut rest = usage.clone();
+        for call in calls {
+            let Ok(call_usage) = serde_json::from_value::<TokenUsage>(call["usage"].clone()) else { continue };
+            rest.remove(&call_usage);
+            let mut record = UsageRecord::new(
+                &config,
+                project_id,
+                task_id,
+                text(&call["agent"]).or_else(|| self.task_agent(task)),
+                text(&call["provider"]),
+                text(&call["model"]),
+                &call_usage,
+            );
+            if call["cache_hit"].as_bool() == Some(true) {
+                record.mark_cache_hit();
+            }
+            records.push(record);
+        }
+        if records.is_empty() || rest.total() > 0 {
+            let mut record = UsageRecord::new(
+                &config,
+                project_id,
+                task_id,
+                self.task_agent(task),
+                text(&output["provider"]),
+                text(&output["model"]),
+                &rest,
+            );
+            if result.cache_hit {
+                record.mark_cache_hit();
+            }
+            records.push(record);
+        }
+        
+        for record in &records {
+            if let Err(e) = record_usage(&self.state.storage, record) {
+                warn!("Failed to record usage for task {}: {}", task_id, e);
+            }
+        }
+        records
+    }
+    
+    /// Keep what a failed run cost, and its acceptance scores when it failed those.
+    fn update_task_failure(&self, project_id: &str, task_id: &str, evaluation: Option<Value>, result: &ExecutionResult, cost_usd: Option<f64>) {
+        let mut tasks = self.state.tasks.write();
+        if let Some(task) = tasks.get_mut(project_id).and_then(|t| t.iter_mut().find(|t| t.id == task_id)) {
+            if evaluation.is_some() {
+                task.evaluation = evaluation;
+            }
+            task.token_usage = result.usage.clone();
+            task.cost_usd = cost_usd;
+        }
+    }
+    
     async fn update_task_error(&self, project_id: &str, task_id: &str, error: Option<String>) {
         let mut tasks = self.state.tasks.write();
         if let Some(project_tasks) = tasks.get_mut(project_id) {
             for task in project_tasks.iter_mut() {
                 if task.id == task_id {
                     task.error = error;
+                    task.partial_output = None;
                     task.status = TaskStatus::Failed;
                     break;
                 }
@@ -246,7 +780,9 @@ impl Clone for TaskRunner {
         Self {
             executor: Arc::clone(&self.executor),
             state: Arc::clone(&self.state),
+            fair_share: Arc::clone(&self.fair_share),
             running_tasks: Arc::clone(&self.running_tasks),
+            app_handle: self.app_handle.clone(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, p50k_base_singleton};
+
+/// Pre-flight token estimate using the model's own tokenizer.
+///
+/// Models tiktoken does not know (Claude, Llama, ...) are counted with cl100k,
+/// which is close enough for budgeting; providers report the real figure afterwards.
+pub fn estimate_tokens(model: &str, text: &str) -> u32 {
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => o200k_base_singleton(),
+        Some(Tokenizer::P50kBase) => p50k_base_singleton(),
+        _ => cl100k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    bpe.encode_with_special_tokens(text).len() as u32
+}
+
+/// Estimate for a system preamble plus user input, as sent to the provider.
+pub fn estimate_prompt_tokens(model: &str, preamble: &str, input: &str) -> u32 {
+    estimate_tokens(model, &format!("{}\n{}", preamble, input))
+}
+
+/// Split text into pieces of at most `max_tokens`, breaking at paragraphs where possible,
+/// then at lines, then between words.
+///
+/// Piece sizes are summed per unit, which slightly overcounts, so pieces stay in bounds.
+pub fn split_by_tokens(model: &str, text: &str, max_tokens: u32) -> Vec<String> {
+    let max_tokens = max_tokens.max(1);
+    let mut units = Vec::new();
+    collect_units(model, text, max_tokens, 0, &mut units);
+
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut piece_tokens = 0;
+    for (unit, tokens) in units {
+        if piece_tokens + tokens > max_tokens && !piece.trim().is_empty() {
+            pieces.push(std::mem::take(&mut piece));
+            piece_tokens = 0;
+        }
+        piece.push_str(&unit);
+        piece_tokens += tokens;
+    }
+    if !piece.trim().is_empty() {
+        pieces.push(piece);
+    }
+    pieces
+}
+
+// Paragraphs, lines, words, then fixed-size character runs for unbroken text
+fn collect_units(model: &str, text: &str, max_tokens: u32, level: usize, units: &mut Vec<(String, u32)>) {
+    let tokens = estimate_tokens(model, text);
+    if tokens <= max_tokens {
+        units.push((text.to_string(), tokens));
+        return;
+    }
+    let parts: Vec<String> = match level {
+        0 => text.split_inclusive("\n\n").map(|s| s.to_string()).collect(),
+        1 => text.split_inclusive('\n').map(|s| s.to_string()).collect(),
+        2 => text.split_inclusive(' ').map(|s| s.to_string()).collect(),
+        _ => {
+            let chars: Vec<char> = text.chars().collect();
+            let per_piece = (chars.len() * max_tokens as usize / tokens as usize).max(1);
+            for run in chars.chunks(per_piece) {
+                let run: String = run.iter().collect();
+                let tokens = estimate_tokens(model, &run);
+                units.push((run, tokens));
+            }
+            return;
+        }
+    };
+    if parts.len() == 1 {
+        collect_units(model, text, max_tokens, level + 1, units);
+        return;
+    }
+    for part in parts {
+        collect_units(model, &part, max_tokens, level + 1, units);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "gpt-4o";
+
+    fn assert_within(pieces: &[String], max_tokens: u32) {
+        for piece in pieces {
+            let tokens = estimate_tokens(MODEL, piece);
+            assert!(tokens <= max_tokens, "piece of {} tokens exceeds {}: {:?}", tokens, max_tokens, piece);
+        }
+    }
+
+    #[test]
+    fn short_text_is_one_piece() {
+        assert_eq!(split_by_tokens(MODEL, "Hello there.", 100), vec!["Hello there.".to_string()]);
+    }
+
+    #[test]
+    fn empty_text_has_no_pieces() {
+        assert!(split_by_tokens(MODEL, "", 10).is_empty());
+        assert!(split_by_tokens(MODEL, " \n\n ", 10).is_empty());
+    }
+
+    #[test]
+    fn breaks_at_paragraphs_first() {
+        let paragraph = "word ".repeat(20);
+        let text = format!("{}\n\n{}\n\n{}", paragraph, paragraph, paragraph);
+        let max = estimate_tokens(MODEL, &paragraph) + 5;
+        let pieces = split_by_tokens(MODEL, &text, max);
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces[0].ends_with("\n\n"));
+        assert_within(&pieces, max);
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn long_lines_break_between_words() {
+        let text = "alpha beta gamma delta ".repeat(50);
+        let pieces = split_by_tokens(MODEL, &text, 16);
+        assert!(pieces.len() > 1);
+        assert_within(&pieces, 16);
+        assert!(pieces.iter().all(|p| p.ends_with(' ')));
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn unbroken_text_is_cut_into_runs() {
+        let text: String = (0..400).map(|i| char::from(b'a' + (i * 7 % 26) as u8)).collect();
+        let pieces = split_by_tokens(MODEL, &text, 20);
+        assert!(pieces.len() > 1);
+        assert_within(&pieces, 20);
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn multibyte_text_splits_on_char_boundaries() {
+        let text = "日本語のテキスト".repeat(40);
+        let pieces = split_by_tokens(MODEL, &text, 10);
+        assert!(pieces.len() > 1);
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn zero_budget_is_treated_as_one_token() {
+        let pieces = split_by_tokens(MODEL, "a b c", 0);
+        assert_eq!(pieces.concat(), "a b c");
+        assert_within(&pieces, 1);
+    }
+}
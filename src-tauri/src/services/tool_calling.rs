@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use crate::models::tool::{ParameterDefinition, ParameterType, Tool, ToolExecution, ToolExecutionResult};
+use super::providers::FunctionSpec;
+
+pub const DEFAULT_MAX_TOOL_STEPS: u32 = 8;
+// Longer stdout/stderr is cut to its tail before it goes back to the model
+const MAX_RESULT_CHARS: usize = 8000;
+
+/// A tool-calling run that failed after tools had already run. Tools can have side
+/// effects, so the task is not retried.
+#[derive(Debug)]
+pub struct ToolsRanError {
+    pub steps: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for ToolsRanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tool calling failed after {} tool steps: {}", self.steps, self.message)
+    }
+}
+
+impl std::error::Error for ToolsRanError {}
+
+/// Function name for a tool; providers only accept `[a-zA-Z0-9_-]{1,64}`.
+pub fn function_name(tool_id: &str) -> String {
+    tool_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+fn type_schema(param_type: &ParameterType) -> Value {
+    match param_type {
+        ParameterType::String | ParameterType::FilePath | ParameterType::DirectoryPath => json!({"type": "string"}),
+        ParameterType::Integer => json!({"type": "integer"}),
+        ParameterType::Float => json!({"type": "number"}),
+        ParameterType::Boolean => json!({"type": "boolean"}),
+        ParameterType::Enum(values) => json!({"type": "string", "enum": values}),
+        ParameterType::List(inner) => json!({"type": "array", "items": type_schema(inner)}),
+    }
+}
+
+/// JSON Schema for one tool parameter, carrying over its description and validation.
+pub fn parameter_schema(param: &ParameterDefinition) -> Value {
+    let mut schema = type_schema(&param.param_type);
+    let mut description = param.description.clone();
+    match param.param_type {
+        ParameterType::FilePath => description.push_str(" (file path)"),
+        ParameterType::DirectoryPath => description.push_str(" (directory path)"),
+        _ => {}
+    }
+
+    if let Some(validation) = &param.validation {
+        if let Some(min) = validation.min_value {
+            schema["minimum"] = json!(min);
+        }
+        if let Some(max) = validation.max_value {
+            schema["maximum"] = json!(max);
+        }
+        if let Some(pattern) = &validation.regex_pattern {
+            schema["pattern"] = json!(pattern);
+        }
+        if let Some(extensions) = &validation.file_extensions {
+            description.push_str(&format!("; extensions: {}", extensions.join(", ")));
+        }
+    }
+    if let Some(default) = &param.default_value {
+        schema["default"] = json!(default);
+    }
+    schema["description"] = json!(description);
+    schema
+}
+
+/// Describe a tool as a callable function.
+///
+/// Parameters carry the values; `arguments` is the command line, which may reference
+/// them as `{name}`.
+pub fn function_spec(tool: &Tool) -> FunctionSpec {
+    let mut properties = serde_json::Map::new();
+    properties.insert(
+        "arguments".to_string(),
+        json!({
+            "type": "array",
+            "items": {"type": "string"},
+            "description": format!(
+                "Command-line arguments for {}, without the executable. Write {{name}} to insert a parameter value.",
+                tool.name
+            )
+        }),
+    );
+    let mut required = vec!["arguments".to_string()];
+
+    let mut names: Vec<&String> = tool.parameters.keys().collect();
+    names.sort();
+    for name in names {
+        let param = &tool.parameters[name];
+        properties.insert(name.clone(), parameter_schema(param));
+        if param.required && param.default_value.is_none() {
+            required.push(name.clone());
+        }
+    }
+
+    let mut description = format!("Run {} locally.", tool.name);
+    if !tool.input_formats.is_empty() {
+        description.push_str(&format!(" Input formats: {}.", tool.input_formats.join(", ")));
+    }
+    if !tool.output_formats.is_empty() {
+        description.push_str(&format!(" Output formats: {}.", tool.output_formats.join(", ")));
+    }
+
+    FunctionSpec {
+        name: function_name(&tool.id),
+        description,
+        parameters: json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        }),
+    }
+}
+
+fn as_argument(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(as_argument).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Turn the model's function arguments into a `ToolManager` execution.
+pub fn tool_execution(tool: &Tool, arguments: &Value) -> Result<ToolExecution> {
+    let args = arguments
+        .as_object()
+        .ok_or_else(|| anyhow!("Arguments for {} must be a JSON object", tool.id))?;
+
+    let mut parameters = HashMap::new();
+    for (name, param) in &tool.parameters {
+        match args.get(name).filter(|v| !v.is_null()) {
+            Some(value) => {
+                parameters.insert(name.clone(), as_argument(value));
+            }
+            None => match &param.default_value {
+                Some(default) => {
+                    parameters.insert(name.clone(), default.clone());
+                }
+                None if param.required => return Err(anyhow!("Missing required parameter '{}'", name)),
+                None => {}
+            },
+        }
+    }
+
+    let command_line = args
+        .get("arguments")
+        .and_then(|a| a.as_array())
+        .ok_or_else(|| anyhow!("'arguments' must be an array of strings"))?
+        .iter()
+        .map(|arg| {
+            parameters
+                .iter()
+                .fold(as_argument(arg), |arg, (name, value)| arg.replace(&format!("{{{}}}", name), value))
+        })
+        .collect();
+
+    Ok(ToolExecution {
+        tool_id: tool.id.clone(),
+        command: tool.id.clone(),
+        arguments: command_line,
+        input_files: Vec::new(),
+        output_files: Vec::new(),
+        parameters,
+        stdin_data: None,
+        expected_exit_codes: Vec::new(),
+        capture_stdout: true,
+        capture_stderr: true,
+        timeout_override: None,
+    })
+}
+
+fn tail(text: &str) -> String {
+    let count = text.chars().count();
+    if count <= MAX_RESULT_CHARS {
+        return text.to_string();
+    }
+    let kept: String = text.chars().skip(count - MAX_RESULT_CHARS).collect();
+    format!("[truncated]\n{}", kept)
+}
+
+/// Tool outcome as the content of the tool message sent back to the model.
+pub fn result_message(result: &Result<ToolExecutionResult>) -> String {
+    match result {
+        Ok(result) => json!({
+            "success": result.success,
+            "exit_code": result.exit_code,
+            "stdout": tail(&result.stdout),
+            "stderr": tail(&result.stderr),
+            "error": result.error_message,
+            "execution_time_ms": result.execution_time_ms
+        }),
+        Err(e) => json!({"success": false, "error": e.to_string()}),
+    }
+    .to_string()
+}
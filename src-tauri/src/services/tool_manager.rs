@@ -1,11 +1,12 @@
-use crate::models::tool::{Tool, ToolCategory, ToolCapability, ToolExecution, ToolExecutionResult};
+use crate::models::tool::{Tool, ToolCapability, ToolExecution, ToolExecutionResult};
 use anyhow::{Context, Result};
 use parking_lot::RwLock;
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command as AsyncCommand;
 use which::which;
 
@@ -41,6 +42,8 @@ impl ToolManager {
             Tool::blender(),
             Tool::imagemagick(),
             Tool::pandoc(),
+            Tool::piper(),
+            Tool::espeak_ng(),
         ];
         
         let mut tools = self.tools.write();
@@ -92,7 +95,7 @@ impl ToolManager {
             if let Ok(path) = which(&executable_name) {
                 tool.executable_path = Some(path.clone());
                 tool.is_available = true;
-                tool_paths.insert(id.clone(), path);
+                tool_paths.insert(id.clone(), path.clone());
                 
                 // Try to get version
                 if let Some(version_cmd) = &tool.validation_command {
@@ -215,8 +218,8 @@ impl ToolManager {
     
     /// Execute a tool with given parameters
     pub async fn execute_tool(&self, execution: ToolExecution) -> Result<ToolExecutionResult> {
-        let tools = self.tools.read();
-        let tool = tools.get(&execution.tool_id)
+        // Clone out of the lock; the guard must not be held across the await below
+        let tool = self.get_tool(&execution.tool_id)
             .ok_or_else(|| anyhow::anyhow!("Tool '{}' not found", execution.tool_id))?;
         
         if !tool.is_available {
@@ -259,9 +262,17 @@ impl ToolManager {
         }
         
         // Execute command
+        cmd.kill_on_drop(true);
+        let mut child = cmd.spawn().context("Failed to execute command")?;
+        if let (Some(data), Some(mut stdin)) = (execution.stdin_data.clone(), child.stdin.take()) {
+            // Written concurrently so a child that fills its stdout first cannot deadlock us
+            tokio::spawn(async move {
+                let _ = stdin.write_all(data.as_bytes()).await;
+            });
+        }
         let output = tokio::time::timeout(
             Duration::from_secs(timeout),
-            cmd.output()
+            child.wait_with_output()
         ).await
             .context("Command timed out")?
             .context("Failed to execute command")?;
@@ -372,12 +383,12 @@ impl ToolManager {
             }
         }
         
-        PathBuf::from(expanded.as_ref())
+        PathBuf::from(expanded.into_owned())
     }
     
     fn extract_version(&self, output: &str) -> Option<String> {
         // Simple version extraction using regex
-        let re = regex::Regex::new(r"(\d+\.\d+(?:\.\d+)?)")?;
+        let re = regex::Regex::new(r"(\d+\.\d+(?:\.\d+)?)").ok()?;
         re.captures(output)
             .and_then(|cap| cap.get(1))
             .map(|m| m.as_str().to_string())
@@ -413,7 +424,7 @@ impl ToolManager {
             let mut score = 0;
             
             // Prefer tools with exact capability match (not too many extra capabilities)
-            score += (t.capabilities.len() - required_capabilities.len()).abs();
+            score += t.capabilities.len().abs_diff(required_capabilities.len());
             
             // Prefer tools that don't require GPU if not needed
             if t.requires_gpu {
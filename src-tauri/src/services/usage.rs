@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::models::{lookup_model, AppConfig, TokenUsage};
+use crate::storage::StorageService;
+
+// Append-only ledger kept in each project's directory
+const USAGE_FILE: &str = "usage.jsonl";
+
+/// One completed provider call, priced at the rates in effect when it ran.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub task_id: String,
+    pub project_id: String,
+    #[serde(default)]
+    pub agent: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    // True when the provider reported no usage and counts are tokenizer estimates
+    pub estimated: bool,
+    pub cost_usd: f64,
+    // Served from the response cache: not billed, `saved_cost_usd` is what it would have cost
+    #[serde(default)]
+    pub cache_hit: bool,
+    #[serde(default)]
+    pub saved_cost_usd: f64,
+    pub at: DateTime<Utc>,
+}
+
+impl UsageRecord {
+    pub fn new(
+        config: &AppConfig,
+        project_id: &str,
+        task_id: &str,
+        agent: Option<String>,
+        provider: Option<String>,
+        model: Option<String>,
+        usage: &TokenUsage,
+    ) -> Self {
+        let cost_usd = model
+            .as_deref()
+            .and_then(|m| lookup_model(&config.models, m))
+            .map(|info| info.cost(usage))
+            .unwrap_or(0.0);
+
+        Self {
+            task_id: task_id.to_string(),
+            project_id: project_id.to_string(),
+            agent,
+            provider,
+            model,
+            prompt_tokens: usage.prompt_tokens.unwrap_or(usage.estimated_prompt_tokens),
+            completion_tokens: usage.completion_tokens.or(usage.estimated_completion_tokens).unwrap_or(0),
+            estimated: !usage.is_actual(),
+            cost_usd,
+            cache_hit: false,
+            saved_cost_usd: 0.0,
+            at: Utc::now(),
+        }
+    }
+
+    pub fn mark_cache_hit(&mut self) {
+        self.cache_hit = true;
+        self.saved_cost_usd = self.cost_usd;
+        self.cost_usd = 0.0;
+    }
+}
+
+pub fn record_usage(storage: &StorageService, record: &UsageRecord) -> anyhow::Result<()> {
+    storage.append_to_jsonl(&record.project_id, USAGE_FILE, &serde_json::to_value(record)?)
+}
+
+/// Ledger entries in `[from, to)`, optionally for one project.
+pub fn load_usage(
+    storage: &StorageService,
+    project_id: Option<&str>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<UsageRecord>> {
+    let projects = match project_id {
+        Some(id) => vec![id.to_string()],
+        None => storage.list_project_dirs()?,
+    };
+
+    let mut records = Vec::new();
+    for project in projects {
+        for value in storage.load_project_jsonl(&project, USAGE_FILE)? {
+            if let Ok(record) = serde_json::from_value::<UsageRecord>(value) {
+                let after_start = from.is_none_or(|f| record.at >= f);
+                let before_end = to.is_none_or(|t| record.at < t);
+                if after_start && before_end {
+                    records.push(record);
+                }
+            }
+        }
+    }
+    records.sort_by_key(|r| r.at);
+    Ok(records)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    pub calls: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    // Calls whose counts are estimates rather than provider-reported usage
+    pub estimated_calls: usize,
+    pub cache_hits: usize,
+    pub saved_cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.calls += 1;
+        self.prompt_tokens += record.prompt_tokens as u64;
+        self.completion_tokens += record.completion_tokens as u64;
+        self.cost_usd += record.cost_usd;
+        if record.estimated {
+            self.estimated_calls += 1;
+        }
+        if record.cache_hit {
+            self.cache_hits += 1;
+            self.saved_cost_usd += record.saved_cost_usd;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageReport {
+    pub totals: UsageTotals,
+    pub by_project: BTreeMap<String, UsageTotals>,
+    pub by_agent: BTreeMap<String, UsageTotals>,
+    pub by_day: BTreeMap<String, UsageTotals>,
+    pub by_model: BTreeMap<String, UsageTotals>,
+}
+
+pub fn build_report(records: &[UsageRecord]) -> UsageReport {
+    let mut report = UsageReport::default();
+    for record in records {
+        report.totals.add(record);
+        report.by_project.entry(record.project_id.clone()).or_default().add(record);
+        report.by_agent
+            .entry(record.agent.clone().unwrap_or_else(|| "unassigned".to_string()))
+            .or_default()
+            .add(record);
+        report.by_day.entry(record.at.format("%Y-%m-%d").to_string()).or_default().add(record);
+        report.by_model
+            .entry(record.model.clone().unwrap_or_else(|| "unknown".to_string()))
+            .or_default()
+            .add(record);
+    }
+    report
+}
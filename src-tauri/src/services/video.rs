@@ -0,0 +1,462 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+use crate::models::tool::ToolExecution;
+use super::artifacts::{self, file_stem};
+use super::tool_manager::ToolManager;
+
+const DEFAULT_SHOT_SECS: f64 = 3.0;
+const DEFAULT_TRANSITION_SECS: f64 = 0.5;
+// xfade transitions offered to storyboards; "cut" is a hard concat
+const TRANSITIONS: &[&str] = &[
+    "cut", "fade", "fadeblack", "fadewhite", "dissolve", "wipeleft", "wiperight", "wipeup", "wipedown",
+    "slideleft", "slideright", "slideup", "slidedown", "circleopen", "circleclose", "radial", "pixelize",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleMode {
+    /// Separate subtitle stream the player can toggle
+    Soft,
+    /// Rendered into the frames; needs an ffmpeg built with libass
+    Burn,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Mp4,
+    Webm,
+    Mkv,
+    Mov,
+    Gif,
+}
+
+impl VideoFormat {
+    fn parse(format: &str) -> Result<Self> {
+        match format.to_lowercase().as_str() {
+            "mp4" => Ok(Self::Mp4),
+            "webm" => Ok(Self::Webm),
+            "mkv" => Ok(Self::Mkv),
+            "mov" => Ok(Self::Mov),
+            "gif" => Ok(Self::Gif),
+            other => Err(anyhow!("Unsupported video format '{}'", other)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Webm => "webm",
+            Self::Mkv => "mkv",
+            Self::Mov => "mov",
+            Self::Gif => "gif",
+        }
+    }
+
+    fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Webm => &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "32", "-c:a", "libopus"],
+            Self::Gif => &[],
+            _ => &["-c:v", "libx264", "-preset", "medium", "-crf", "20", "-c:a", "aac"],
+        }
+    }
+
+    fn subtitle_codec(&self) -> Option<&'static str> {
+        match self {
+            Self::Mp4 | Self::Mov => Some("mov_text"),
+            Self::Webm => Some("webvtt"),
+            Self::Mkv => Some("srt"),
+            Self::Gif => None,
+        }
+    }
+}
+
+/// Shot list as written in a video task's input. `image` and `subtitle_from` may name an
+/// upstream task instead of a file.
+#[derive(Debug, Default, Deserialize)]
+struct StoryboardSpec {
+    #[serde(default, alias = "storyboard")]
+    shots: Vec<ShotSpec>,
+    audio: Option<String>,
+    format: Option<String>,
+    resolution: Option<String>,
+    fps: Option<u32>,
+    subtitles: Option<SubtitleMode>,
+    // Defaults for shots that do not set their own
+    transition: Option<String>,
+    transition_secs: Option<f64>,
+    shot_duration: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShotSpec {
+    image: String,
+    duration: Option<f64>,
+    transition: Option<String>,
+    subtitle: Option<String>,
+    subtitle_from: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Shot {
+    pub image: PathBuf,
+    pub duration: f64,
+    /// Transition from the previous shot into this one
+    pub transition: String,
+    pub subtitle: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Storyboard {
+    pub shots: Vec<Shot>,
+    pub audio: Option<PathBuf>,
+    pub format: VideoFormat,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub subtitles: SubtitleMode,
+    pub transition_secs: f64,
+}
+
+fn upstream_output<'a>(upstream: &'a [Value], task_id: &str) -> Option<&'a Value> {
+    upstream.iter().find(|u| u["task_id"] == task_id).map(|u| &u["output"])
+}
+
+fn upstream_of_type<'a>(upstream: &'a [Value], kind: &'a str) -> impl Iterator<Item = &'a Value> {
+    upstream.iter().map(|u| &u["output"]).filter(move |o| o["type"] == kind)
+}
+
+/// Local file for an upstream task ID or a path given directly.
+fn resolve_media(reference: &str, upstream: &[Value]) -> Result<PathBuf> {
+    let path = match upstream_output(upstream, reference) {
+        Some(output) => output["path"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Upstream task {} has no local media file", reference))?,
+        None => reference,
+    };
+    let path = PathBuf::from(path);
+    if !path.is_file() {
+        return Err(anyhow!("Media file {} does not exist", path.display()));
+    }
+    Ok(path)
+}
+
+fn text_of(output: &Value) -> Option<&str> {
+    output["content"].as_str().or_else(|| output.as_str())
+}
+
+/// Spread sentences from upstream text over the shots, in order.
+fn distribute_sentences(text: &str, shots: usize) -> Vec<Option<String>> {
+    let sentences: Vec<&str> = text
+        .split_inclusive(['.', '!', '?'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if sentences.is_empty() || shots == 0 {
+        return vec![None; shots];
+    }
+    let per_shot = (sentences.len() as f64 / shots as f64).ceil() as usize;
+    (0..shots)
+        .map(|i| {
+            let chunk: Vec<&str> = sentences.iter().skip(i * per_shot).take(per_shot).copied().collect();
+            (!chunk.is_empty()).then(|| chunk.join(" "))
+        })
+        .collect()
+}
+
+fn parse_resolution(resolution: &str) -> Result<(u32, u32)> {
+    let (w, h) = resolution
+        .split_once('x')
+        .ok_or_else(|| anyhow!("Resolution must look like 1280x720, got '{}'", resolution))?;
+    Ok((w.trim().parse()?, h.trim().parse()?))
+}
+
+impl Storyboard {
+    /// Build the storyboard from the task input, falling back to every upstream image in
+    /// order, the first upstream audio, and subtitles taken from upstream text.
+    pub async fn from_task(input: &Value, upstream: &[Value]) -> Result<Self> {
+        // Full-context retries wrap the original input
+        let input = input.get("original_input").unwrap_or(input);
+        let spec: StoryboardSpec = match input {
+            Value::Object(_) => serde_json::from_value(input.clone())?,
+            _ => StoryboardSpec::default(),
+        };
+
+        let format = VideoFormat::parse(spec.format.as_deref().unwrap_or("mp4"))?;
+        let (width, height) = parse_resolution(spec.resolution.as_deref().unwrap_or("1280x720"))?;
+        let transition_secs = spec.transition_secs.unwrap_or(DEFAULT_TRANSITION_SECS).max(0.0);
+        let default_transition = spec.transition.clone().unwrap_or_else(|| "fade".to_string());
+
+        let audio = match &spec.audio {
+            Some(reference) => Some(resolve_media(reference, upstream)?),
+            None => upstream_of_type(upstream, "audio")
+                .find_map(|o| o["path"].as_str())
+                .map(PathBuf::from)
+                .filter(|p| p.is_file()),
+        };
+        let audio_secs = match &audio {
+            Some(path) => artifacts::media_duration(path).await,
+            None => None,
+        };
+
+        let mut shots = Vec::new();
+        if spec.shots.is_empty() {
+            for output in upstream_of_type(upstream, "image") {
+                if let Some(path) = output["path"].as_str().map(PathBuf::from).filter(|p| p.is_file()) {
+                    shots.push(Shot { image: path, duration: 0.0, transition: default_transition.clone(), subtitle: None });
+                }
+            }
+            let text: Vec<&str> = upstream_of_type(upstream, "text").filter_map(text_of).collect();
+            let subtitles = distribute_sentences(&text.join(" "), shots.len());
+            for (shot, subtitle) in shots.iter_mut().zip(subtitles) {
+                shot.subtitle = subtitle;
+            }
+        } else {
+            for shot in &spec.shots {
+                let subtitle = match (&shot.subtitle, &shot.subtitle_from) {
+                    (Some(text), _) => Some(text.clone()),
+                    (None, Some(task_id)) => upstream_output(upstream, task_id).and_then(text_of).map(|s| s.to_string()),
+                    (None, None) => None,
+                };
+                shots.push(Shot {
+                    image: resolve_media(&shot.image, upstream)?,
+                    duration: shot.duration.unwrap_or(0.0),
+                    transition: shot.transition.clone().unwrap_or_else(|| default_transition.clone()),
+                    subtitle,
+                });
+            }
+        }
+        if shots.is_empty() {
+            return Err(anyhow!("Video task has no shots: give a storyboard or depend on image tasks"));
+        }
+        for shot in &shots {
+            if !TRANSITIONS.contains(&shot.transition.as_str()) {
+                return Err(anyhow!("Unknown transition '{}'; use one of {}", shot.transition, TRANSITIONS.join(", ")));
+            }
+        }
+
+        // Shots without a duration share what the narration needs, else the default length
+        let fixed: f64 = shots.iter().map(|s| s.duration).sum();
+        let open = shots.iter().filter(|s| s.duration <= 0.0).count();
+        if open > 0 {
+            let overlap = transition_secs * (shots.len() - 1) as f64;
+            let share = match (spec.shot_duration, audio_secs) {
+                (Some(secs), _) => secs,
+                (None, Some(audio)) => ((audio + overlap - fixed) / open as f64).max(1.0),
+                (None, None) => DEFAULT_SHOT_SECS,
+            };
+            for shot in shots.iter_mut().filter(|s| s.duration <= 0.0) {
+                shot.duration = share;
+            }
+        }
+
+        let subtitles = match spec.subtitles {
+            Some(mode) => mode,
+            None if shots.iter().any(|s| s.subtitle.is_some()) => SubtitleMode::Soft,
+            None => SubtitleMode::None,
+        };
+        Ok(Self {
+            shots,
+            audio,
+            format,
+            width,
+            height,
+            fps: spec.fps.unwrap_or(30),
+            subtitles: if format == VideoFormat::Gif { SubtitleMode::None } else { subtitles },
+            transition_secs,
+        })
+    }
+
+    /// Start time of each shot and the total length, with transitions overlapping shots.
+    pub fn timeline(&self) -> (Vec<f64>, f64) {
+        let mut starts = Vec::with_capacity(self.shots.len());
+        let mut length = 0.0;
+        for (i, shot) in self.shots.iter().enumerate() {
+            let overlap = if i == 0 { 0.0 } else { self.overlap(i) };
+            starts.push(length - overlap);
+            length += shot.duration - overlap;
+        }
+        (starts, length)
+    }
+
+    /// Transition length into shot `i`, capped so it never swallows either shot.
+    fn overlap(&self, i: usize) -> f64 {
+        if self.shots[i].transition == "cut" {
+            return 0.0;
+        }
+        let shortest = self.shots[i - 1].duration.min(self.shots[i].duration);
+        self.transition_secs.min(shortest / 2.0)
+    }
+
+    pub fn srt(&self) -> String {
+        let (starts, length) = self.timeline();
+        let mut srt = String::new();
+        let mut index = 1;
+        for (i, shot) in self.shots.iter().enumerate() {
+            if let Some(text) = shot.subtitle.as_deref().filter(|t| !t.trim().is_empty()) {
+                let end = starts.get(i + 1).copied().unwrap_or(length);
+                srt.push_str(&format!("{}\n{} --> {}\n{}\n\n", index, srt_time(starts[i]), srt_time(end), text.trim()));
+                index += 1;
+            }
+        }
+        srt
+    }
+
+    fn filter_graph(&self, burn_subtitles: Option<&Path>) -> String {
+        let (w, h, fps) = (self.width, self.height, self.fps);
+        let mut graph: Vec<String> = (0..self.shots.len())
+            .map(|i| {
+                format!(
+                    "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps},format=yuv420p[v{i}]"
+                )
+            })
+            .collect();
+
+        let mut last = "v0".to_string();
+        let mut length = self.shots[0].duration;
+        for i in 1..self.shots.len() {
+            let label = format!("x{}", i);
+            let overlap = self.overlap(i);
+            if overlap > 0.0 {
+                graph.push(format!(
+                    "[{}][v{}]xfade=transition={}:duration={:.3}:offset={:.3}[{}]",
+                    last, i, self.shots[i].transition, overlap, length - overlap, label
+                ));
+            } else {
+                graph.push(format!("[{}][v{}]concat=n=2:v=1:a=0[{}]", last, i, label));
+            }
+            length += self.shots[i].duration - overlap;
+            last = label;
+        }
+
+        let finish = match (burn_subtitles, self.format) {
+            (Some(srt), _) => format!("subtitles='{}'", escape_filter_path(srt)),
+            (None, VideoFormat::Gif) => "split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse".to_string(),
+            (None, _) => "null".to_string(),
+        };
+        graph.push(format!("[{}]{}[vout]", last, finish));
+        graph.join(";")
+    }
+
+    /// Full ffmpeg argument list writing the composed video to `output`.
+    pub fn ffmpeg_args(&self, srt: Option<&Path>, output: &Path) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-y".to_string(), "-hide_banner".to_string()];
+        for shot in &self.shots {
+            args.extend(["-loop".to_string(), "1".to_string(), "-t".to_string(), format!("{:.3}", shot.duration)]);
+            args.extend(["-i".to_string(), shot.image.to_string_lossy().into_owned()]);
+        }
+        let audio = self.audio.as_ref().filter(|_| self.format != VideoFormat::Gif);
+        if let Some(audio) = audio {
+            args.extend(["-i".to_string(), audio.to_string_lossy().into_owned()]);
+        }
+        let soft_subtitles = srt.filter(|_| self.subtitles == SubtitleMode::Soft);
+        if let Some(srt) = soft_subtitles {
+            args.extend(["-i".to_string(), srt.to_string_lossy().into_owned()]);
+        }
+
+        let burn = srt.filter(|_| self.subtitles == SubtitleMode::Burn);
+        args.extend(["-filter_complex".to_string(), self.filter_graph(burn), "-map".to_string(), "[vout]".to_string()]);
+        let mut next_input = self.shots.len();
+        if audio.is_some() {
+            args.extend(["-map".to_string(), format!("{}:a", next_input)]);
+            next_input += 1;
+        }
+        if soft_subtitles.is_some() {
+            if let Some(codec) = self.format.subtitle_codec() {
+                args.extend(["-map".to_string(), format!("{}:s", next_input), "-c:s".to_string(), codec.into()]);
+            }
+        }
+        args.extend(self.format.codec_args().iter().map(|a| a.to_string()));
+        if self.format != VideoFormat::Gif {
+            args.extend(["-pix_fmt".to_string(), "yuv420p".to_string()]);
+        }
+
+        let (_, length) = self.timeline();
+        args.extend(["-t".to_string(), format!("{:.3}", length), output.to_string_lossy().into_owned()]);
+        args
+    }
+}
+
+fn srt_time(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02},{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// Quote a path for use inside a filtergraph option value.
+fn escape_filter_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "/")
+        .replace(':', "\\:")
+        .replace('\'', "\\'")
+}
+
+/// Render the storyboard with the detected ffmpeg and describe the result as a task output.
+pub async fn compose(tool_manager: &ToolManager, storyboard: &Storyboard, dir: &Path, task_id: &str) -> Result<Value> {
+    let ffmpeg = tool_manager
+        .get_tool("ffmpeg")
+        .filter(|tool| tool.is_available)
+        .ok_or_else(|| anyhow!("Video composition needs ffmpeg, which was not detected"))?;
+
+    fs::create_dir_all(dir)?;
+    let stem = file_stem(task_id);
+    let srt_path = match storyboard.subtitles {
+        SubtitleMode::None => None,
+        _ => {
+            let srt = storyboard.srt();
+            if srt.is_empty() {
+                None
+            } else {
+                let path = dir.join(format!("{}.srt", stem));
+                fs::write(&path, srt)?;
+                Some(path)
+            }
+        }
+    };
+    let output = dir.join(format!("{}.{}", stem, storyboard.format.extension()));
+    let arguments = storyboard.ffmpeg_args(srt_path.as_deref(), &output);
+    debug!("Composing video for task {}: ffmpeg {:?}", task_id, arguments);
+
+    let result = tool_manager
+        .execute_tool(ToolExecution {
+            tool_id: ffmpeg.id.clone(),
+            command: ffmpeg.id.clone(),
+            arguments,
+            input_files: storyboard.shots.iter().map(|s| s.image.clone()).chain(storyboard.audio.clone()).collect(),
+            output_files: vec![output.clone()],
+            parameters: Default::default(),
+            stdin_data: None,
+            expected_exit_codes: Vec::new(),
+            capture_stdout: true,
+            capture_stderr: true,
+            timeout_override: None,
+        })
+        .await?;
+    if !result.success {
+        let stderr: Vec<&str> = result.stderr.lines().rev().take(20).collect();
+        return Err(anyhow!(
+            "ffmpeg failed with exit code {}: {}",
+            result.exit_code,
+            stderr.into_iter().rev().collect::<Vec<_>>().join("\n")
+        ));
+    }
+
+    let (_, length) = storyboard.timeline();
+    let duration = artifacts::media_duration(&output).await.unwrap_or(length);
+    Ok(json!({
+        "type": "video",
+        "path": output.to_string_lossy(),
+        "format": storyboard.format.extension(),
+        "width": storyboard.width,
+        "height": storyboard.height,
+        "fps": storyboard.fps,
+        "duration_secs": duration,
+        "shots": storyboard.shots.len(),
+        "audio": storyboard.audio.as_ref().map(|p| p.to_string_lossy().into_owned()),
+        "subtitles": srt_path.as_ref().map(|p| p.to_string_lossy().into_owned()),
+        "provider": "ffmpeg"
+    }))
+}
@@ -0,0 +1,412 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::warn;
+use crate::models::{Agent, Capability};
+use super::agent_host::{AgentRequest, AgentResponse};
+use super::agent_probe::remote_headers;
+
+const PROTOCOL_VERSION: u32 = 1;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+// Connection is considered dead when nothing arrives for this long
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Messages sent from SuperCollider to a WebSocket agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello {
+        protocol_version: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        // Tasks still awaiting a result, so the agent can resume them
+        resume_tasks: Vec<String>,
+    },
+    AssignTask { request: AgentRequest },
+    CancelTask { task_id: String },
+    Ping { ts: i64 },
+}
+
+/// Messages a WebSocket agent sends back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentMessage {
+    Welcome {
+        session_id: String,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+        #[serde(default)]
+        token_limit: Option<u32>,
+        #[serde(default)]
+        max_concurrent_tasks: Option<usize>,
+        // Subset of `resume_tasks` the agent still holds; the rest are reassigned
+        #[serde(default)]
+        resumed_tasks: Vec<String>,
+    },
+    Progress {
+        task_id: String,
+        progress: f32,
+        #[serde(default)]
+        message: Option<String>,
+    },
+    PartialOutput { task_id: String, delta: Value },
+    TaskResult { response: AgentResponse },
+    Pong { ts: i64 },
+    Error {
+        #[serde(default)]
+        task_id: Option<String>,
+        message: String,
+    },
+}
+
+/// Progress and partial output forwarded to listeners such as the UI.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsAgentEvent {
+    Progress { agent: String, task_id: String, progress: f32, message: Option<String> },
+    PartialOutput { agent: String, task_id: String, delta: Value },
+    Connected { agent: String, session_id: String, resumed: usize },
+    Disconnected { agent: String, error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WsHandshake {
+    pub session_id: String,
+    pub capabilities: Vec<Capability>,
+    pub token_limit: Option<u32>,
+    pub max_concurrent_tasks: Option<usize>,
+}
+
+struct PendingTask {
+    request: AgentRequest,
+    tx: oneshot::Sender<AgentResponse>,
+}
+
+enum Outbound {
+    Assign(String),
+    Cancel(String),
+}
+
+/// Persistent WebSocket transport for remote agents with `ws://` or `wss://` endpoints.
+pub struct WsAgentConnection {
+    agent: Agent,
+    pending: Arc<Mutex<HashMap<String, PendingTask>>>,
+    handshake: Arc<Mutex<Option<WsHandshake>>>,
+    outbound: Mutex<Option<mpsc::UnboundedSender<Outbound>>>,
+    events: broadcast::Sender<WsAgentEvent>,
+}
+
+pub fn is_websocket_endpoint(endpoint: &str) -> bool {
+    let lower = endpoint.to_lowercase();
+    lower.starts_with("ws://") || lower.starts_with("wss://")
+}
+
+impl WsAgentConnection {
+    pub fn new(agent: &Agent) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            agent: agent.clone(),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            handshake: Arc::new(Mutex::new(None)),
+            outbound: Mutex::new(None),
+            events,
+        }
+    }
+
+    /// The agent as registered when the connection was made.
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// Open the connection now rather than on the first task.
+    pub fn connect(&self) {
+        let mut outbound = self.outbound.lock();
+        if outbound.as_ref().is_none_or(|tx| tx.is_closed()) {
+            *outbound = Some(self.start());
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WsAgentEvent> {
+        self.events.subscribe()
+    }
+
+    /// Capabilities and limits the agent advertised in its last handshake.
+    pub fn handshake(&self) -> Option<WsHandshake> {
+        self.handshake.lock().clone()
+    }
+
+    /// Assign a task and wait for its final result; there is no fixed timeout
+    /// because heartbeats detect dead connections instead.
+    pub async fn execute(&self, request: AgentRequest) -> anyhow::Result<AgentResponse> {
+        let task_id = request.task_id.clone();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().insert(task_id.clone(), PendingTask { request, tx });
+
+        if !self.send(Outbound::Assign(task_id.clone())) {
+            self.pending.lock().remove(&task_id);
+            return Err(anyhow::anyhow!("WebSocket agent {} is not connected", self.agent.name));
+        }
+
+        rx.await.map_err(|_| anyhow::anyhow!("WebSocket agent {} dropped task {}", self.agent.name, task_id))
+    }
+
+    pub fn is_running(&self, task_id: &str) -> bool {
+        self.pending.lock().contains_key(task_id)
+    }
+
+    pub fn cancel(&self, task_id: &str) {
+        if let Some(pending) = self.pending.lock().remove(task_id) {
+            let _ = pending.tx.send(AgentResponse {
+                task_id: task_id.to_string(),
+                success: false,
+                output: None,
+                error: Some("Task cancelled".to_string()),
+                tokens_used: None,
+                execution_time_ms: 0,
+            });
+        }
+        self.send(Outbound::Cancel(task_id.to_string()));
+    }
+
+    fn send(&self, message: Outbound) -> bool {
+        let mut outbound = self.outbound.lock();
+        let alive = outbound.as_ref().is_some_and(|tx| !tx.is_closed());
+        if !alive {
+            *outbound = Some(self.start());
+        }
+        outbound.as_ref().is_some_and(|tx| tx.send(message).is_ok())
+    }
+
+    /// Spawn the connection loop, which owns the socket and reconnects with resume.
+    fn start(&self) -> mpsc::UnboundedSender<Outbound> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let runner = ConnectionLoop {
+            agent: self.agent.clone(),
+            pending: Arc::clone(&self.pending),
+            handshake: Arc::clone(&self.handshake),
+            events: self.events.clone(),
+        };
+        // Commands may register agents from outside the async runtime
+        tauri::async_runtime::spawn(async move {
+            runner.run(rx).await;
+        });
+        tx
+    }
+}
+
+struct ConnectionLoop {
+    agent: Agent,
+    pending: Arc<Mutex<HashMap<String, PendingTask>>>,
+    handshake: Arc<Mutex<Option<WsHandshake>>>,
+    events: broadcast::Sender<WsAgentEvent>,
+}
+
+impl ConnectionLoop {
+    async fn run(self, mut rx: mpsc::UnboundedReceiver<Outbound>) {
+        let mut attempts = 0u32;
+
+        loop {
+            let mut handshaken = false;
+            match self.session(&mut rx, &mut handshaken).await {
+                Ok(()) => return, // Owner dropped the sender
+                Err(e) => {
+                    warn!("WebSocket agent {} disconnected: {}", self.agent.name, e);
+                    let _ = self.events.send(WsAgentEvent::Disconnected {
+                        agent: self.agent.name.clone(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+
+            // A completed handshake means the agent was reachable; start backoff over
+            if handshaken {
+                attempts = 0;
+            }
+            attempts += 1;
+            if attempts > MAX_RECONNECT_ATTEMPTS {
+                self.fail_pending("WebSocket agent unreachable after repeated reconnects");
+                return;
+            }
+            let delay = Duration::from_secs(1u64 << attempts.min(5)).min(MAX_RECONNECT_DELAY);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn session(&self, rx: &mut mpsc::UnboundedReceiver<Outbound>, handshaken: &mut bool) -> anyhow::Result<()> {
+        let endpoint = self.agent.endpoint_url.clone().unwrap_or_default();
+        let mut request = endpoint.as_str().into_client_request()?;
+        for (name, value) in remote_headers(&self.agent)?.iter() {
+            request.headers_mut().insert(name.clone(), value.clone());
+        }
+
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+        let (mut sink, mut stream) = socket.split();
+
+        // Handshake, offering the previous session and in-flight tasks for resume
+        let previous_session = self.handshake.lock().as_ref().map(|h| h.session_id.clone());
+        let resume_tasks: Vec<String> = self.pending.lock().keys().cloned().collect();
+        let hello = ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            session_id: previous_session,
+            resume_tasks: resume_tasks.clone(),
+        };
+        sink.send(Message::Text(serde_json::to_string(&hello)?)).await?;
+
+        let welcome = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            while let Some(message) = stream.next().await {
+                if let Message::Text(text) = message? {
+                    return Ok::<_, anyhow::Error>(serde_json::from_str::<AgentMessage>(&text)?);
+                }
+            }
+            Err(anyhow::anyhow!("Connection closed during handshake"))
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Handshake timed out"))??;
+
+        let resumed = match welcome {
+            AgentMessage::Welcome { session_id, capabilities, token_limit, max_concurrent_tasks, resumed_tasks } => {
+                let _ = self.events.send(WsAgentEvent::Connected {
+                    agent: self.agent.name.clone(),
+                    session_id: session_id.clone(),
+                    resumed: resumed_tasks.len(),
+                });
+                *self.handshake.lock() = Some(WsHandshake { session_id, capabilities, token_limit, max_concurrent_tasks });
+                resumed_tasks
+            }
+            other => return Err(anyhow::anyhow!("Expected welcome, got {:?}", other)),
+        };
+        *handshaken = true;
+
+        // Reassign in-flight tasks the agent did not keep across the reconnect
+        for task_id in resume_tasks.iter().filter(|id| !resumed.contains(id)) {
+            if let Some(message) = self.assign_message(task_id) {
+                sink.send(message).await?;
+            }
+        }
+        // Assigns queued while disconnected are covered by the resume above
+        let mut handed_over: HashSet<String> = resume_tasks.into_iter().collect();
+
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+
+        loop {
+            tokio::select! {
+                outbound = rx.recv() => {
+                    let message = match outbound {
+                        Some(Outbound::Assign(task_id)) if handed_over.remove(&task_id) => None,
+                        Some(Outbound::Assign(task_id)) => self.assign_message(&task_id),
+                        Some(Outbound::Cancel(task_id)) => {
+                            Some(Message::Text(serde_json::to_string(&ClientMessage::CancelTask { task_id })?))
+                        }
+                        None => {
+                            let _ = sink.close().await;
+                            return Ok(());
+                        }
+                    };
+                    if let Some(message) = message {
+                        sink.send(message).await?;
+                    }
+                }
+                incoming = stream.next() => {
+                    last_seen = Instant::now();
+                    match incoming {
+                        Some(Ok(Message::Text(text))) => self.handle_message(&text),
+                        Some(Ok(Message::Ping(payload))) => sink.send(Message::Pong(payload)).await?,
+                        Some(Ok(Message::Close(_))) | None => {
+                            return Err(anyhow::anyhow!("Connection closed by agent"));
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => return Err(e.into()),
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > HEARTBEAT_TIMEOUT {
+                        return Err(anyhow::anyhow!("Heartbeat timed out"));
+                    }
+                    let ping = ClientMessage::Ping { ts: chrono::Utc::now().timestamp_millis() };
+                    sink.send(Message::Text(serde_json::to_string(&ping)?)).await?;
+                }
+            }
+        }
+    }
+
+    fn assign_message(&self, task_id: &str) -> Option<Message> {
+        let pending = self.pending.lock();
+        let request = pending.get(task_id)?.request.clone();
+        serde_json::to_string(&ClientMessage::AssignTask { request })
+            .ok()
+            .map(Message::Text)
+    }
+
+    fn handle_message(&self, text: &str) {
+        let message = match serde_json::from_str::<AgentMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("WebSocket agent {} sent invalid message: {}", self.agent.name, e);
+                return;
+            }
+        };
+
+        match message {
+            AgentMessage::Progress { task_id, progress, message } => {
+                let _ = self.events.send(WsAgentEvent::Progress {
+                    agent: self.agent.name.clone(),
+                    task_id,
+                    progress,
+                    message,
+                });
+            }
+            AgentMessage::PartialOutput { task_id, delta } => {
+                let _ = self.events.send(WsAgentEvent::PartialOutput {
+                    agent: self.agent.name.clone(),
+                    task_id,
+                    delta,
+                });
+            }
+            AgentMessage::TaskResult { response } => {
+                if let Some(pending) = self.pending.lock().remove(&response.task_id) {
+                    let _ = pending.tx.send(response);
+                }
+            }
+            AgentMessage::Error { task_id: Some(task_id), message } => {
+                if let Some(pending) = self.pending.lock().remove(&task_id) {
+                    let _ = pending.tx.send(AgentResponse {
+                        task_id,
+                        success: false,
+                        output: None,
+                        error: Some(message),
+                        tokens_used: None,
+                        execution_time_ms: 0,
+                    });
+                }
+            }
+            AgentMessage::Error { task_id: None, message } => {
+                warn!("WebSocket agent {} reported: {}", self.agent.name, message);
+            }
+            AgentMessage::Pong { .. } | AgentMessage::Welcome { .. } => {}
+        }
+    }
+
+    fn fail_pending(&self, error: &str) {
+        for (task_id, pending) in self.pending.lock().drain() {
+            let _ = pending.tx.send(AgentResponse {
+                task_id,
+                success: false,
+                output: None,
+                error: Some(error.to_string()),
+                tokens_used: None,
+                execution_time_ms: 0,
+            });
+        }
+    }
+}
@@ -3,13 +3,20 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use crate::models::{Project, Task, Agent, AppConfig};
 use crate::storage::StorageService;
+use crate::services::context_pool::ContextPool;
 
+// Clones share the same data, so the task runner sees what commands change
+#[derive(Clone)]
 pub struct AppState {
-    pub projects: RwLock<HashMap<String, Project>>,
-    pub tasks: RwLock<HashMap<String, Vec<Task>>>,
-    pub agents: RwLock<Vec<Agent>>,
-    pub config: RwLock<AppConfig>,
+    pub projects: Arc<RwLock<HashMap<String, Project>>>,
+    pub tasks: Arc<RwLock<HashMap<String, Vec<Task>>>>,
+    pub agents: Arc<RwLock<Vec<Agent>>>,
+    pub config: Arc<RwLock<AppConfig>>,
     pub storage: Arc<StorageService>,
+    // Project currently open in the dashboard; gets a larger scheduling share
+    pub foreground_project: Arc<RwLock<Option<String>>>,
+    // Task outputs and clarifications, kept on disk per project across restarts
+    pub context_pool: Arc<ContextPool>,
 }
 
 impl AppState {
@@ -42,12 +49,16 @@ impl AppState {
             }
         }
         
+        let context_pool = Arc::new(ContextPool::with_storage(Arc::clone(&storage)));
+        
         Ok(Self {
-            projects: RwLock::new(projects),
-            tasks: RwLock::new(HashMap::new()),
-            agents: RwLock::new(agents),
-            config: RwLock::new(config),
+            projects: Arc::new(RwLock::new(projects)),
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            agents: Arc::new(RwLock::new(agents)),
+            config: Arc::new(RwLock::new(config)),
             storage,
+            foreground_project: Arc::new(RwLock::new(None)),
+            context_pool,
         })
     }
 }
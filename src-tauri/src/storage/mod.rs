@@ -24,6 +24,11 @@ impl StorageService {
                 .join("supercollider")
         };
 
+        Self::at(base_path)
+    }
+
+    /// Storage rooted at `base_path` instead of the platform data directory.
+    pub fn at(base_path: PathBuf) -> Result<Self> {
         fs::create_dir_all(&base_path)?;
         fs::create_dir_all(base_path.join("projects"))?;
         fs::create_dir_all(base_path.join("backups"))?;
@@ -106,6 +111,31 @@ impl StorageService {
         Ok(data)
     }
 
+    pub fn delete_project_data(&self, project_id: &str, filename: &str) -> Result<()> {
+        let path = self.base_path.join("projects").join(project_id).join(filename);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Files in a project's directory whose names start with `prefix`.
+    pub fn list_project_files(&self, project_id: &str, prefix: &str) -> Result<Vec<String>> {
+        let project_dir = self.base_path.join("projects").join(project_id);
+        if !project_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(project_dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if name.starts_with(prefix) {
+                files.push(name);
+            }
+        }
+        Ok(files)
+    }
+
     pub fn append_to_jsonl(&self, project_id: &str, filename: &str, data: &serde_json::Value) -> Result<()> {
         let project_dir = self.base_path.join("projects").join(project_id);
         fs::create_dir_all(&project_dir)?;
@@ -125,6 +155,32 @@ impl StorageService {
         Ok(())
     }
 
+    pub fn load_project_jsonl(&self, project_id: &str, filename: &str) -> Result<Vec<serde_json::Value>> {
+        let path = self.base_path.join("projects").join(project_id).join(filename);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        
+        let contents = fs::read_to_string(path)?;
+        let records = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        Ok(records)
+    }
+
+    pub fn list_project_dirs(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(self.base_path.join("projects"))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                ids.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(ids)
+    }
+
     pub fn backup(&self) -> Result<String> {
         let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
         let backup_name = format!("backup_{}.tar.gz", timestamp);
@@ -144,6 +144,10 @@ export async function queueReorder(projectId: string, position: number) {
   return invokeWithFallback<{ ok: boolean; queue: string[] }>('queue_reorder', { project_id: projectId, position })
 }
 
+export async function queueSetForeground(projectId: string | null) {
+  return invokeWithFallback<{ ok: boolean; foreground_project: string | null }>('queue_set_foreground', { project_id: projectId })
+}
+
 // Lazy queue helpers
 export async function queueLoadSavedProjects(limit?: number) {
   return invokeWithFallback<{ ok: boolean; loaded: number; message?: string }>('queue_load_saved_projects', { limit })
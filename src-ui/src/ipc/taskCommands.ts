@@ -20,6 +20,18 @@ export interface Task {
   modified?: boolean;
   last_modified?: string;
   oneshot_count?: number;
+  evaluation?: TaskEvaluation;
+}
+
+// Result of the acceptance evaluator pass, set when a task has acceptance criteria
+export interface TaskEvaluation {
+  score: number;
+  threshold: number;
+  passed: boolean;
+  findings: string[];
+  criteria: { criterion: string; weight: number; score: number; finding: string }[];
+  rework_rounds: number;
+  history?: { score: number; findings: string[]; content: string }[];
 }
 
 export interface TaskCreateRequest {
@@ -92,6 +92,12 @@ export default function Dashboard() {
     setGreeting(greetings[Math.floor(Math.random() * greetings.length)])
   }, [])
 
+  // Give the project being watched a bigger share of the agents
+  useEffect(() => {
+    api.queueSetForeground(selectedProject).catch(() => {})
+    return () => { api.queueSetForeground(null).catch(() => {}) }
+  }, [selectedProject])
+
   // Poll backend for running tasks
   useEffect(() => {
     let cancelled = false